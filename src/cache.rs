@@ -46,8 +46,16 @@ impl Cache for FileSystemCache {
         if let Some(parent) = path_obj.parent() {
             fs::create_dir_all(parent)?;
         }
-        let mut file = OpenOptions::new().write(true).create(true).open(path)?;
+        // the cache is shared by the workers of a multi-core campaign, write to a
+        // temporary file first so that a concurrent load never sees a partial entry
+        let tmp_path = format!("{}.{}.tmp", path, std::process::id());
+        let mut file = OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(&tmp_path)?;
         file.write_all(value.as_bytes())?;
+        fs::rename(tmp_path, path)?;
         Ok(())
     }
 
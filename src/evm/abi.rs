@@ -20,7 +20,7 @@ use serde::{de::DeserializeOwned, Deserialize, Serialize};
 use tracing::debug;
 
 use super::{
-    types::checksum,
+    types::{checksum, simplify_u256},
     utils::{colored_address, prettify_value},
 };
 /// Definition of ABI types and their encoding, decoding, mutating methods
@@ -311,8 +311,71 @@ impl BoxedABI {
     }
 }
 
-/// Randomly sample an args with any type with size `size`
-fn sample_abi<Loc, Addr, VS, S, CI>(state: &mut S, size: usize) -> BoxedABI
+/// Longest array sampled for args with unknown types
+const MAX_SAMPLED_ARRAY_LEN: u64 = 4;
+
+/// Randomly sample an ABI type, a 256-bit one if `is_static`. Compound types
+/// are nested at most `depth` levels.
+fn sample_abi_type<S>(state: &mut S, is_static: bool, depth: usize) -> String
+where
+    S: HasRand,
+{
+    let choices = if is_static || depth == 0 { 5 } else { 10 };
+    match state.rand_mut().below(choices) {
+        0 => format!("uint{}", (state.rand_mut().below(32) + 1) * 8),
+        1 => format!("int{}", (state.rand_mut().below(32) + 1) * 8),
+        2 => format!("bytes{}", state.rand_mut().below(32) + 1),
+        3 => "bool".to_string(),
+        4 => "address".to_string(),
+        5 => "string".to_string(),
+        6 => "bytes".to_string(),
+        // struct
+        7 => {
+            let len = state.rand_mut().below(MAX_SAMPLED_ARRAY_LEN) + 1;
+            let items = (0..len)
+                .map(|_| sample_abi_type(state, false, depth - 1))
+                .join(",");
+            format!("({})", items)
+        }
+        8 => {
+            let len = state.rand_mut().below(MAX_SAMPLED_ARRAY_LEN) + 1;
+            format!("{}[{}]", sample_abi_type(state, false, depth - 1), len)
+        }
+        9 => format!("{}[]", sample_abi_type(state, false, depth - 1)),
+        _ => unreachable!(),
+    }
+}
+
+/// Randomly sample a dynamic ABI type, i.e., one encoded behind an offset.
+/// Compound types are nested at most `depth` levels.
+fn sample_dynamic_abi_type<S>(state: &mut S, depth: usize) -> String
+where
+    S: HasRand,
+{
+    let choices = if depth == 0 { 2 } else { 4 };
+    match state.rand_mut().below(choices) {
+        0 => "string".to_string(),
+        1 => "bytes".to_string(),
+        // struct with at least one dynamic item
+        2 => {
+            let len = state.rand_mut().below(MAX_SAMPLED_ARRAY_LEN) + 1;
+            let dynamic_idx = state.rand_mut().below(len);
+            let items = (0..len)
+                .map(|idx| match idx == dynamic_idx {
+                    true => sample_dynamic_abi_type(state, depth - 1),
+                    false => sample_abi_type(state, false, depth - 1),
+                })
+                .join(",");
+            format!("({})", items)
+        }
+        3 => format!("{}[]", sample_abi_type(state, false, depth - 1)),
+        _ => unreachable!(),
+    }
+}
+
+/// Randomly sample an arg of type `abi_name`, elements of arrays being
+/// sampled independently
+fn sample_abi_value<Loc, Addr, VS, S, CI>(state: &mut S, abi_name: &str) -> Box<dyn ABI>
 where
     S: State + HasRand + HasItyState<Loc, Addr, VS, CI> + HasMaxSize + HasCaller<EVMAddress>,
     VS: VMStateT + Default,
@@ -320,60 +383,82 @@ where
     Addr: Clone + Debug + Serialize + DeserializeOwned,
     CI: Serialize + DeserializeOwned + Debug + Clone + ConciseSerde,
 {
-    // TODO(@shou): use a better sampling strategy
-    if size == 32 {
-        // sample a static type
-        match state.rand_mut().below(100) % 2 {
-            0 => BoxedABI::new(Box::new(A256 {
-                data: vec![0; 32],
-                is_address: false,
-                dont_mutate: false,
-                inner_type: (state.rand_mut().below(100) % 4).into(),
-            })),
-            1 => BoxedABI::new(Box::new(A256 {
-                data: state.get_rand_address().0.into(),
-                is_address: true,
-                dont_mutate: false,
-                inner_type: A256InnerType::Address,
-            })),
-            _ => unreachable!(),
-        }
-    } else {
-        // sample a dynamic type
-        let max_size = state.max_size();
-        let vec_size = state.rand_mut().below(max_size as u64) as usize;
-        match state.rand_mut().below(100) % 4 {
-            // dynamic
-            0 => BoxedABI::new(Box::new(ADynamic {
-                data: vec![state.rand_mut().below(255) as u8; vec_size],
+    if abi_name.starts_with('(') && abi_name.ends_with(')') {
+        return Box::new(AArray {
+            data: split_with_parenthesis(&abi_name[1..abi_name.len() - 1])
+                .iter()
+                .map(|item| BoxedABI::new(sample_abi_value(state, item)))
+                .collect(),
+            dynamic_size: false,
+        });
+    }
+    if let Some((item, len)) = abi_name
+        .strip_suffix(']')
+        .and_then(|name| name.rsplit_once('['))
+    {
+        let (len, dynamic_size) = match len.parse::<usize>() {
+            Ok(len) => (len, false),
+            Err(_) => (
+                (state.rand_mut().below(MAX_SAMPLED_ARRAY_LEN) + 1) as usize,
+                true,
+            ),
+        };
+        return Box::new(AArray {
+            data: (0..len)
+                .map(|_| BoxedABI::new(sample_abi_value(state, item)))
+                .collect(),
+            dynamic_size,
+        });
+    }
+
+    let mut abi = get_abi_type(abi_name, &None);
+    match abi_name {
+        "string" | "bytes" => {
+            let max_size = state.max_size();
+            let len = state.rand_mut().below(max_size as u64) as usize;
+            let data = (0..len)
+                .map(|_| match abi_name {
+                    // printable ASCII
+                    "string" => (state.rand_mut().below(95) + 32) as u8,
+                    _ => state.rand_mut().below(256) as u8,
+                })
+                .collect();
+            Box::new(ADynamic {
+                data,
                 multiplier: 32,
-            })),
-            // tuple
-            1 => BoxedABI::new(Box::new(AArray {
-                data: vec![sample_abi(state, 32); vec_size],
-                dynamic_size: false,
-            })),
-            // array[]
-            2 => {
-                let abi = sample_abi(state, 32);
-                BoxedABI::new(Box::new(AArray {
-                    data: vec![abi; vec_size],
-                    dynamic_size: false,
-                }))
-            }
-            // array[...]
-            3 => {
-                let abi = sample_abi(state, 32);
-                BoxedABI::new(Box::new(AArray {
-                    data: vec![abi; vec_size],
-                    dynamic_size: true,
-                }))
-            }
-            _ => unreachable!(),
+            })
+        }
+        _ => {
+            let a256 = abi.as_any().downcast_mut::<A256>().unwrap();
+            a256.data = match a256.inner_type {
+                A256InnerType::Address => state.get_rand_address().0.to_vec(),
+                A256InnerType::Bool => vec![state.rand_mut().below(2) as u8],
+                _ => (0..a256.data.len())
+                    .map(|_| state.rand_mut().below(256) as u8)
+                    .collect(),
+            };
+            abi
         }
     }
 }
 
+/// Randomly sample an args with size `size`, a 256-bit type if `size` is 32
+/// and a dynamic type otherwise
+fn sample_abi<Loc, Addr, VS, S, CI>(state: &mut S, size: usize) -> BoxedABI
+where
+    S: State + HasRand + HasItyState<Loc, Addr, VS, CI> + HasMaxSize + HasCaller<EVMAddress>,
+    VS: VMStateT + Default,
+    Loc: Clone + Debug + Serialize + DeserializeOwned,
+    Addr: Clone + Debug + Serialize + DeserializeOwned,
+    CI: Serialize + DeserializeOwned + Debug + Clone + ConciseSerde,
+{
+    let abi_name = match size {
+        32 => sample_abi_type(state, true, 2),
+        _ => sample_dynamic_abi_type(state, 2),
+    };
+    BoxedABI::new(sample_abi_value(state, &abi_name))
+}
+
 impl BoxedABI {
     /// Mutate the args
     pub fn mutate<Loc, Addr, VS, S, CI>(&mut self, state: &mut S) -> MutationResult
@@ -429,6 +514,9 @@ impl BoxedABI {
                         a256.data = [0; 20].to_vec();
                     }
 
+                    MutationResult::Mutated
+                } else if let A256InnerType::Bool = a256.inner_type {
+                    a256.data = vec![state.rand_mut().below(2) as u8];
                     MutationResult::Mutated
                 } else {
                     byte_mutator(state, a256, vm_slots)
@@ -470,8 +558,12 @@ impl BoxedABI {
                             if state.max_size() <= aarray.data.len() {
                                 return MutationResult::Skipped;
                             }
+                            // mutated copies of random elements
                             for _ in 0..state.rand_mut().next() as usize % state.max_size() {
-                                aarray.data.push(aarray.data[0].clone());
+                                let index = state.rand_mut().next() as usize % aarray.data.len();
+                                let mut item = aarray.data[index].clone();
+                                item.mutate_with_vm_slots(state, vm_slots.clone());
+                                aarray.data.push(item);
                             }
                         }
                         91..=100 => {
@@ -515,6 +607,87 @@ impl BoxedABI {
     }
 }
 
+impl BoxedABI {
+    /// Get simpler variants of the args, each differing from `self` in a
+    /// single position: a number zeroed or rounded, a dynamic value emptied or
+    /// an element removed from a dynamic array. Addresses are left untouched.
+    ///
+    /// Used by the minimizer to produce human-readable PoCs.
+    pub fn simplify_candidates(&self) -> Vec<BoxedABI> {
+        let function = self.function;
+        let wrap = |b: Box<dyn ABI>| BoxedABI { b, function };
+        let mut this = self.clone();
+        match self.get_type() {
+            TEmpty => vec![],
+            T256 => {
+                let a256 = this.b.deref_mut().as_any().downcast_mut::<A256>().unwrap();
+                a256.simplify_candidates()
+                    .into_iter()
+                    .map(|a| wrap(Box::new(a)))
+                    .collect_vec()
+            }
+            TDynamic => {
+                let adyn = this
+                    .b
+                    .deref_mut()
+                    .as_any()
+                    .downcast_mut::<ADynamic>()
+                    .unwrap();
+                if adyn.data.is_empty() {
+                    return vec![];
+                }
+                vec![wrap(Box::new(ADynamic {
+                    data: Vec::new(),
+                    multiplier: adyn.multiplier,
+                }))]
+            }
+            TArray => {
+                let aarray = this
+                    .b
+                    .deref_mut()
+                    .as_any()
+                    .downcast_mut::<AArray>()
+                    .unwrap();
+                let mut candidates = vec![];
+                if aarray.dynamic_size {
+                    for idx in 0..aarray.data.len() {
+                        let mut shorter = aarray.clone();
+                        shorter.data.remove(idx);
+                        candidates.push(wrap(Box::new(shorter)));
+                    }
+                }
+                for idx in 0..aarray.data.len() {
+                    for item in aarray.data[idx].simplify_candidates() {
+                        let mut simpler = aarray.clone();
+                        simpler.data[idx] = item;
+                        candidates.push(wrap(Box::new(simpler)));
+                    }
+                }
+                candidates
+            }
+            TUnknown => {
+                let a_unknown = this
+                    .b
+                    .deref_mut()
+                    .as_any()
+                    .downcast_mut::<AUnknown>()
+                    .unwrap();
+                a_unknown
+                    .concrete
+                    .simplify_candidates()
+                    .into_iter()
+                    .map(|concrete| {
+                        wrap(Box::new(AUnknown {
+                            concrete,
+                            size: a_unknown.size,
+                        }))
+                    })
+                    .collect_vec()
+            }
+        }
+    }
+}
+
 impl Clone for Box<dyn ABI> {
     fn clone(&self) -> Box<dyn ABI> {
         self.clone_box()
@@ -606,6 +779,36 @@ impl From<u64> for A256InnerType {
     }
 }
 
+impl A256 {
+    /// Simpler values for this arg, see [`simplify_u256`]. Signed negative
+    /// values and fixed-size bytes can only be zeroed.
+    fn simplify_candidates(&self) -> Vec<A256> {
+        if self.is_address || self.dont_mutate {
+            return vec![];
+        }
+        let value = U256::try_from_be_slice(&self.data).unwrap_or_default();
+        let negative = self.data.first().map_or(false, |b| b & 0x80 != 0);
+        let values = match self.inner_type {
+            A256InnerType::Int if negative => vec![U256::ZERO],
+            A256InnerType::Bytes if value != U256::ZERO => vec![U256::ZERO],
+            A256InnerType::Int | A256InnerType::Uint | A256InnerType::Bool => simplify_u256(value),
+            _ => vec![],
+        };
+
+        let len = self.data.len().min(32);
+        values
+            .into_iter()
+            .map(|v| {
+                let bytes: [u8; 32] = v.to_be_bytes();
+                A256 {
+                    data: bytes[32 - len..].to_vec(),
+                    ..self.clone()
+                }
+            })
+            .collect_vec()
+    }
+}
+
 impl Input for A256 {
     fn generate_name(&self, idx: usize) -> String {
         format!("A256_{}", idx)
@@ -630,17 +833,18 @@ impl ABI for A256 {
     }
 
     fn get_bytes(&self) -> Vec<u8> {
-        // pad self.data to 32 bytes with 0s on the left
-        let mut bytes = vec![0; 32];
+        // bytesN are padded with 0s on the right, intN are sign-extended and the
+        // rest are padded with 0s on the left
         let data_len = self.data.len();
-        unsafe {
-            let mut ptr = bytes.as_mut_ptr();
-            ptr = ptr.add(32 - data_len);
-            for i in 0..data_len {
-                *ptr.add(i) = self.data[i];
+        match self.inner_type {
+            A256InnerType::Bytes => [self.data.clone(), vec![0; 32 - data_len]].concat(),
+            _ => {
+                let negative = matches!(self.inner_type, A256InnerType::Int)
+                    && self.data.first().map_or(false, |byte| byte & 0x80 != 0);
+                let padding = if negative { 0xff } else { 0 };
+                [vec![padding; 32 - data_len], self.data.clone()].concat()
             }
         }
-        bytes
     }
 
     fn as_any(&mut self) -> &mut dyn Any {
@@ -655,26 +859,37 @@ impl ABI for A256 {
         if bytes.len() < 32 {
             return false;
         }
-        self.data = bytes[..32].to_vec();
+        // keep the width of the type, rejecting values that do not fit in it
+        let len = match self.data.len() {
+            1..=32 => self.data.len(),
+            _ => 32,
+        };
+        let (data, padding) = match self.inner_type {
+            A256InnerType::Bytes => (&bytes[..len], &bytes[len..32]),
+            _ => (&bytes[32 - len..32], &bytes[..32 - len]),
+        };
+        let negative = matches!(self.inner_type, A256InnerType::Int) && data[0] & 0x80 != 0;
+        let pad_byte = if negative { 0xff } else { 0 };
+        if padding.iter().any(|byte| *byte != pad_byte) {
+            return false;
+        }
+        if matches!(self.inner_type, A256InnerType::Bool) && data.iter().any(|byte| *byte > 1) {
+            return false;
+        }
+        self.data = data.to_vec();
         true
     }
 
     fn to_string(&self) -> String {
         match self.inner_type {
-            A256InnerType::Int => I256::from_hex_str(&vec_to_hex(&self.data))
-                .unwrap_or_default()
-                .to_string(),
+            A256InnerType::Int => {
+                I256::from_raw(ethers::types::U256::from_big_endian(&self.get_bytes())).to_string()
+            }
             A256InnerType::Uint => {
                 let value = U256::try_from_be_slice(&self.data).unwrap_or_default();
                 prettify_value(value)
             }
-            A256InnerType::Bool => {
-                if self.data == [0] {
-                    "false".to_string()
-                } else {
-                    "true".to_string()
-                }
-            }
+            A256InnerType::Bool => self.data.iter().any(|byte| *byte != 0).to_string(),
             A256InnerType::Bytes => vec_to_hex(&self.data),
             A256InnerType::Address => checksum(&EVMAddress::from_slice({
                 if self.data.len() == 32 {
@@ -700,7 +915,9 @@ impl ABI for A256 {
             let counter = CONCOLIC_COUNTER;
             CONCOLIC_COUNTER += 1;
             let mut ptr = bytes.as_mut_ptr();
-            ptr = ptr.add(32 - data_len);
+            if !matches!(self.inner_type, A256InnerType::Bytes) {
+                ptr = ptr.add(32 - data_len);
+            }
             for i in 0..data_len {
                 debug!("[concolic] AAAAAAAA {}_A256_{}", counter, i);
                 *ptr.add(i) = Expr::sym_byte(format!("{}_A256_{}", counter, i));
@@ -967,7 +1184,9 @@ impl ABI for AArray {
             if start + size > bytes.len() {
                 return false;
             }
-            item.b.set_bytes(bytes[start..].to_vec());
+            if !item.b.set_bytes(bytes[start..].to_vec()) {
+                return false;
+            }
             offset += size;
         }
         true
@@ -1535,6 +1754,97 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_simplify_candidates() {
+        let mut abi = get_abi_type_boxed(&String::from("(uint256,address,uint256[])"));
+        let aarray = abi.b.as_any().downcast_mut::<AArray>().unwrap();
+        aarray.data[0]
+            .b
+            .as_any()
+            .downcast_mut::<A256>()
+            .unwrap()
+            .data = vec![0x12, 0x34];
+        aarray.data[1]
+            .b
+            .as_any()
+            .downcast_mut::<A256>()
+            .unwrap()
+            .data = vec![0x11; 20];
+
+        // 4660 => 0, 1, 1000, 4000, 4600, plus removing the only (zero) element
+        // of the dynamic array. The address is never simplified.
+        let candidates = abi.simplify_candidates();
+        assert_eq!(candidates.len(), 6);
+        assert!(candidates
+            .iter()
+            .all(|c| c.get_bytes() != abi.get_bytes() && c.get_bytes()[48..68] == [0x11; 20]));
+    }
+
+    #[test]
+    fn test_type_faithful_encoding() {
+        let mut abi = get_abi_type_boxed("(int8,bytes4,bool)");
+        let aarray = abi.b.as_any().downcast_mut::<AArray>().unwrap();
+        aarray.data[0]
+            .b
+            .as_any()
+            .downcast_mut::<A256>()
+            .unwrap()
+            .data = vec![0xff];
+        aarray.data[1]
+            .b
+            .as_any()
+            .downcast_mut::<A256>()
+            .unwrap()
+            .data = vec![0x12, 0x34, 0x56, 0x78];
+        let bytes = abi.get_bytes_vec();
+        assert_eq!(bytes[..32], [0xff; 32]);
+        assert_eq!(bytes[32..36], [0x12, 0x34, 0x56, 0x78]);
+        assert_eq!(bytes[36..96], [0; 60]);
+        assert_eq!(abi.b.to_string(), "(-1, 0x12345678, false)");
+
+        let mut decoded = get_abi_type_boxed("(int8,bytes4,bool)");
+        assert!(decoded.b.set_bytes(bytes.clone()));
+        assert_eq!(decoded.get_bytes_vec(), bytes);
+
+        // values wider than their type are rejected
+        for (idx, byte) in [(0, 0x7f), (40, 0x01), (95, 0x02)] {
+            let mut wide = bytes.clone();
+            wide[idx] = byte;
+            assert!(!get_abi_type_boxed("(int8,bytes4,bool)").b.set_bytes(wide));
+        }
+    }
+
+    #[test]
+    fn test_sample_abi() {
+        let mut test_state: EVMFuzzState = FuzzState::new(0, None);
+        test_state.add_address(&EVMAddress::from_slice(&[0x11; 20]));
+        for size in [32, 64] {
+            for _ in 0..100 {
+                let abi_name = match size {
+                    32 => sample_abi_type(&mut test_state, true, 2),
+                    _ => sample_dynamic_abi_type(&mut test_state, 2),
+                };
+                let abi = BoxedABI::new(sample_abi_value::<
+                    EVMAddress,
+                    EVMAddress,
+                    EVMState,
+                    EVMFuzzState,
+                    ConciseEVMInput,
+                >(&mut test_state, &abi_name));
+                if size == 32 {
+                    assert!(matches!(abi.get_type(), T256));
+                } else {
+                    assert!(!abi.is_static(), "{}", abi_name);
+                }
+                // sampled values are decoded as their type
+                let bytes = abi.get_bytes_vec();
+                let mut decoded = get_abi_type_boxed(&abi_name);
+                decoded.b.set_bytes(bytes.clone());
+                assert_eq!(decoded.get_bytes_vec(), bytes, "{}", abi_name);
+            }
+        }
+    }
+
     #[test]
     fn test_100_times() {
         for _ in 0..100 {
@@ -0,0 +1,517 @@
+//! Deployment script for offchain targets
+//!
+//! A script lists the contracts to deploy (in order), the setup calls to
+//! make once everything is deployed, and the initial ETH balances, e.g.
+//!
+//! ```toml
+//! [[deployments]]
+//! contract = "Token"
+//! args = ["1000000 ether"]
+//!
+//! [[deployments]]
+//! contract = "Vault"
+//! name = "vault"
+//! args = ["$Token"]
+//!
+//! [[balances]]
+//! address = "$vault"
+//! amount = "10 ether"
+//!
+//! [[calls]]
+//! to = "$Token"
+//! function = "approve(address,uint256)"
+//! args = ["$vault", "1000 ether"]
+//! ```
+//!
+//! `$name` refers to the address of a previous deployment (or `$deployer`).
+//! Args are ABI encoded with the types of the constructor or of the function
+//! signature: numbers are decimal or `0x`, optionally negative for signed
+//! types and followed by `wei`/`gwei`/`ether`, bytes are `0x` hex, strings
+//! may be quoted, and arrays and tuples are written `[a, b]` and `(a, b)`.
+use crate::evm::contract_utils::{set_hash, ContractInfo, ContractLoader};
+use crate::evm::types::{generate_random_address, EVMAddress, EVMFuzzState, EVMU256};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::error::Error;
+use std::str::FromStr;
+use tracing::debug;
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Deployment {
+    /// Name of the contract in the loaded target
+    pub contract: String,
+    /// Name used to reference this deployment, defaults to `contract`
+    #[serde(default)]
+    pub name: Option<String>,
+    #[serde(default)]
+    pub args: Vec<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct SetupCall {
+    /// Caller, defaults to the deployer
+    #[serde(default)]
+    pub from: Option<String>,
+    pub to: String,
+    /// Function signature, e.g. `mint(address,uint256)`
+    pub function: String,
+    #[serde(default)]
+    pub args: Vec<String>,
+    #[serde(default)]
+    pub value: Option<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Balance {
+    pub address: String,
+    pub amount: String,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct DeploymentScript {
+    #[serde(default)]
+    pub deployments: Vec<Deployment>,
+    #[serde(default)]
+    pub calls: Vec<SetupCall>,
+    #[serde(default)]
+    pub balances: Vec<Balance>,
+}
+
+impl Deployment {
+    pub fn name(&self) -> &str {
+        self.name.as_ref().unwrap_or(&self.contract)
+    }
+}
+
+impl SetupCall {
+    /// Calldata of the call, with symbolic addresses resolved
+    pub fn calldata(&self, names: &HashMap<String, EVMAddress>) -> Vec<u8> {
+        let mut selector = [0; 4];
+        set_hash(self.function.replace(' ', "").as_str(), &mut selector);
+        let types = param_types(&self.function);
+        [selector.to_vec(), encode_args(&types, &self.args, names)].concat()
+    }
+}
+
+impl DeploymentScript {
+    /// Parse a script from a `.toml` or `.json` file
+    pub fn from_file(file: String) -> Result<Self, Box<dyn Error>> {
+        let content = std::fs::read_to_string(&file)?;
+        if file.ends_with(".toml") {
+            Self::from_toml(content)
+        } else {
+            Self::from_json(content)
+        }
+    }
+
+    pub fn from_json(json: String) -> Result<Self, Box<dyn Error>> {
+        Ok(serde_json::from_str(&json)?)
+    }
+
+    pub fn from_toml(toml: String) -> Result<Self, Box<dyn Error>> {
+        Ok(toml::from_str(&toml)?)
+    }
+
+    /// Reorder the contracts of the loader as listed in the script and set
+    /// their constructor args. Contracts deployed more than once get a fresh
+    /// address for each further deployment, and contracts not in the script
+    /// are not deployed.
+    ///
+    /// Returns the address of each deployment (and of the deployer) by name.
+    pub fn arrange(
+        &self,
+        loader: &mut ContractLoader,
+        deployer: EVMAddress,
+        state: &mut EVMFuzzState,
+    ) -> HashMap<String, EVMAddress> {
+        let mut names = HashMap::from([("deployer".to_string(), deployer)]);
+        let mut contracts = vec![];
+        for deployment in &self.deployments {
+            let mut contract = loader
+                .contracts
+                .iter()
+                .find(|c| c.short_name() == deployment.contract)
+                .unwrap_or_else(|| {
+                    panic!(
+                        "contract {} in deployment script not found",
+                        deployment.contract
+                    )
+                })
+                .clone();
+            if contracts
+                .iter()
+                .any(|c: &ContractInfo| c.deployed_address == contract.deployed_address)
+            {
+                contract.deployed_address = generate_random_address(state);
+            }
+            if !deployment.args.is_empty() {
+                let types = contract
+                    .abi
+                    .iter()
+                    .find(|abi| abi.is_constructor)
+                    .map(|abi| param_types(&abi.abi))
+                    .unwrap_or_default();
+                contract.set_constructor_args(encode_args(&types, &deployment.args, &names));
+            }
+            if names
+                .insert(deployment.name().to_string(), contract.deployed_address)
+                .is_some()
+            {
+                panic!(
+                    "duplicate deployment name {} in deployment script",
+                    deployment.name()
+                );
+            }
+            contract.name = deployment.name().to_string();
+            contracts.push(contract);
+        }
+        for contract in &loader.contracts {
+            if !self
+                .deployments
+                .iter()
+                .any(|d| d.contract == contract.short_name())
+            {
+                debug!(
+                    "Contract {} is not in deployment script, skipping",
+                    contract.name
+                );
+            }
+        }
+        loader.contracts = contracts;
+        names
+    }
+}
+
+/// Resolve `$name` to the address of a deployment, or parse a literal address
+pub fn resolve_address(s: &str, names: &HashMap<String, EVMAddress>) -> EVMAddress {
+    match s.strip_prefix('$') {
+        Some(name) => *names
+            .get(name)
+            .unwrap_or_else(|| panic!("unknown deployment {} in deployment script", s)),
+        None => EVMAddress::from_str(s)
+            .unwrap_or_else(|_| panic!("invalid address {} in deployment script", s)),
+    }
+}
+
+/// Parse an amount such as `100`, `0x64` or `1.5 ether` into wei
+pub fn parse_amount(s: &str) -> EVMU256 {
+    let (number, decimals) = match s.trim().split_once(char::is_whitespace) {
+        Some((number, unit)) => match unit.trim() {
+            "wei" => (number, 0),
+            "gwei" => (number, 9),
+            "ether" => (number, 18),
+            _ => panic!("unknown unit in amount {}", s),
+        },
+        None => (s.trim(), 0),
+    };
+    if number.starts_with("0x") {
+        let value = EVMU256::from_str(number).unwrap_or_else(|_| panic!("invalid amount {}", s));
+        return value * EVMU256::from(10).pow(EVMU256::from(decimals));
+    }
+    let (integer, fraction) = number.split_once('.').unwrap_or((number, ""));
+    if fraction.len() > decimals {
+        panic!("too many decimals in amount {}", s);
+    }
+    let digits = format!("{}{:0<width$}", integer, fraction, width = decimals);
+    EVMU256::from_str(&digits).unwrap_or_else(|_| panic!("invalid amount {}", s))
+}
+
+fn invalid_arg(ty: &str, value: &str) -> ! {
+    panic!("invalid {} arg {} in deployment script", ty, value)
+}
+
+/// Split `a, (b, c), "d,e"` at its top-level commas
+fn split_top_level(s: &str) -> Vec<String> {
+    let (mut parts, mut current, mut depth, mut quoted) = (vec![], String::new(), 0, false);
+    for c in s.chars() {
+        match c {
+            '"' => quoted = !quoted,
+            '(' | '[' if !quoted => depth += 1,
+            ')' | ']' if !quoted => depth -= 1,
+            ',' if !quoted && depth == 0 => {
+                parts.push(current.trim().to_string());
+                current.clear();
+                continue;
+            }
+            _ => {}
+        }
+        current.push(c);
+    }
+    if !parts.is_empty() || !current.trim().is_empty() {
+        parts.push(current.trim().to_string());
+    }
+    parts
+}
+
+/// Types of the params of `name(type,...)` or `(type,...)`
+fn param_types(signature: &str) -> Vec<String> {
+    let signature = signature.replace(' ', "");
+    match signature
+        .strip_suffix(')')
+        .and_then(|signature| signature.split_once('('))
+    {
+        Some((_, params)) => split_top_level(params),
+        None => panic!("invalid signature {} in deployment script", signature),
+    }
+}
+
+/// Element type and length (`None` if dynamic) of an array type
+fn array_type(ty: &str) -> Option<(&str, Option<usize>)> {
+    let (element, len) = ty.strip_suffix(']')?.rsplit_once('[')?;
+    if len.is_empty() {
+        return Some((element, None));
+    }
+    let len = len
+        .parse()
+        .unwrap_or_else(|_| panic!("invalid type {} in deployment script", ty));
+    Some((element, Some(len)))
+}
+
+fn tuple_types(ty: &str) -> Option<Vec<String>> {
+    ty.strip_prefix('(')?.strip_suffix(')').map(split_top_level)
+}
+
+fn is_dynamic(ty: &str) -> bool {
+    match (array_type(ty), tuple_types(ty)) {
+        (Some((_, None)), _) => true,
+        (Some((element, Some(_))), _) => is_dynamic(element),
+        (None, Some(types)) => types.iter().any(|ty| is_dynamic(ty)),
+        (None, None) => ty == "string" || ty == "bytes",
+    }
+}
+
+/// Size of the encoding of a static type, or of the offset of a dynamic one
+fn head_size(ty: &str) -> usize {
+    if is_dynamic(ty) {
+        return 32;
+    }
+    match (array_type(ty), tuple_types(ty)) {
+        (Some((element, Some(len))), _) => len * head_size(element),
+        (None, Some(types)) => types.iter().map(|ty| head_size(ty)).sum(),
+        _ => 32,
+    }
+}
+
+fn word(value: EVMU256) -> Vec<u8> {
+    value.to_be_bytes::<32>().to_vec()
+}
+
+fn decode_hex(ty: &str, value: &str) -> Vec<u8> {
+    value
+        .strip_prefix("0x")
+        .and_then(|hex| hex::decode(hex).ok())
+        .unwrap_or_else(|| invalid_arg(ty, value))
+}
+
+/// Length and right padded content of `bytes` or `string`
+fn encode_bytes(bytes: &[u8]) -> Vec<u8> {
+    let mut encoded = word(EVMU256::from(bytes.len()));
+    encoded.extend(bytes);
+    encoded.resize(32 + (bytes.len() + 31) / 32 * 32, 0);
+    encoded
+}
+
+/// Encode a single arg of type `ty`
+fn encode_arg(ty: &str, value: &str, names: &HashMap<String, EVMAddress>) -> Vec<u8> {
+    let value = value.trim();
+    let items = |open: char, close: char| {
+        value
+            .strip_prefix(open)
+            .and_then(|value| value.strip_suffix(close))
+            .map(split_top_level)
+            .unwrap_or_else(|| invalid_arg(ty, value))
+    };
+    if let Some((element, len)) = array_type(ty) {
+        let items = items('[', ']');
+        let types = vec![element.to_string(); items.len()];
+        return match len {
+            None => [
+                word(EVMU256::from(items.len())),
+                encode_args(&types, &items, names),
+            ]
+            .concat(),
+            Some(len) if len == items.len() => encode_args(&types, &items, names),
+            Some(_) => invalid_arg(ty, value),
+        };
+    }
+    if let Some(types) = tuple_types(ty) {
+        return encode_args(&types, &items('(', ')'), names);
+    }
+
+    match ty {
+        "address" => [vec![0; 12], resolve_address(value, names).0.to_vec()].concat(),
+        "bool" => match value {
+            "true" => word(EVMU256::from(1)),
+            "false" => word(EVMU256::ZERO),
+            _ => invalid_arg(ty, value),
+        },
+        "string" => encode_bytes(value.trim_matches('"').as_bytes()),
+        "bytes" => encode_bytes(&decode_hex(ty, value)),
+        _ if ty.starts_with("bytes") => {
+            let bytes = decode_hex(ty, value);
+            match ty[5..].parse::<usize>() {
+                Ok(size) if size <= 32 && bytes.len() <= size => {
+                    let mut encoded = bytes;
+                    encoded.resize(32, 0);
+                    encoded
+                }
+                _ => invalid_arg(ty, value),
+            }
+        }
+        _ if ty.starts_with("uint") => word(parse_amount(value)),
+        _ if ty.starts_with("int") => match value.strip_prefix('-') {
+            Some(abs) => word(EVMU256::ZERO.wrapping_sub(parse_amount(abs))),
+            None => word(parse_amount(value)),
+        },
+        _ => panic!("unsupported type {} in deployment script", ty),
+    }
+}
+
+/// ABI encode `args` as a tuple of `types`
+pub fn encode_args(
+    types: &[String],
+    args: &[String],
+    names: &HashMap<String, EVMAddress>,
+) -> Vec<u8> {
+    if types.len() != args.len() {
+        panic!(
+            "expected {} args ({}), got {} in deployment script",
+            types.len(),
+            types.join(","),
+            args.len()
+        );
+    }
+    let mut head = vec![];
+    let mut tail = vec![];
+    let heads_size: usize = types.iter().map(|ty| head_size(ty)).sum();
+    for (ty, arg) in types.iter().zip(args) {
+        let encoded = encode_arg(ty, arg, names);
+        if is_dynamic(ty) {
+            head.extend(word(EVMU256::from(heads_size + tail.len())));
+            tail.extend(encoded);
+        } else {
+            head.extend(encoded);
+        }
+    }
+    [head, tail].concat()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_amount() {
+        assert_eq!(parse_amount("100"), EVMU256::from(100));
+        assert_eq!(parse_amount("0x64"), EVMU256::from(100));
+        assert_eq!(parse_amount("2 gwei"), EVMU256::from(2_000_000_000u64));
+        assert_eq!(
+            parse_amount("1.5 ether"),
+            EVMU256::from(1_500_000_000_000_000_000u128)
+        );
+    }
+
+    #[test]
+    fn test_deployment_script() {
+        let script = DeploymentScript::from_toml(
+            r#"
+            [[deployments]]
+            contract = "Token"
+
+            [[deployments]]
+            contract = "Vault"
+            name = "vault"
+            args = ["$Token", "true"]
+
+            [[calls]]
+            to = "$Token"
+            function = "approve(address, uint256)"
+            args = ["$vault", "1 ether"]
+            "#
+            .to_string(),
+        )
+        .unwrap();
+        assert_eq!(script.deployments[1].name(), "vault");
+
+        let token = EVMAddress::from_str("0x00000000000000000000000000000000000000aa").unwrap();
+        let vault = EVMAddress::from_str("0x00000000000000000000000000000000000000bb").unwrap();
+        let names = HashMap::from([("Token".to_string(), token), ("vault".to_string(), vault)]);
+
+        let types = param_types("constructor(address,bool)");
+        let args = encode_args(&types, &script.deployments[1].args, &names);
+        assert_eq!(args.len(), 64);
+        assert_eq!(args[12..32], token.0);
+        assert_eq!(args[63], 1);
+
+        let calldata = script.calls[0].calldata(&names);
+        // approve(address,uint256)
+        assert_eq!(calldata[..4], [0x09, 0x5e, 0xa7, 0xb3]);
+        assert_eq!(calldata[16..36], vault.0);
+        assert_eq!(
+            EVMU256::from_be_bytes::<32>(calldata[36..68].try_into().unwrap()),
+            parse_amount("1 ether")
+        );
+    }
+
+    #[test]
+    fn test_encode_dynamic_args() {
+        let token = EVMAddress::from_str("0x00000000000000000000000000000000000000aa").unwrap();
+        let names = HashMap::from([("Token".to_string(), token)]);
+        let words = |encoded: Vec<u8>| {
+            encoded
+                .chunks(32)
+                .map(|word| EVMU256::try_from_be_slice(word).unwrap())
+                .collect::<Vec<_>>()
+        };
+        let args = |args: &[&str]| args.iter().map(|arg| arg.to_string()).collect::<Vec<_>>();
+
+        let encoded = encode_args(
+            &param_types("f(string,int8,uint256[])"),
+            &args(&["\"a,b\"", "-1", "[1, 2 gwei]"]),
+            &names,
+        );
+        let encoded = words(encoded);
+        assert_eq!(encoded.len(), 8);
+        // offsets of the string and of the array
+        assert_eq!(encoded[0], EVMU256::from(0x60));
+        assert_eq!(encoded[1], EVMU256::MAX);
+        assert_eq!(encoded[2], EVMU256::from(0xa0));
+        assert_eq!(encoded[3], EVMU256::from(3));
+        assert_eq!(
+            encoded[4],
+            EVMU256::from_be_bytes::<32>(
+                [b"a,b".as_slice(), &[0; 29]].concat().try_into().unwrap()
+            )
+        );
+        assert_eq!(encoded[5], EVMU256::from(2));
+        assert_eq!(encoded[6], EVMU256::from(1));
+        assert_eq!(encoded[7], EVMU256::from(2_000_000_000u64));
+
+        // the tuple is dynamic as it holds bytes, the static array is inline
+        let types = param_types("g((address,bytes),bytes2[2])");
+        assert_eq!(types, vec!["(address,bytes)", "bytes2[2]"]);
+        let encoded = words(encode_args(
+            &types,
+            &args(&["($Token, 0x1234)", "[0xabcd, 0x01]"]),
+            &names,
+        ));
+        assert_eq!(encoded.len(), 7);
+        assert_eq!(encoded[0], EVMU256::from(0x60));
+        assert_eq!(encoded[1], EVMU256::from(0xabcd) << 240);
+        assert_eq!(encoded[2], EVMU256::from(0x01) << 248);
+        assert_eq!(encoded[3], EVMU256::from(0xaa));
+        assert_eq!(encoded[4], EVMU256::from(0x40));
+        assert_eq!(encoded[5], EVMU256::from(2));
+        assert_eq!(encoded[6], EVMU256::from(0x1234) << 240);
+    }
+
+    #[test]
+    #[should_panic(expected = "invalid bool arg yes")]
+    fn test_encode_invalid_arg() {
+        encode_args(
+            &param_types("f(bool)"),
+            &["yes".to_string()],
+            &HashMap::new(),
+        );
+    }
+}
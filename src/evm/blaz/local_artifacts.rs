@@ -0,0 +1,439 @@
+use bytes::Bytes;
+use glob::glob;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::error::Error;
+use std::path::{Path, PathBuf};
+use tracing::{debug, warn};
+
+/// Position of a library address that still needs to be linked into bytecode
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct LinkReference {
+    /// Fully qualified library name (`path/to/File.sol:Library`)
+    pub library: String,
+    /// Byte offset of the 20-byte placeholder
+    pub start: usize,
+}
+
+/// A contract compiled locally by Foundry or Hardhat
+#[derive(Clone, Debug)]
+pub struct LocalArtifact {
+    /// Path of the source file declaring the contract
+    pub source_name: String,
+    pub contract_name: String,
+    pub abi: String,
+    /// Creation bytecode, unlinked library placeholders are zeroed
+    pub creation_bytecode: Bytes,
+    /// Runtime bytecode, unlinked library placeholders are zeroed
+    pub deployed_bytecode: Bytes,
+    /// Source map of the runtime bytecode
+    pub source_map: String,
+    pub link_references: Vec<LinkReference>,
+    pub deployed_link_references: Vec<LinkReference>,
+    /// (file name, source code), indexed by the source id of the compilation
+    pub sources: Vec<(String, String)>,
+}
+
+/// Solc standard JSON input and output of a single compilation, as written to
+/// `build-info` by Foundry (with `--build-info`) and Hardhat
+struct BuildInfo {
+    sources: Vec<(String, String)>,
+    /// (source name, contract name) -> solc output of the contract
+    contracts: HashMap<(String, String), Value>,
+}
+
+impl BuildInfo {
+    fn from_file(path: &Path) -> Result<Self, Box<dyn Error>> {
+        let json = serde_json::from_str::<Value>(&std::fs::read_to_string(path)?)?;
+        let output_sources = json["output"]["sources"]
+            .as_object()
+            .ok_or("build info has no output sources")?;
+
+        let mut sources = vec![(String::new(), String::new()); output_sources.len()];
+        for (filename, source) in output_sources {
+            let idx = source["id"].as_u64().ok_or("get source id failed")? as usize;
+            let code = json["input"]["sources"][filename]["content"]
+                .as_str()
+                .unwrap_or_default();
+            if idx >= sources.len() {
+                sources.resize(idx + 1, (String::new(), String::new()));
+            }
+            sources[idx] = (filename.clone(), code.to_string());
+        }
+
+        let mut contracts = HashMap::new();
+        if let Some(output_contracts) = json["output"]["contracts"].as_object() {
+            for (filename, file_contracts) in output_contracts {
+                for (contract_name, contract) in file_contracts.as_object().into_iter().flatten() {
+                    contracts.insert((filename.clone(), contract_name.clone()), contract.clone());
+                }
+            }
+        }
+        Ok(Self { sources, contracts })
+    }
+}
+
+/// Decode a hex bytecode string that may still contain library placeholders
+/// (`__$<hash>$__` or `__LibraryName____`). Placeholders are zeroed and need
+/// to be patched using the link references.
+pub fn decode_unlinked_bytecode(code: &str) -> Result<Bytes, hex::FromHexError> {
+    // bytes rather than chars, so that non-ASCII chars are reported by the
+    // hex decoding instead of splitting a char
+    let code = code.trim().trim_start_matches("0x").as_bytes();
+    let mut normalized = Vec::with_capacity(code.len());
+    let mut idx = 0;
+    while idx < code.len() {
+        if code[idx..].starts_with(b"__") {
+            let end = (idx + 40).min(code.len());
+            normalized.resize(normalized.len() + end - idx, b'0');
+            idx = end;
+        } else {
+            normalized.extend_from_slice(&code[idx..(idx + 2).min(code.len())]);
+            idx += 2;
+        }
+    }
+    hex::decode(normalized).map(Bytes::from)
+}
+
+/// Parse `{"File.sol": {"Library": [{"start": 1, "length": 20}]}}`
+pub fn parse_link_references(json: &Value) -> Vec<LinkReference> {
+    let mut references = vec![];
+    for (filename, libraries) in json.as_object().into_iter().flatten() {
+        for (library, offsets) in libraries.as_object().into_iter().flatten() {
+            for offset in offsets.as_array().into_iter().flatten() {
+                if let Some(start) = offset["start"].as_u64() {
+                    references.push(LinkReference {
+                        library: format!("{}:{}", filename, library),
+                        start: start as usize,
+                    });
+                }
+            }
+        }
+    }
+    references.sort_by_key(|r| r.start);
+    references
+}
+
+/// Bytecode object in Foundry artifacts is `{"object": "0x..."}`, Hardhat
+/// stores the hex string directly
+fn bytecode_object(json: &Value) -> &str {
+    json["object"]
+        .as_str()
+        .or_else(|| json.as_str())
+        .unwrap_or_default()
+}
+
+/// All json files below `dir`, except build info and Hardhat debug files
+fn artifact_files(dir: &str) -> Vec<PathBuf> {
+    glob(format!("{}/**/*.json", dir.trim_end_matches('/')).as_str())
+        .expect("invalid artifacts directory")
+        .filter_map(|path| path.ok())
+        .filter(|path| {
+            let path_str = path.to_str().unwrap_or_default();
+            !path_str.contains("build-info") && !path_str.ends_with(".dbg.json")
+        })
+        .collect()
+}
+
+impl LocalArtifact {
+    /// Parse a single Foundry artifact (`out/File.sol/Contract.json`), `None`
+    /// if the json is not an artifact
+    pub fn from_foundry_json(json: &Value) -> Result<Option<Self>, Box<dyn Error>> {
+        // `compilationTarget` is always present in the metadata, the AST only
+        // when it is requested
+        let target = json["metadata"]["settings"]["compilationTarget"]
+            .as_object()
+            .and_then(|target| target.iter().next())
+            .and_then(|(source, name)| Some((source.clone(), name.as_str()?.to_string())))
+            .or_else(|| {
+                Some((
+                    json["ast"]["absolutePath"].as_str()?.to_string(),
+                    json["contractName"].as_str()?.to_string(),
+                ))
+            });
+        let (source_name, contract_name) = match target {
+            Some(target) => target,
+            None => return Ok(None),
+        };
+
+        Ok(Some(Self {
+            source_name,
+            contract_name,
+            abi: serde_json::to_string(&json["abi"])?,
+            creation_bytecode: decode_unlinked_bytecode(bytecode_object(&json["bytecode"]))?,
+            deployed_bytecode: decode_unlinked_bytecode(bytecode_object(
+                &json["deployedBytecode"],
+            ))?,
+            source_map: json["deployedBytecode"]["sourceMap"]
+                .as_str()
+                .unwrap_or_default()
+                .to_string(),
+            link_references: parse_link_references(&json["bytecode"]["linkReferences"]),
+            deployed_link_references: parse_link_references(
+                &json["deployedBytecode"]["linkReferences"],
+            ),
+            sources: vec![],
+        }))
+    }
+
+    /// Parse a single Hardhat artifact (`artifacts/File.sol/Contract.json`),
+    /// `None` if the json is not an artifact
+    pub fn from_hardhat_json(json: &Value) -> Result<Option<Self>, Box<dyn Error>> {
+        let (source_name, contract_name) =
+            match (json["sourceName"].as_str(), json["contractName"].as_str()) {
+                (Some(source_name), Some(contract_name)) => {
+                    (source_name.to_string(), contract_name.to_string())
+                }
+                _ => return Ok(None),
+            };
+        Ok(Some(Self {
+            source_name,
+            contract_name,
+            abi: serde_json::to_string(&json["abi"])?,
+            creation_bytecode: decode_unlinked_bytecode(bytecode_object(&json["bytecode"]))?,
+            deployed_bytecode: decode_unlinked_bytecode(bytecode_object(
+                &json["deployedBytecode"],
+            ))?,
+            // Hardhat only keeps source maps in the build info
+            source_map: String::new(),
+            link_references: parse_link_references(&json["linkReferences"]),
+            deployed_link_references: parse_link_references(&json["deployedLinkReferences"]),
+            sources: vec![],
+        }))
+    }
+
+    /// `source:Name`, as used by link references
+    pub fn qualified_name(&self) -> String {
+        format!("{}:{}", self.source_name, self.contract_name)
+    }
+
+    /// Whether there is anything to deploy (interfaces and abstract contracts
+    /// have no bytecode)
+    pub fn is_deployable(&self) -> bool {
+        !self.creation_bytecode.is_empty()
+    }
+
+    /// Foundry and Hardhat dependencies (forge-std, OpenZeppelin...), tests
+    /// and scripts are compiled along with the project but are not fuzz
+    /// targets
+    pub fn is_dependency(&self) -> bool {
+        self.source_name.starts_with("lib/")
+            || self.source_name.contains("node_modules/")
+            || self.source_name.starts_with("@")
+            || self.source_name.ends_with(".t.sol")
+            || self.source_name.ends_with(".s.sol")
+    }
+
+    /// Fill source map and sources from the solc output of the compilation
+    /// that produced this contract
+    fn with_build_info(mut self, build_info: &BuildInfo) -> Self {
+        let key = (self.source_name.clone(), self.contract_name.clone());
+        if let Some(contract) = build_info.contracts.get(&key) {
+            if self.source_map.is_empty() {
+                self.source_map = contract["evm"]["deployedBytecode"]["sourceMap"]
+                    .as_str()
+                    .unwrap_or_default()
+                    .to_string();
+            }
+            self.sources = build_info.sources.clone();
+        }
+        self
+    }
+
+    fn built_by(&self, build_info: &BuildInfo) -> bool {
+        let key = (self.source_name.clone(), self.contract_name.clone());
+        match build_info.contracts.get(&key) {
+            Some(contract) => {
+                decode_unlinked_bytecode(bytecode_object(&contract["evm"]["deployedBytecode"]))
+                    .map_or(false, |code| code == self.deployed_bytecode)
+            }
+            None => false,
+        }
+    }
+}
+
+/// Contracts compiled by a local Foundry or Hardhat project
+#[derive(Clone, Debug, Default)]
+pub struct LocalArtifacts {
+    pub contracts: Vec<LocalArtifact>,
+}
+
+impl LocalArtifacts {
+    /// Load all artifacts in a Foundry `out` directory.
+    ///
+    /// Sources are taken from `out/build-info` when the project is built with
+    /// `--build-info`, otherwise they are read from the project directory
+    /// using the source ids in the artifacts' AST.
+    pub fn from_foundry_out(out_dir: &str) -> Result<Self, Box<dyn Error>> {
+        let build_infos = Self::load_build_infos(out_dir);
+
+        let mut contracts = vec![];
+        // source id -> source name, for projects built without build info
+        let mut source_ids: HashMap<usize, String> = HashMap::new();
+        for path in artifact_files(out_dir) {
+            let json = serde_json::from_str::<Value>(&std::fs::read_to_string(&path)?)?;
+            let artifact = match LocalArtifact::from_foundry_json(&json)
+                .map_err(|e| format!("invalid artifact {:?}: {}", path, e))?
+            {
+                Some(artifact) => artifact,
+                None => {
+                    debug!("Skipping non-artifact file {:?}", path);
+                    continue;
+                }
+            };
+            if let (Some(id), Some(source)) =
+                (json["id"].as_u64(), json["ast"]["absolutePath"].as_str())
+            {
+                source_ids.insert(id as usize, source.to_string());
+            }
+            contracts.push(
+                match build_infos.iter().find(|info| artifact.built_by(info)) {
+                    Some(info) => artifact.with_build_info(info),
+                    None => artifact,
+                },
+            );
+        }
+
+        // fall back to reading sources relative to the project root
+        if !source_ids.is_empty() {
+            let root = Path::new(out_dir).parent().unwrap_or(Path::new("."));
+            let size = source_ids.keys().max().unwrap() + 1;
+            let mut sources = vec![(String::new(), String::new()); size];
+            for (id, name) in source_ids {
+                let code = std::fs::read_to_string(root.join(&name)).unwrap_or_default();
+                sources[id] = (name, code);
+            }
+            contracts
+                .iter_mut()
+                .filter(|c| c.sources.is_empty())
+                .for_each(|c| c.sources = sources.clone());
+        }
+
+        Ok(Self { contracts })
+    }
+
+    /// Load all artifacts in a Hardhat `artifacts` directory. Source maps and
+    /// sources come from the build info referenced by each `.dbg.json` file.
+    pub fn from_hardhat_artifacts(artifacts_dir: &str) -> Result<Self, Box<dyn Error>> {
+        let mut build_infos: HashMap<PathBuf, Option<BuildInfo>> = HashMap::new();
+
+        let mut contracts = vec![];
+        for path in artifact_files(artifacts_dir) {
+            let json = serde_json::from_str::<Value>(&std::fs::read_to_string(&path)?)?;
+            if json["_format"]
+                .as_str()
+                .map_or(true, |f| !f.starts_with("hh-sol-artifact"))
+            {
+                debug!("Skipping non-artifact file {:?}", path);
+                continue;
+            }
+            let artifact = match LocalArtifact::from_hardhat_json(&json)
+                .map_err(|e| format!("invalid artifact {:?}: {}", path, e))?
+            {
+                Some(artifact) => artifact,
+                None => continue,
+            };
+
+            let build_info_path = std::fs::read_to_string(path.with_extension("dbg.json"))
+                .ok()
+                .and_then(|dbg| serde_json::from_str::<Value>(&dbg).ok())
+                .and_then(|dbg| dbg["buildInfo"].as_str().map(|p| p.to_string()))
+                .map(|p| path.parent().unwrap().join(p));
+            let build_info = match build_info_path {
+                Some(p) => build_infos
+                    .entry(p.clone())
+                    .or_insert_with(|| BuildInfo::from_file(&p).ok())
+                    .as_ref(),
+                None => None,
+            };
+            contracts.push(match build_info {
+                Some(info) => artifact.with_build_info(info),
+                None => {
+                    warn!(
+                        "No build info for {}:{}, source maps are unavailable",
+                        artifact.source_name, artifact.contract_name
+                    );
+                    artifact
+                }
+            });
+        }
+        Ok(Self { contracts })
+    }
+
+    fn load_build_infos(dir: &str) -> Vec<BuildInfo> {
+        glob(format!("{}/build-info/*.json", dir.trim_end_matches('/')).as_str())
+            .expect("invalid artifacts directory")
+            .filter_map(|path| path.ok())
+            .filter_map(|path| match BuildInfo::from_file(&path) {
+                Ok(info) => Some(info),
+                Err(e) => {
+                    warn!("Failed to parse build info {:?}: {}", path, e);
+                    None
+                }
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decode_unlinked_bytecode() {
+        let code = "0x6001__$3f8f5bd0d3f9e1f4c4b7b1ea6e8df0a6b3$__6002";
+        let decoded = decode_unlinked_bytecode(code).unwrap();
+        assert_eq!(decoded.len(), 2 + 20 + 2);
+        assert_eq!(decoded[..2], [0x60, 0x01]);
+        assert_eq!(decoded[2..22], [0; 20]);
+        assert_eq!(decoded[22..], [0x60, 0x02]);
+        assert!(decode_unlinked_bytecode("0x60zz").is_err());
+        assert!(decode_unlinked_bytecode("0x6é__$3f8f5bd0d3f9e1f4c4b7b1ea6e8df0a6b3$__").is_err());
+    }
+
+    #[test]
+    fn test_foundry_artifact() {
+        let json = serde_json::json!({
+            "abi": [{"type": "function", "name": "foo", "inputs": [], "outputs": [], "stateMutability": "nonpayable"}],
+            "bytecode": {
+                "object": "0x6080__$3f8f5bd0d3f9e1f4c4b7b1ea6e8df0a6b3$__",
+                "sourceMap": "",
+                "linkReferences": {"src/Lib.sol": {"Lib": [{"start": 2, "length": 20}]}}
+            },
+            "deployedBytecode": {"object": "0x6080", "sourceMap": "0:10:0:-:0", "linkReferences": {}},
+            "metadata": {"settings": {"compilationTarget": {"src/Foo.sol": "Foo"}}},
+            "id": 3
+        });
+        let artifact = LocalArtifact::from_foundry_json(&json)
+            .unwrap()
+            .expect("parse failed");
+        assert_eq!(artifact.source_name, "src/Foo.sol");
+        assert_eq!(artifact.contract_name, "Foo");
+        assert_eq!(artifact.creation_bytecode.len(), 22);
+        assert_eq!(artifact.source_map, "0:10:0:-:0");
+        assert_eq!(
+            artifact.link_references,
+            vec![LinkReference {
+                library: "src/Lib.sol:Lib".to_string(),
+                start: 2
+            }]
+        );
+        assert!(artifact.is_deployable());
+        assert!(!artifact.is_dependency());
+
+        let test = LocalArtifact {
+            source_name: "test/Foo.t.sol".to_string(),
+            ..artifact
+        };
+        assert!(test.is_dependency());
+        let mut json = json;
+        json["deployedBytecode"]["object"] = "0x608".into();
+        assert!(LocalArtifact::from_foundry_json(&json).is_err());
+        assert!(
+            LocalArtifact::from_foundry_json(&serde_json::json!({"abi": []}))
+                .unwrap()
+                .is_none()
+        );
+    }
+}
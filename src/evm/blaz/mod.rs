@@ -1,15 +1,18 @@
 use crate::evm::bytecode_analyzer::find_constants;
 use crate::evm::bytecode_iterator::all_bytecode;
 use crate::evm::bytecode_iterator::SKIP_CBOR;
-use crate::evm::contract_utils::extract_sig_from_contract;
+use crate::evm::contract_utils::{extract_sig_from_contract, ContractInfo};
 use crate::skip_cbor;
 use bytes::Bytes;
 use itertools::Itertools;
 use revm_interpreter::opcode::{EQ, PUSH4};
 use revm_primitives::{Bytecode, HashSet};
+use std::cmp::Reverse;
 use std::time::Duration;
 
 pub mod builder;
+pub mod deployment_script;
+pub mod local_artifacts;
 pub mod offchain_artifacts;
 pub mod offchain_config;
 
@@ -45,6 +48,32 @@ pub fn is_bytecode_similar_strict_ranking(hay: Vec<u8>, needle: Vec<u8>) -> usiz
     })
 }
 
+/// Find the known contract that a contract created with `init_code` is an
+/// instance of, i.e., the one sharing all its function signatures with the
+/// fewest unmatched constants
+pub fn find_similar_contract<'a>(
+    init_code: &[u8],
+    contracts: &'a [ContractInfo],
+) -> Option<&'a ContractInfo> {
+    contracts
+        .iter()
+        .filter(|contract| {
+            let code = contract.creation_code();
+            !contract.abi.is_empty()
+                && !extract_sig_from_contract(hex::encode(code).as_str()).is_empty()
+                && is_bytecode_similar_lax(init_code.to_vec(), code.to_vec())
+        })
+        .min_by_key(|contract| {
+            (
+                is_bytecode_similar_strict_ranking(
+                    init_code.to_vec(),
+                    contract.creation_code().to_vec(),
+                ),
+                Reverse(contract.abi.len()),
+            )
+        })
+}
+
 #[cfg(test)]
 mod test {
     use crate::evm::blaz::is_bytecode_similar_lax;
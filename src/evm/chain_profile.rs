@@ -0,0 +1,342 @@
+//! Execution profiles of the chains (`--chain-type`), for the semantics
+//! differing from mainnet on L2s: their system contracts, answered
+//! deterministically by the host, the block number returned by NUMBER and
+//! their hardfork (e.g., whether PUSH0 is available).
+use crate::evm::onchain::endpoints::{Chain, OnChainConfig};
+use crate::evm::oracles::amm::selector;
+use crate::evm::types::{EVMAddress, EVMU256};
+use bytes::Bytes;
+use revm_primitives::Env;
+use std::str::FromStr;
+use tracing::debug;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum L2Kind {
+    /// Not an L2, or without emulated system contracts
+    None,
+    /// ArbSys and ArbGasInfo precompiles, NUMBER returning the L1 block number
+    Arbitrum,
+    /// L1Block and GasPriceOracle predeploys of the OP stack
+    OPStack,
+}
+
+pub const ARB_SYS: &str = "0x0000000000000000000000000000000000000064";
+pub const ARB_GAS_INFO: &str = "0x000000000000000000000000000000000000006c";
+pub const OP_L1_BLOCK: &str = "0x4200000000000000000000000000000000000015";
+pub const OP_GAS_PRICE_ORACLE: &str = "0x420000000000000000000000000000000000000f";
+
+/// ArbOS 20, ArbSys adding 55 to the version
+const ARB_OS_VERSION: u64 = 75;
+/// Prices of `ArbGasInfo.getPricesInWei()`
+const ARB_PRICES_IN_WEI: [u64; 6] = [0, 0, 0, 10_000_000, 0, 10_000_000];
+/// Bedrock L1 fee parameters
+const OP_L1_FEE_OVERHEAD: u64 = 188;
+const OP_L1_FEE_SCALAR: u64 = 684_000;
+const OP_L1_FEE_DECIMALS: u64 = 6;
+
+#[derive(Clone, Debug)]
+pub struct ChainProfile {
+    pub l2: L2Kind,
+    pub chain_id: u64,
+    /// Hardfork used unless `--spec-id` is given
+    pub spec_id: &'static str,
+    /// Block number of the L1, the NUMBER of Arbitrum and `L1Block.number()`
+    /// of OP, the block number of the campaign if unknown
+    pub l1_block_number: Option<EVMU256>,
+    pub l1_timestamp: Option<EVMU256>,
+    pub l1_base_fee: EVMU256,
+    /// Block number of the L2 when NUMBER returns the L1 one
+    pub l2_block_number: Option<EVMU256>,
+}
+
+impl Default for ChainProfile {
+    fn default() -> Self {
+        Self {
+            l2: L2Kind::None,
+            chain_id: 1,
+            spec_id: "Latest",
+            l1_block_number: None,
+            l1_timestamp: None,
+            l1_base_fee: EVMU256::from(30_000_000_000u64),
+            l2_block_number: None,
+        }
+    }
+}
+
+fn word(value: EVMU256) -> Vec<u8> {
+    value.to_be_bytes::<32>().to_vec()
+}
+
+fn words(values: &[u64]) -> Bytes {
+    Bytes::from(
+        values
+            .iter()
+            .flat_map(|value| word(EVMU256::from(*value)))
+            .collect::<Vec<_>>(),
+    )
+}
+
+/// `bytes` argument of an ABI-encoded call
+fn bytes_arg(input: &[u8]) -> Option<&[u8]> {
+    let read = |pos: usize| -> Option<usize> {
+        let word = input.get(pos..pos + 32)?;
+        let value = EVMU256::try_from_be_slice(word)?;
+        (value <= EVMU256::from(input.len())).then(|| value.as_limbs()[0] as usize)
+    };
+    let offset = 4 + read(4)?;
+    let len = read(offset)?;
+    input.get(offset + 32..offset + 32 + len)
+}
+
+impl ChainProfile {
+    pub fn new(chain: Option<Chain>) -> Self {
+        let chain = match chain {
+            Some(chain) => chain,
+            None => return Self::default(),
+        };
+        Self {
+            l2: match chain {
+                Chain::ARBITRUM => L2Kind::Arbitrum,
+                Chain::OPTIMISM | Chain::BASE => L2Kind::OPStack,
+                _ => L2Kind::None,
+            },
+            chain_id: chain.get_chain_id() as u64,
+            spec_id: match chain {
+                // PUSH0 is not available
+                Chain::FANTOM => "London",
+                Chain::ZKEVM | Chain::ZkevmTestnet => "Merge",
+                _ => "Latest",
+            },
+            ..Default::default()
+        }
+    }
+
+    /// L1 block of the forked block, as seen by the L2
+    pub fn fetch_l1_info(&mut self, onchain: &mut OnChainConfig) {
+        match self.l2 {
+            L2Kind::Arbitrum => {
+                self.l1_block_number = onchain.fetch_blk_field("l1BlockNumber");
+                self.l2_block_number = EVMU256::from_str(&onchain.block_number).ok();
+            }
+            L2Kind::OPStack => {
+                let l1_block = EVMAddress::from_str(OP_L1_BLOCK).unwrap();
+                let call = |sig: &str| {
+                    onchain
+                        .eth_call(l1_block, &selector(sig))
+                        .and_then(|out| EVMU256::try_from_be_slice(out.get(0..32)?))
+                };
+                self.l1_block_number = call("number()");
+                self.l1_timestamp = call("timestamp()");
+                if let Some(base_fee) = call("basefee()") {
+                    self.l1_base_fee = base_fee;
+                }
+            }
+            L2Kind::None => {}
+        }
+        debug!("chain profile {:?}", self);
+    }
+
+    /// Block number returned by NUMBER, given the one of the forked block
+    pub fn block_number(&self, fork_block_number: EVMU256) -> EVMU256 {
+        match self.l2 {
+            L2Kind::Arbitrum => self.l1_block_number.unwrap_or(fork_block_number),
+            _ => fork_block_number,
+        }
+    }
+
+    fn l1_gas_used(data: &[u8]) -> u64 {
+        let calldata_gas: u64 = data
+            .iter()
+            .map(|byte| if *byte == 0 { 4 } else { 16 })
+            .sum();
+        // the signature of the transaction is not in the data
+        calldata_gas + OP_L1_FEE_OVERHEAD + 68 * 16
+    }
+
+    /// Output of a call to a system contract of the chain, None if `address`
+    /// is not one or the function is not emulated
+    pub fn system_call(&self, address: EVMAddress, input: &[u8], env: &Env) -> Option<Bytes> {
+        if self.l2 == L2Kind::None || input.len() < 4 {
+            return None;
+        }
+        let is = |sig: &str| input[0..4] == selector(sig)[..];
+        let l1_block_number = self.l1_block_number.unwrap_or(env.block.number);
+        let l1_timestamp = self.l1_timestamp.unwrap_or(env.block.timestamp);
+        let uint = |value: EVMU256| Some(Bytes::from(word(value)));
+
+        match self.l2 {
+            L2Kind::Arbitrum if address == EVMAddress::from_str(ARB_SYS).unwrap() => {
+                if is("arbBlockNumber()") {
+                    uint(self.l2_block_number.unwrap_or(env.block.number))
+                } else if is("arbBlockHash(uint256)") {
+                    // as BLOCKHASH
+                    uint(EVMU256::ZERO)
+                } else if is("arbChainID()") {
+                    uint(EVMU256::from(self.chain_id))
+                } else if is("arbOSVersion()") {
+                    uint(EVMU256::from(ARB_OS_VERSION))
+                } else if is("isTopLevelCall()") {
+                    uint(EVMU256::from(1))
+                } else if is("wasMyCallersAddressAliased()") || is("getStorageGasAvailable()") {
+                    uint(EVMU256::ZERO)
+                } else if is("withdrawEth(address)") || is("sendTxToL1(address,bytes)") {
+                    // id of the L2 to L1 message
+                    uint(EVMU256::ZERO)
+                } else {
+                    None
+                }
+            }
+            L2Kind::Arbitrum if address == EVMAddress::from_str(ARB_GAS_INFO).unwrap() => {
+                if is("getPricesInWei()") {
+                    Some(words(&ARB_PRICES_IN_WEI))
+                } else if is("getL1BaseFeeEstimate()") || is("getL1GasPriceEstimate()") {
+                    uint(self.l1_base_fee)
+                } else if is("getCurrentTxL1GasFees()") {
+                    uint(EVMU256::ZERO)
+                } else if is("getMinimumGasPrice()") {
+                    uint(EVMU256::from(ARB_PRICES_IN_WEI[5]))
+                } else {
+                    None
+                }
+            }
+            L2Kind::OPStack if address == EVMAddress::from_str(OP_L1_BLOCK).unwrap() => {
+                if is("number()") {
+                    uint(l1_block_number)
+                } else if is("timestamp()") {
+                    uint(l1_timestamp)
+                } else if is("basefee()") {
+                    uint(self.l1_base_fee)
+                } else if is("hash()") || is("batcherHash()") || is("sequenceNumber()") {
+                    uint(EVMU256::ZERO)
+                } else if is("l1FeeOverhead()") {
+                    uint(EVMU256::from(OP_L1_FEE_OVERHEAD))
+                } else if is("l1FeeScalar()") {
+                    uint(EVMU256::from(OP_L1_FEE_SCALAR))
+                } else {
+                    None
+                }
+            }
+            L2Kind::OPStack if address == EVMAddress::from_str(OP_GAS_PRICE_ORACLE).unwrap() => {
+                if is("l1BaseFee()") {
+                    uint(self.l1_base_fee)
+                } else if is("overhead()") {
+                    uint(EVMU256::from(OP_L1_FEE_OVERHEAD))
+                } else if is("scalar()") {
+                    uint(EVMU256::from(OP_L1_FEE_SCALAR))
+                } else if is("decimals()") {
+                    uint(EVMU256::from(OP_L1_FEE_DECIMALS))
+                } else if is("gasPrice()") {
+                    uint(env.tx.gas_price)
+                } else if is("baseFee()") {
+                    uint(env.block.basefee)
+                } else if is("getL1GasUsed(bytes)") {
+                    uint(EVMU256::from(Self::l1_gas_used(bytes_arg(input)?)))
+                } else if is("getL1Fee(bytes)") {
+                    let gas_used = EVMU256::from(Self::l1_gas_used(bytes_arg(input)?));
+                    uint(
+                        gas_used * self.l1_base_fee * EVMU256::from(OP_L1_FEE_SCALAR)
+                            / EVMU256::from(10u64.pow(OP_L1_FEE_DECIMALS as u32)),
+                    )
+                } else {
+                    None
+                }
+            }
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn call(profile: &ChainProfile, address: &str, sig: &str, args: &[u8]) -> Option<Bytes> {
+        let input = [selector(sig).to_vec(), args.to_vec()].concat();
+        profile.system_call(
+            EVMAddress::from_str(address).unwrap(),
+            &input,
+            &Env::default(),
+        )
+    }
+
+    fn uint(out: Option<Bytes>) -> EVMU256 {
+        EVMU256::try_from_be_slice(&out.unwrap()).unwrap()
+    }
+
+    #[test]
+    fn test_arbitrum_profile() {
+        let mut profile = ChainProfile::new(Some(Chain::ARBITRUM));
+        profile.l1_block_number = Some(EVMU256::from(18_000_000));
+        profile.l2_block_number = Some(EVMU256::from(150_000_000));
+        assert_eq!(
+            uint(call(&profile, ARB_SYS, "arbBlockNumber()", &[])),
+            EVMU256::from(150_000_000)
+        );
+        assert_eq!(
+            profile.block_number(EVMU256::from(150_000_000)),
+            EVMU256::from(18_000_000)
+        );
+        assert_eq!(
+            uint(call(&profile, ARB_SYS, "arbChainID()", &[])),
+            EVMU256::from(42161)
+        );
+        assert_eq!(
+            call(&profile, ARB_GAS_INFO, "getPricesInWei()", &[]).unwrap(),
+            words(&ARB_PRICES_IN_WEI)
+        );
+        // deterministic
+        assert_eq!(
+            call(&profile, ARB_SYS, "arbOSVersion()", &[]),
+            call(&profile, ARB_SYS, "arbOSVersion()", &[])
+        );
+        assert_eq!(call(&profile, ARB_SYS, "unknown()", &[]), None);
+        assert_eq!(call(&profile, OP_L1_BLOCK, "number()", &[]), None);
+    }
+
+    #[test]
+    fn test_op_profile() {
+        let mut profile = ChainProfile::new(Some(Chain::BASE));
+        profile.l1_block_number = Some(EVMU256::from(18_000_000));
+        assert_eq!(
+            uint(call(&profile, OP_L1_BLOCK, "number()", &[])),
+            EVMU256::from(18_000_000)
+        );
+        assert_eq!(
+            uint(call(&profile, OP_GAS_PRICE_ORACLE, "decimals()", &[])),
+            EVMU256::from(6)
+        );
+
+        // getL1GasUsed(hex"00ff")
+        let mut args = word(EVMU256::from(32));
+        args.extend(word(EVMU256::from(2)));
+        args.extend([0x00, 0xff]);
+        args.extend([0; 30]);
+        assert_eq!(
+            uint(call(
+                &profile,
+                OP_GAS_PRICE_ORACLE,
+                "getL1GasUsed(bytes)",
+                &args
+            )),
+            EVMU256::from(4 + 16 + OP_L1_FEE_OVERHEAD + 68 * 16)
+        );
+        assert_eq!(
+            call(
+                &profile,
+                OP_GAS_PRICE_ORACLE,
+                "getL1Fee(bytes)",
+                &args[..40]
+            ),
+            None
+        );
+        assert_eq!(call(&profile, ARB_SYS, "arbChainID()", &[]), None);
+    }
+
+    #[test]
+    fn test_mainnet_profile() {
+        let profile = ChainProfile::new(Some(Chain::ETH));
+        assert_eq!(call(&profile, OP_L1_BLOCK, "number()", &[]), None);
+        assert_eq!(profile.spec_id, "Latest");
+        assert_eq!(ChainProfile::new(Some(Chain::FANTOM)).spec_id, "London");
+    }
+}
@@ -0,0 +1,274 @@
+//! Periodic checkpoints of a campaign, enabled by `--checkpoint-interval`, so
+//! that it can be resumed with `--resume <work_dir>` after a crash or
+//! preemption.
+//!
+//! A checkpoint holds the fuzz state (corpora, infant states, votes, bug
+//! metadata), the code known by the host (including contracts created while
+//! fuzzing) and the caches of the onchain middleware. Metadata is keyed by
+//! type, so a checkpoint can only be resumed by the same build of ityfuzz.
+use crate::evm::input::EVMInput;
+use crate::evm::onchain::onchain::OnChain;
+use crate::evm::types::{EVMAddress, EVMFuzzExecutor, EVMFuzzState, EVMQueueExecutor, EVMU256};
+use crate::evm::vm::EVMState;
+use bytes::Bytes;
+use libafl::events::ProgressReporter;
+use libafl::prelude::{CorpusId, ObserversTuple, Stage};
+use libafl::state::UsesState;
+use libafl::{Error, Evaluator};
+use revm_primitives::Bytecode;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::rc::Rc;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tracing::info;
+
+pub type EVMOnChain = OnChain<EVMState, EVMInput, EVMFuzzState>;
+
+const STATE_FILE: &str = "state.cbor";
+const HOST_FILE: &str = "host.cbor";
+const ONCHAIN_FILE: &str = "onchain.cbor";
+const ARGS_FILE: &str = "args.json";
+
+pub fn checkpoint_dir(work_dir: &str) -> String {
+    format!("{}/checkpoint", work_dir)
+}
+
+#[derive(Serialize, Deserialize, Default)]
+pub struct HostCheckpoint {
+    pub code: HashMap<EVMAddress, Bytes>,
+    pub address_to_hash: HashMap<EVMAddress, Vec<[u8; 4]>>,
+}
+
+#[derive(Serialize, Deserialize, Default)]
+pub struct OnChainCheckpoint {
+    pub loaded_data: HashSet<(EVMAddress, EVMU256)>,
+    pub loaded_code: HashSet<EVMAddress>,
+    pub loaded_abi: HashSet<EVMAddress>,
+    pub storage_all: HashMap<EVMAddress, HashMap<String, EVMU256>>,
+    pub storage_dump: HashMap<EVMAddress, HashMap<EVMU256, EVMU256>>,
+    /// Addresses whose storage is stubbed, learned or denied
+    #[serde(default)]
+    pub stubbed: HashSet<EVMAddress>,
+    #[serde(default)]
+    pub productive: HashSet<EVMAddress>,
+}
+
+impl OnChainCheckpoint {
+    pub fn new(onchain: &EVMOnChain) -> Self {
+        Self {
+            loaded_data: onchain.loaded_data.clone(),
+            loaded_code: onchain.loaded_code.clone(),
+            loaded_abi: onchain.loaded_abi.clone(),
+            storage_all: onchain
+                .storage_all
+                .iter()
+                .map(|(addr, storage)| (*addr, storage.as_ref().clone()))
+                .collect(),
+            storage_dump: onchain
+                .storage_dump
+                .iter()
+                .map(|(addr, storage)| (*addr, storage.as_ref().clone()))
+                .collect(),
+            stubbed: onchain.stubbed.clone(),
+            productive: onchain.productive.clone(),
+        }
+    }
+
+    pub fn restore(self, onchain: &mut EVMOnChain) {
+        onchain.loaded_data.extend(self.loaded_data);
+        onchain.loaded_code.extend(self.loaded_code);
+        onchain.loaded_abi.extend(self.loaded_abi);
+        for (addr, storage) in self.storage_all {
+            onchain.storage_all.insert(addr, Arc::new(storage));
+        }
+        for (addr, storage) in self.storage_dump {
+            onchain.storage_dump.insert(addr, Arc::new(storage));
+        }
+        for addr in self.stubbed {
+            onchain.stub(addr);
+        }
+        onchain.productive.extend(self.productive);
+    }
+}
+
+/// Write atomically, so that a checkpoint interrupted by preemption does not
+/// corrupt the previous one
+fn write_cbor<T: Serialize>(dir: &str, file: &str, value: &T) {
+    let tmp = format!("{}/{}.tmp", dir, file);
+    let data = serde_cbor::to_vec(value).expect("failed to serialize checkpoint");
+    fs::write(&tmp, data).expect("failed to write checkpoint");
+    fs::rename(&tmp, format!("{}/{}", dir, file)).expect("failed to write checkpoint");
+}
+
+fn read_cbor<T: DeserializeOwned>(dir: &str, file: &str) -> Option<T> {
+    let data = fs::read(format!("{}/{}", dir, file)).ok()?;
+    Some(serde_cbor::from_slice(&data).expect("failed to deserialize checkpoint"))
+}
+
+/// Save the command line of the campaign, used by `--resume`
+pub fn save_args(work_dir: &str, args: &[String]) {
+    let dir = checkpoint_dir(work_dir);
+    fs::create_dir_all(&dir).expect("failed to create checkpoint dir");
+    fs::write(
+        format!("{}/{}", dir, ARGS_FILE),
+        serde_json::to_string(args).unwrap(),
+    )
+    .expect("failed to save arguments");
+}
+
+pub fn load_args(work_dir: &str) -> Vec<String> {
+    let path = format!("{}/{}", checkpoint_dir(work_dir), ARGS_FILE);
+    let args = fs::read_to_string(&path)
+        .unwrap_or_else(|_| panic!("no checkpoint to resume from at {}", path));
+    serde_json::from_str(&args).expect("failed to parse saved arguments")
+}
+
+pub fn save_checkpoint(
+    work_dir: &str,
+    state: &EVMFuzzState,
+    executor: &EVMQueueExecutor,
+    onchain: Option<&EVMOnChain>,
+) {
+    let dir = checkpoint_dir(work_dir);
+    fs::create_dir_all(&dir).expect("failed to create checkpoint dir");
+
+    let host = &executor.host;
+    let host_checkpoint = HostCheckpoint {
+        code: host
+            .code
+            .iter()
+            .map(|(addr, code)| (*addr, code.bytecode().clone()))
+            .collect(),
+        address_to_hash: host.address_to_hash.clone(),
+    };
+    write_cbor(&dir, HOST_FILE, &host_checkpoint);
+    if let Some(onchain) = onchain {
+        write_cbor(&dir, ONCHAIN_FILE, &OnChainCheckpoint::new(onchain));
+    }
+    // written last, a state is only resumed with the code it needs
+    write_cbor(&dir, STATE_FILE, state);
+}
+
+/// Restore the checkpoint in `work_dir` over a freshly initialized campaign
+pub fn load_checkpoint(
+    work_dir: &str,
+    state: &mut EVMFuzzState,
+    executor: &mut EVMQueueExecutor,
+    onchain: Option<Rc<RefCell<EVMOnChain>>>,
+) {
+    let dir = checkpoint_dir(work_dir);
+    let saved_state: EVMFuzzState = match read_cbor(&dir, STATE_FILE) {
+        Some(saved_state) => saved_state,
+        None => {
+            info!("No checkpoint found in {}, starting from scratch", dir);
+            return;
+        }
+    };
+    *state = saved_state;
+
+    let host_checkpoint: HostCheckpoint = read_cbor(&dir, HOST_FILE).unwrap_or_default();
+    for (addr, code) in host_checkpoint.code {
+        if !executor.host.code.contains_key(&addr) {
+            executor.host.set_code(addr, Bytecode::new_raw(code), state);
+        }
+    }
+    for (addr, hashes) in host_checkpoint.address_to_hash {
+        if !executor.host.address_to_hash.contains_key(&addr) {
+            executor.host.add_hashes(addr, hashes);
+        }
+    }
+
+    if let Some(onchain) = onchain {
+        if let Some(onchain_checkpoint) = read_cbor::<OnChainCheckpoint>(&dir, ONCHAIN_FILE) {
+            // borrowed after restoring the code, as set_code calls the middlewares
+            onchain_checkpoint.restore(&mut onchain.borrow_mut());
+        }
+    }
+    info!(
+        "Resumed from checkpoint in {} ({} executions)",
+        dir,
+        libafl::state::HasExecutions::executions(state)
+    );
+}
+
+/// Stage saving a checkpoint every `interval`
+pub struct CheckpointStage<OT> {
+    executor: Rc<RefCell<EVMQueueExecutor>>,
+    onchain: Option<Rc<RefCell<EVMOnChain>>>,
+    work_dir: String,
+    interval: Duration,
+    last_checkpoint: Instant,
+    pub phantom: std::marker::PhantomData<OT>,
+}
+
+impl<OT> UsesState for CheckpointStage<OT> {
+    type State = EVMFuzzState;
+}
+
+impl<OT> CheckpointStage<OT> {
+    /// A zero interval disables checkpoints
+    pub fn new(
+        executor: Rc<RefCell<EVMQueueExecutor>>,
+        onchain: Option<Rc<RefCell<EVMOnChain>>>,
+        work_dir: String,
+        interval: Duration,
+    ) -> Self {
+        Self {
+            executor,
+            onchain,
+            work_dir,
+            interval,
+            last_checkpoint: Instant::now(),
+            phantom: std::marker::PhantomData,
+        }
+    }
+}
+
+impl<EM, Z, OT> Stage<EVMFuzzExecutor<OT>, EM, Z> for CheckpointStage<OT>
+where
+    Z: Evaluator<EVMFuzzExecutor<OT>, EM, State = Self::State>,
+    EM: ProgressReporter + UsesState<State = Self::State>,
+    OT: ObserversTuple<Self::State>,
+{
+    fn perform(
+        &mut self,
+        _fuzzer: &mut Z,
+        _executor: &mut EVMFuzzExecutor<OT>,
+        state: &mut Self::State,
+        _manager: &mut EM,
+        _corpus_idx: CorpusId,
+    ) -> Result<(), Error> {
+        if self.interval.is_zero() || self.last_checkpoint.elapsed() < self.interval {
+            return Ok(());
+        }
+        let onchain = self.onchain.as_ref().map(|onchain| onchain.borrow());
+        save_checkpoint(
+            &self.work_dir,
+            state,
+            &self.executor.borrow(),
+            onchain.as_deref(),
+        );
+        self.last_checkpoint = Instant::now();
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::Path;
+
+    #[test]
+    fn test_args_roundtrip() {
+        let dir = tempfile::tempdir().unwrap();
+        let work_dir = dir.path().to_str().unwrap();
+        let args = vec!["evm".to_string(), "-t".to_string(), "./tests/*".to_string()];
+        save_args(work_dir, &args);
+        assert_eq!(load_args(work_dir), args);
+        assert!(Path::new(&checkpoint_dir(work_dir)).exists());
+    }
+}
@@ -17,7 +17,7 @@ use libafl::prelude::{Corpus, HasMetadata, Input};
 
 use libafl::state::{HasCorpus, State};
 
-use revm_interpreter::{Host, Interpreter};
+use revm_interpreter::{Host, InstructionResult, Interpreter};
 use revm_primitives::Bytecode;
 use std::collections::{HashMap, HashSet};
 
@@ -1416,6 +1416,7 @@ where
         interp: &mut Interpreter,
         host: &mut FuzzHost<VS, I, S, SC>,
         state: &mut S,
+        result: InstructionResult,
         by: &Bytes,
     ) {
         self.pop_ctx();
@@ -1,12 +1,18 @@
 /// Configuration for the EVM fuzzer
+use crate::evm::chain_profile::ChainProfile;
 use crate::evm::contract_utils::{ContractInfo, ContractLoader};
 use crate::evm::onchain::endpoints::{OnChainConfig, PriceOracle};
+use crate::evm::onchain::fetch_lists::FetchLists;
+use crate::evm::onchain::flashloan_venue::FlashloanVenue;
 
 use crate::evm::blaz::builder::BuildJob;
+use crate::evm::blaz::deployment_script::DeploymentScript;
 use crate::evm::blaz::offchain_artifacts::OffChainArtifact;
 use crate::evm::blaz::offchain_config::OffchainConfig;
 use crate::evm::oracles::erc20::IERC20OracleFlashloan;
+use crate::evm::scribble::ScribbleMetadata;
 use crate::evm::types::EVMAddress;
+use crate::fuzzer::StopConditions;
 use crate::oracle::{Oracle, Producer};
 use std::cell::RefCell;
 use std::collections::HashSet;
@@ -54,7 +60,12 @@ impl FuzzerTypes {
 pub struct Config<VS, Addr, Code, By, Loc, SlotTy, Out, I, S, CI> {
     pub onchain: Option<OnChainConfig>,
     pub onchain_storage_fetching: Option<StorageFetchingMode>,
+    pub onchain_fetch_lists: FetchLists,
+    /// Distinct slots fetched from an address without new coverage or bug
+    /// before its storage is stubbed, 0 if disabled
+    pub onchain_learn_threshold: usize,
     pub flashloan: bool,
+    pub flashloan_venues: Vec<FlashloanVenue>,
     pub is_icy_oracle: bool,
     pub concolic: bool,
     pub concolic_caller: bool,
@@ -67,24 +78,43 @@ pub struct Config<VS, Addr, Code, By, Loc, SlotTy, Out, I, S, CI> {
     pub flashloan_oracle: Rc<RefCell<IERC20OracleFlashloan>>,
     pub icy_oracle: Rc<RefCell<IcyBugOracle>>,
     pub replay_file: Option<String>,
+    /// Directory or glob of the traces seeding the corpus
+    pub seed_corpus: Option<String>,
     pub selfdestruct_oracle: bool,
     pub reentrancy_oracle: bool,
+    /// Threshold (percent) of the spot price oracle, None if disabled
+    pub spot_price_oracle: Option<f64>,
     pub state_comp_oracle: Option<String>,
     pub state_comp_matching: Option<String>,
     pub work_dir: String,
+    /// Seconds between checkpoints, 0 if disabled
+    pub checkpoint_interval: u64,
+    /// Restore the checkpoint in the work dir before fuzzing
+    pub resume: bool,
+    /// Id of this worker in a multi-core campaign
+    pub worker_id: Option<usize>,
+    /// Directory shared by the workers of a multi-core campaign
+    pub sync_dir: Option<String>,
+    pub sync_interval: u64,
     pub write_relationship: bool,
     pub run_forever: bool,
+    pub stop_conditions: StopConditions,
     pub sha3_bypass: bool,
     pub base_path: String,
     pub echidna_oracle: bool,
+    pub assertion_mode: bool,
     pub panic_on_bug: bool,
     pub spec_id: String,
+    pub chain_profile: ChainProfile,
     pub only_fuzz: HashSet<EVMAddress>,
     pub typed_bug: bool,
+    /// Scribble annotations reported by the typed bug oracle
+    pub scribble: Option<ScribbleMetadata>,
     pub selfdestruct_bug: bool,
     pub arbitrary_external_call: bool,
     pub builder: Option<BuildJob>,
     pub local_files_basedir_pattern: Option<String>,
+    pub deployment_script: Option<DeploymentScript>,
 }
 
 impl<VS, Addr, Code, By, Loc, SlotTy, Out, I, S, CI> Debug
@@ -94,7 +124,10 @@ impl<VS, Addr, Code, By, Loc, SlotTy, Out, I, S, CI> Debug
         f.debug_struct("Config")
             .field("onchain", &self.onchain)
             // .field("onchain_storage_fetching", &self.onchain_storage_fetching)
+            .field("onchain_fetch_lists", &self.onchain_fetch_lists)
+            .field("onchain_learn_threshold", &self.onchain_learn_threshold)
             .field("flashloan", &self.flashloan)
+            .field("flashloan_venues", &self.flashloan_venues)
             .field("is_icy_oracle", &self.is_icy_oracle)
             .field("concolic", &self.concolic)
             .field("concolic_caller", &self.concolic_caller)
@@ -105,22 +138,34 @@ impl<VS, Addr, Code, By, Loc, SlotTy, Out, I, S, CI> Debug
             .field("price_oracle", &self.price_oracle)
             // .field("icy_oracle", &self.icy_oracle)
             .field("replay_file", &self.replay_file)
+            .field("seed_corpus", &self.seed_corpus)
             // .field("flashloan_oracle", &self.flashloan_oracle)
             .field("selfdestruct_oracle", &self.selfdestruct_oracle)
+            .field("spot_price_oracle", &self.spot_price_oracle)
             .field("state_comp_oracle", &self.state_comp_oracle)
             .field("state_comp_matching", &self.state_comp_matching)
             .field("work_dir", &self.work_dir)
+            .field("checkpoint_interval", &self.checkpoint_interval)
+            .field("resume", &self.resume)
+            .field("worker_id", &self.worker_id)
+            .field("sync_dir", &self.sync_dir)
+            .field("sync_interval", &self.sync_interval)
             .field("write_relationship", &self.write_relationship)
             .field("run_forever", &self.run_forever)
+            .field("stop_conditions", &self.stop_conditions)
             .field("sha3_bypass", &self.sha3_bypass)
             .field("base_path", &self.base_path)
             .field("echidna_oracle", &self.echidna_oracle)
+            .field("assertion_mode", &self.assertion_mode)
             .field("panic_on_bug", &self.panic_on_bug)
             .field("spec_id", &self.spec_id)
+            .field("chain_profile", &self.chain_profile)
             .field("only_fuzz", &self.only_fuzz)
             .field("typed_bug", &self.typed_bug)
+            .field("scribble", &self.scribble)
             .field("selfdestruct_bug", &self.selfdestruct_bug)
             // .field("builder", &self.builder)
+            .field("deployment_script", &self.deployment_script)
             .finish()
     }
 }
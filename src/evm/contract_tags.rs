@@ -0,0 +1,349 @@
+//! Classify contracts by the standards they implement, from the selectors in
+//! their dispatcher, the proxy slots in their bytecode and by probing them
+//! with static calls. Works for contracts without ABI (decompiled or created
+//! while fuzzing).
+use crate::evm::contract_utils::{extract_sig_from_contract, set_hash};
+use crate::evm::types::EVMAddress;
+use bytes::Bytes;
+use libafl_bolts::impl_serdeany;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum ProxyKind {
+    /// Implementation stored in the EIP-1967 slot
+    EIP1967,
+    /// UUPS, implementation stored in keccak256("PROXIABLE")
+    EIP1822,
+    /// Implementation fetched from the beacon in the EIP-1967 beacon slot
+    Beacon,
+    /// OpenZeppelin (zos) proxy predating EIP-1967
+    OZLegacy,
+    /// EIP-1167 clone, implementation embedded in the code
+    MinimalClone,
+    /// EIP-2535 diamond, facets listed by its `facets()` loupe
+    Diamond,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum ContractTag {
+    ERC20,
+    ERC721,
+    ERC1155,
+    ERC4626,
+    UniswapV2Pair,
+    UniswapV3Pool,
+    Proxy(ProxyKind),
+}
+
+/// Tags of the contracts found so far
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct ContractTags {
+    pub tags: HashMap<EVMAddress, HashSet<ContractTag>>,
+}
+
+impl_serdeany!(ContractTags);
+
+impl ContractTags {
+    pub fn has(&self, addr: &EVMAddress, tag: ContractTag) -> bool {
+        self.tags
+            .get(addr)
+            .map_or(false, |tags| tags.contains(&tag))
+    }
+
+    /// Contracts having the given tag
+    pub fn with_tag(&self, tag: ContractTag) -> Vec<EVMAddress> {
+        self.tags
+            .iter()
+            .filter(|(_, tags)| tags.contains(&tag))
+            .map(|(addr, _)| *addr)
+            .collect()
+    }
+}
+
+pub const EIP1967_IMPLEMENTATION_SLOT: &str =
+    "360894a13ba1a3210667c828492db98dca3e2076cc3735a920a3ca505d382bbc";
+pub const EIP1967_BEACON_SLOT: &str =
+    "a3f0ad74e5423aebfd80d3ef4346578335a9a72aeaee59ff6cb3582b35133d50";
+pub const EIP1822_PROXIABLE_SLOT: &str =
+    "c5f16f0fcc639fa48a6947836d9850f504798523bf8c9a3a87d5876cf622bcf7";
+pub const OZ_LEGACY_IMPLEMENTATION_SLOT: &str =
+    "7050c9e0f4ca769c69bd3a8ef740bc37934f8e2c036e5a723fd8ee048ed3f8c3";
+
+/// EIP-1167 runtime code, around the 20 bytes of the implementation
+const MINIMAL_CLONE_PREFIX: [u8; 10] = [0x36, 0x3d, 0x3d, 0x37, 0x3d, 0x3d, 0x3d, 0x36, 0x3d, 0x73];
+const MINIMAL_CLONE_SUFFIX: [u8; 15] = [
+    0x5a, 0xf4, 0x3d, 0x82, 0x80, 0x3e, 0x90, 0x3d, 0x91, 0x60, 0x2b, 0x57, 0xfd, 0x5b, 0xf3,
+];
+
+/// Implementation of an EIP-1167 clone
+pub fn minimal_clone_implementation(code: &[u8]) -> Option<EVMAddress> {
+    if code.len() < 45 || code[..10] != MINIMAL_CLONE_PREFIX || code[30..45] != MINIMAL_CLONE_SUFFIX
+    {
+        return None;
+    }
+    Some(EVMAddress::from_slice(&code[10..30]))
+}
+
+fn sig(signature: &str) -> [u8; 4] {
+    let mut hash = [0; 4];
+    set_hash(signature, &mut hash);
+    hash
+}
+
+fn call(signature: &str, args: &[u8]) -> Bytes {
+    Bytes::from([sig(signature).to_vec(), args.to_vec()].concat())
+}
+
+/// Functions a contract must dispatch to implement the standard
+fn required_signatures(tag: ContractTag) -> &'static [&'static str] {
+    match tag {
+        ContractTag::ERC20 => &[
+            "balanceOf(address)",
+            "transfer(address,uint256)",
+            "transferFrom(address,address,uint256)",
+            "approve(address,uint256)",
+        ],
+        ContractTag::ERC721 => &[
+            "ownerOf(uint256)",
+            "safeTransferFrom(address,address,uint256)",
+            "setApprovalForAll(address,bool)",
+        ],
+        ContractTag::ERC1155 => &[
+            "balanceOf(address,uint256)",
+            "balanceOfBatch(address[],uint256[])",
+            "safeTransferFrom(address,address,uint256,uint256,bytes)",
+        ],
+        ContractTag::ERC4626 => &[
+            "asset()",
+            "totalAssets()",
+            "deposit(uint256,address)",
+            "redeem(uint256,address,address)",
+        ],
+        ContractTag::UniswapV2Pair => &[
+            "getReserves()",
+            "swap(uint256,uint256,address,bytes)",
+            "skim(address)",
+            "sync()",
+        ],
+        ContractTag::UniswapV3Pool => &[
+            "slot0()",
+            "liquidity()",
+            "swap(address,bool,int256,uint160,bytes)",
+        ],
+        ContractTag::Proxy(_) => &[],
+    }
+}
+
+const SELECTOR_TAGS: [ContractTag; 6] = [
+    ContractTag::ERC20,
+    ContractTag::ERC721,
+    ContractTag::ERC1155,
+    ContractTag::ERC4626,
+    ContractTag::UniswapV2Pair,
+    ContractTag::UniswapV3Pool,
+];
+
+pub fn classify_selectors(selectors: &HashSet<[u8; 4]>) -> HashSet<ContractTag> {
+    SELECTOR_TAGS
+        .iter()
+        .filter(|tag| {
+            required_signatures(**tag)
+                .iter()
+                .all(|s| selectors.contains(&sig(s)))
+        })
+        .cloned()
+        .collect()
+}
+
+/// A proxy embeds the slot holding its implementation and delegates calls.
+/// Implementations of UUPS proxies also embed the slot but are only tagged if
+/// they delegate calls. Diamonds are only found by probing their loupe (see
+/// `onchain::proxy_resolver`).
+pub fn classify_proxy(code: &[u8]) -> Option<ProxyKind> {
+    if minimal_clone_implementation(code).is_some() {
+        return Some(ProxyKind::MinimalClone);
+    }
+    // DELEGATECALL
+    if !code.contains(&0xf4) {
+        return None;
+    }
+    let contains = |slot: &str| {
+        let slot = hex::decode(slot).unwrap();
+        code.windows(32).any(|w| w == slot.as_slice())
+    };
+    if contains(EIP1967_BEACON_SLOT) {
+        Some(ProxyKind::Beacon)
+    } else if contains(EIP1967_IMPLEMENTATION_SLOT) {
+        Some(ProxyKind::EIP1967)
+    } else if contains(EIP1822_PROXIABLE_SLOT) {
+        Some(ProxyKind::EIP1822)
+    } else if contains(OZ_LEGACY_IMPLEMENTATION_SLOT) {
+        Some(ProxyKind::OZLegacy)
+    } else {
+        None
+    }
+}
+
+/// Tags found statically from the runtime code
+pub fn classify_code(code: &[u8]) -> HashSet<ContractTag> {
+    let selectors = extract_sig_from_contract(&hex::encode(code))
+        .into_iter()
+        .collect::<HashSet<_>>();
+    let mut tags = classify_selectors(&selectors);
+    if let Some(kind) = classify_proxy(code) {
+        tags.insert(ContractTag::Proxy(kind));
+    }
+    tags
+}
+
+fn word(out: &[u8], idx: usize) -> Option<&[u8]> {
+    out.get(idx * 32..idx * 32 + 32)
+}
+
+fn is_small(out: &[u8], max: u8) -> bool {
+    word(out, 0).map_or(false, |w| w[..31].iter().all(|b| *b == 0) && w[31] <= max)
+}
+
+fn is_true(out: &[u8]) -> bool {
+    is_small(out, 1) && !is_small(out, 0)
+}
+
+fn is_address(out: &[u8]) -> bool {
+    word(out, 0).map_or(false, |w| {
+        w[..12].iter().all(|b| *b == 0) && w[12..].iter().any(|b| *b != 0)
+    })
+}
+
+pub type ProbeCheck = fn(&[Vec<u8>]) -> bool;
+
+/// Static calls confirming a tag when the dispatcher could not be read (e.g.,
+/// the contract forwards calls to another one). A probe passes if all its
+/// calls succeed and the outputs pass the check.
+pub fn probes() -> Vec<(ContractTag, Vec<Bytes>, ProbeCheck)> {
+    let zero_address = [0u8; 32];
+    let supports_interface = |id: [u8; 4]| {
+        let mut arg = id.to_vec();
+        arg.resize(32, 0);
+        call("supportsInterface(bytes4)", &arg)
+    };
+    vec![
+        (
+            ContractTag::ERC721,
+            vec![supports_interface([0x80, 0xac, 0x58, 0xcd])],
+            |out| is_true(&out[0]),
+        ),
+        (
+            ContractTag::ERC1155,
+            vec![supports_interface([0xd9, 0xb6, 0x7a, 0x26])],
+            |out| is_true(&out[0]),
+        ),
+        (
+            ContractTag::ERC20,
+            vec![
+                call("decimals()", &[]),
+                call("totalSupply()", &[]),
+                call("balanceOf(address)", &zero_address),
+            ],
+            |out| is_small(&out[0], 255) && out[1].len() >= 32 && out[2].len() >= 32,
+        ),
+        (
+            ContractTag::ERC4626,
+            vec![call("asset()", &[]), call("totalAssets()", &[])],
+            |out| is_address(&out[0]) && out[1].len() >= 32,
+        ),
+        (
+            ContractTag::UniswapV2Pair,
+            vec![
+                call("getReserves()", &[]),
+                call("token0()", &[]),
+                call("token1()", &[]),
+            ],
+            |out| out[0].len() >= 96 && is_address(&out[1]) && is_address(&out[2]),
+        ),
+        (
+            ContractTag::UniswapV3Pool,
+            vec![
+                call("slot0()", &[]),
+                call("liquidity()", &[]),
+                call("token0()", &[]),
+            ],
+            |out| out[0].len() >= 224 && out[1].len() >= 32 && is_address(&out[2]),
+        ),
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use revm_primitives::{keccak256, U256};
+
+    #[test]
+    fn test_proxy_slots() {
+        let slot = |s: &str| {
+            let hash = U256::from_be_bytes(keccak256(s.as_bytes()).0);
+            hex::encode((hash - U256::from(1)).to_be_bytes::<32>())
+        };
+        assert_eq!(
+            slot("eip1967.proxy.implementation"),
+            EIP1967_IMPLEMENTATION_SLOT
+        );
+        assert_eq!(slot("eip1967.proxy.beacon"), EIP1967_BEACON_SLOT);
+        assert_eq!(
+            hex::encode(keccak256("PROXIABLE".as_bytes()).0),
+            EIP1822_PROXIABLE_SLOT
+        );
+        assert_eq!(
+            hex::encode(keccak256("org.zeppelinos.proxy.implementation".as_bytes()).0),
+            OZ_LEGACY_IMPLEMENTATION_SLOT
+        );
+
+        // PUSH32 slot SLOAD ... DELEGATECALL
+        let mut code = vec![0x7f];
+        code.extend(hex::decode(EIP1967_IMPLEMENTATION_SLOT).unwrap());
+        code.extend([0x54, 0xf4]);
+        assert_eq!(classify_proxy(&code), Some(ProxyKind::EIP1967));
+        code.pop();
+        assert_eq!(classify_proxy(&code), None);
+
+        let clone = hex::decode(
+            "363d3d373d3d3d363d73bebebebebebebebebebebebebebebebebebebebe5af43d82803e903d91602b57fd5bf3",
+        )
+        .unwrap();
+        assert_eq!(classify_proxy(&clone), Some(ProxyKind::MinimalClone));
+        assert_eq!(
+            minimal_clone_implementation(&clone),
+            Some(EVMAddress::from_slice(&[0xbe; 20]))
+        );
+    }
+
+    #[test]
+    fn test_classify_selectors() {
+        let selectors = |sigs: &[&str]| sigs.iter().map(|s| sig(s)).collect::<HashSet<_>>();
+        let mut erc20 = required_signatures(ContractTag::ERC20).to_vec();
+        assert_eq!(
+            classify_selectors(&selectors(&erc20)),
+            HashSet::from([ContractTag::ERC20])
+        );
+        erc20.extend(required_signatures(ContractTag::ERC4626));
+        assert_eq!(
+            classify_selectors(&selectors(&erc20)),
+            HashSet::from([ContractTag::ERC20, ContractTag::ERC4626])
+        );
+
+        // ERC721 shares balanceOf, transferFrom and approve with ERC20
+        let erc721 = selectors(&[
+            "balanceOf(address)",
+            "ownerOf(uint256)",
+            "transferFrom(address,address,uint256)",
+            "safeTransferFrom(address,address,uint256)",
+            "approve(address,uint256)",
+            "setApprovalForAll(address,bool)",
+        ]);
+        assert_eq!(
+            classify_selectors(&erc721),
+            HashSet::from([ContractTag::ERC721])
+        );
+        assert_eq!(sig("transfer(address,uint256)"), [0xa9, 0x05, 0x9c, 0xbb]);
+    }
+}
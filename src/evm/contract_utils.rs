@@ -1,4 +1,5 @@
 use crate::evm::types::{fixed_address, generate_random_address, EVMAddress, EVMFuzzState};
+use bytes::Bytes;
 use core::panic;
 /// Load contract from file system or remote
 use glob::glob;
@@ -14,6 +15,7 @@ extern crate crypto;
 
 use crate::evm::abi::get_abi_type_boxed_with_address;
 use crate::evm::onchain::endpoints::OnChainConfig;
+use crate::evm::onchain::proxy_resolver::merge_implementation_abis;
 use crate::evm::srcmap::parser::{
     decode_instructions, decode_instructions_with_replacement, SourceMapLocation,
 };
@@ -21,9 +23,12 @@ use crate::evm::srcmap::parser::{
 use self::crypto::digest::Digest;
 use self::crypto::sha3::Sha3;
 use crate::evm::blaz::builder::{BuildJob, BuildJobResult};
+use crate::evm::blaz::local_artifacts::{LinkReference, LocalArtifacts};
 use crate::evm::blaz::offchain_artifacts::OffChainArtifact;
 use crate::evm::blaz::offchain_config::OffchainConfig;
 use crate::evm::bytecode_iterator::all_bytecode;
+use crate::evm::contract_tags::ProxyKind;
+use crate::evm::proxy;
 use regex::Regex;
 use revm_interpreter::opcode::PUSH4;
 use serde::{Deserialize, Serialize};
@@ -54,6 +59,38 @@ pub struct ContractInfo {
     pub deployed_address: EVMAddress,
     pub source_map: Option<HashMap<usize, SourceMapLocation>>,
     pub build_artifact: Option<BuildJobResult>,
+    /// Unlinked library addresses in `code`
+    pub link_references: Vec<LinkReference>,
+    /// Implementation or beacon of a proxy, only called through the proxy
+    pub behind_proxy: bool,
+}
+
+impl ContractInfo {
+    /// Contract name without the path and glob of the target
+    pub fn short_name(&self) -> String {
+        self.name
+            .trim_end_matches('*')
+            .rsplit('/')
+            .next()
+            .unwrap()
+            .to_string()
+    }
+
+    /// Creation code without the appended constructor args
+    pub fn creation_code(&self) -> &[u8] {
+        if self.abi.iter().any(|abi| abi.is_constructor) {
+            &self.code[..self.code.len() - self.constructor_args.len()]
+        } else {
+            &self.code
+        }
+    }
+
+    /// Replace the constructor args appended to the creation code
+    pub fn set_constructor_args(&mut self, constructor_args: Vec<u8>) {
+        self.code.truncate(self.creation_code().len());
+        self.code.extend(constructor_args.clone());
+        self.constructor_args = constructor_args;
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -222,6 +259,24 @@ impl ContractLoader {
             .collect()
     }
 
+    /// Append constructor args to the creation code of the contract, using
+    /// the default args from the constructor ABI if none is provided
+    fn append_constructor_args(contract: &mut ContractInfo) {
+        if let Some(abi) = contract.abi.iter().find(|abi| abi.is_constructor) {
+            let mut abi_instance =
+                get_abi_type_boxed_with_address(&abi.abi, fixed_address(FIX_DEPLOYER).0.to_vec());
+            abi_instance.set_func_with_signature(abi.function, &abi.function_name, &abi.abi);
+            if contract.constructor_args.is_empty() {
+                debug!("No constructor args found, using default constructor args");
+                contract.constructor_args = abi_instance.get().get_bytes();
+            }
+            // debug!("Constructor args: {:?}", result.constructor_args);
+            contract.code.extend(contract.constructor_args.clone());
+        } else {
+            debug!("No constructor in ABI found, skipping");
+        }
+    }
+
     pub fn from_prefix(
         prefix: &str,
         state: &mut EVMFuzzState,
@@ -253,6 +308,8 @@ impl ContractLoader {
                     .clone()
             }),
             build_artifact: None,
+            link_references: vec![],
+            behind_proxy: false,
         };
         let mut abi_result = ABIInfo {
             source: prefix.to_string(),
@@ -287,25 +344,23 @@ impl ContractLoader {
             }
         }
 
-        if let Some(abi) = abi_result.abi.iter().find(|abi| abi.is_constructor) {
-            let mut abi_instance =
-                get_abi_type_boxed_with_address(&abi.abi, fixed_address(FIX_DEPLOYER).0.to_vec());
-            abi_instance.set_func_with_signature(abi.function, &abi.function_name, &abi.abi);
-            if contract_result.constructor_args.is_empty() {
-                debug!("No constructor args found, using default constructor args");
-                contract_result.constructor_args = abi_instance.get().get_bytes();
-            }
-            // debug!("Constructor args: {:?}", result.constructor_args);
-            contract_result
-                .code
-                .extend(contract_result.constructor_args.clone());
-        } else {
-            debug!("No constructor in ABI found, skipping");
+        Self::append_constructor_args(&mut contract_result);
+        Self::use_proxy_deploy_code(&mut contract_result, proxy_deploy_codes);
+        Self {
+            contracts: if !contract_result.code.is_empty() {
+                vec![contract_result]
+            } else {
+                vec![]
+            },
+            abis: vec![abi_result],
         }
+    }
 
-        // now check if contract is deployed through proxy by checking function signatures
-        // if it is, then we use the new bytecode from proxy
-        // todo: find a better way to do this
+    /// Check if the contract is deployed through a proxy by comparing its
+    /// function signatures with those of the codes deployed by the proxy, if it
+    /// is, then we use the code deployed by the proxy
+    // todo: find a better way to do this
+    fn use_proxy_deploy_code(contract_result: &mut ContractInfo, proxy_deploy_codes: &Vec<String>) {
         let current_code = hex::encode(&contract_result.code);
         for deployed_code in proxy_deploy_codes {
             // if deploy_code startwiths '0x' then remove it
@@ -334,14 +389,6 @@ impl ContractLoader {
                 }
             }
         }
-        Self {
-            contracts: if !contract_result.code.is_empty() {
-                vec![contract_result]
-            } else {
-                vec![]
-            },
-            abis: vec![abi_result],
-        }
     }
 
     // This function loads constructs Contract infos from path p
@@ -462,12 +509,13 @@ impl ContractLoader {
 
             let contract_code = bytecode.expect("Failed to get bytecode");
 
-            let abi_parsed = if let Some(abi) = abi {
+            let mut abi_parsed = if let Some(abi) = abi {
                 Self::parse_abi_str(&abi)
             } else {
                 debug!("ABI not found for {}, we'll decompile", addr);
                 vec![]
             };
+            merge_implementation_abis(onchain, addr, &mut abi_parsed);
             contracts.push(ContractInfo {
                 name: format!("{:?}", addr),
                 code: contract_code.bytes().to_vec(),
@@ -477,6 +525,8 @@ impl ContractLoader {
                 deployed_address: addr,
                 source_map: None,
                 build_artifact,
+                link_references: vec![],
+                behind_proxy: false,
             });
             abis.push(ABIInfo {
                 source: addr.to_string(),
@@ -530,11 +580,283 @@ impl ContractLoader {
                     more_info.abi.clone(),
                     more_info.source_map_replacements.clone(),
                 )),
+                link_references: vec![],
+                behind_proxy: false,
+            })
+        }
+
+        Self { contracts, abis }
+    }
+
+    /// Load contracts from a Foundry `out` directory, i.e., `out/**/*.json`
+    pub fn from_foundry_out(
+        out_dir: &str,
+        state: &mut EVMFuzzState,
+        constructor_args_map: &HashMap<String, Vec<String>>,
+    ) -> Self {
+        let artifacts =
+            LocalArtifacts::from_foundry_out(out_dir).expect("failed to load foundry artifacts");
+        Self::from_local_artifacts(artifacts, state, constructor_args_map)
+    }
+
+    /// Load contracts from a Hardhat `artifacts` directory, i.e.,
+    /// `artifacts/**/*.json`
+    pub fn from_hardhat_artifacts(
+        artifacts_dir: &str,
+        state: &mut EVMFuzzState,
+        constructor_args_map: &HashMap<String, Vec<String>>,
+    ) -> Self {
+        let artifacts = LocalArtifacts::from_hardhat_artifacts(artifacts_dir)
+            .expect("failed to load hardhat artifacts");
+        Self::from_local_artifacts(artifacts, state, constructor_args_map)
+    }
+
+    fn from_local_artifacts(
+        artifacts: LocalArtifacts,
+        state: &mut EVMFuzzState,
+        constructor_args_map: &HashMap<String, Vec<String>>,
+    ) -> Self {
+        // libraries linked by the targets are deployed as well, even if they
+        // come from dependencies
+        let mut libraries: HashSet<String> = HashSet::new();
+        loop {
+            let linked: HashSet<String> = artifacts
+                .contracts
+                .iter()
+                .filter(|artifact| {
+                    !artifact.is_dependency() || libraries.contains(&artifact.qualified_name())
+                })
+                .flat_map(|artifact| {
+                    artifact
+                        .link_references
+                        .iter()
+                        .chain(artifact.deployed_link_references.iter())
+                        .map(|link| link.library.clone())
+                })
+                .collect();
+            if linked.is_subset(&libraries) {
+                break;
+            }
+            libraries.extend(linked);
+        }
+
+        let mut contracts: Vec<ContractInfo> = vec![];
+        let mut abis: Vec<ABIInfo> = vec![];
+        let mut library_addresses: HashMap<String, EVMAddress> = HashMap::new();
+        let mut deployed_link_references: Vec<Vec<LinkReference>> = vec![];
+        for artifact in artifacts
+            .contracts
+            .into_iter()
+            .filter(|artifact| {
+                artifact.is_deployable()
+                    && (!artifact.is_dependency() || libraries.contains(&artifact.qualified_name()))
             })
+            .sorted_by_key(|artifact| {
+                // deploy libraries first
+                (
+                    !libraries.contains(&artifact.qualified_name()),
+                    artifact.source_name.clone(),
+                    artifact.contract_name.clone(),
+                )
+            })
+        {
+            debug!(
+                "Loading contract {}:{}",
+                artifact.source_name, artifact.contract_name
+            );
+            let artifact_name = artifact.qualified_name();
+            let abi = Self::parse_abi_str(&artifact.abi);
+            abis.push(ABIInfo {
+                source: artifact_name.clone(),
+                abi: abi.clone(),
+            });
+
+            let constructor_args = constructor_args_map
+                .get(&artifact.contract_name)
+                .map(|args| Self::constructor_args_encode(args))
+                .unwrap_or_default();
+            let mut contract = ContractInfo {
+                name: artifact.contract_name.clone(),
+                code: artifact.creation_bytecode.to_vec(),
+                abi,
+                is_code_deployed: false,
+                constructor_args,
+                deployed_address: generate_random_address(state),
+                source_map: None,
+                build_artifact: if artifact.source_map.is_empty() {
+                    None
+                } else {
+                    Some(BuildJobResult::new(
+                        artifact.sources,
+                        artifact.source_map,
+                        artifact.deployed_bytecode,
+                        artifact.abi,
+                        vec![],
+                    ))
+                },
+                link_references: artifact.link_references,
+                behind_proxy: false,
+            };
+            library_addresses.insert(artifact_name, contract.deployed_address);
+            deployed_link_references.push(artifact.deployed_link_references);
+            contracts.push(contract);
+        }
+
+        for (contract, deployed_link_references) in
+            contracts.iter_mut().zip(deployed_link_references)
+        {
+            link_libraries(
+                &mut contract.code,
+                &contract.link_references,
+                &library_addresses,
+            );
+            if let Some(build_artifact) = &mut contract.build_artifact {
+                let mut bytecodes = build_artifact.bytecodes.to_vec();
+                link_libraries(
+                    &mut bytecodes,
+                    &deployed_link_references,
+                    &library_addresses,
+                );
+                build_artifact.bytecodes = Bytes::from(bytecodes);
+            }
+            Self::append_constructor_args(contract);
+        }
+
+        Self { contracts, abis }
+    }
+
+    /// Load the deployable contracts of builder artifacts, deployed at random
+    /// addresses, with the code deployed through the proxy if it matches one
+    /// of `proxy_deploy_codes`
+    pub fn from_offchain_artifacts(
+        offchain_artifacts: &Vec<OffChainArtifact>,
+        state: &mut EVMFuzzState,
+        proxy_deploy_codes: &Vec<String>,
+        constructor_args_map: &HashMap<String, Vec<String>>,
+    ) -> Self {
+        let mut contracts: Vec<ContractInfo> = vec![];
+        let mut abis: Vec<ABIInfo> = vec![];
+        for artifact in offchain_artifacts {
+            for (slug, more_info) in artifact
+                .contracts
+                .iter()
+                .sorted_by_key(|(slug, _)| <&(String, String)>::clone(slug))
+            {
+                // interfaces and abstract contracts
+                if more_info.deploy_bytecode.is_empty() {
+                    continue;
+                }
+                let abi = Self::parse_abi_str(&more_info.abi);
+                abis.push(ABIInfo {
+                    source: format!("{}:{}", slug.0, slug.1),
+                    abi: abi.clone(),
+                });
+                let mut contract = ContractInfo {
+                    name: format!("{}:{}", slug.0, slug.1),
+                    code: more_info.deploy_bytecode.to_vec(),
+                    abi,
+                    is_code_deployed: false,
+                    constructor_args: constructor_args_map
+                        .get(&slug.1)
+                        .map(|args| Self::constructor_args_encode(args))
+                        .unwrap_or_default(),
+                    deployed_address: generate_random_address(state),
+                    source_map: None,
+                    build_artifact: Some(BuildJobResult::new(
+                        artifact.sources.clone(),
+                        more_info.source_map.clone(),
+                        more_info.deploy_bytecode.clone(),
+                        more_info.abi.clone(),
+                        more_info.source_map_replacements.clone(),
+                    )),
+                    link_references: vec![],
+                    behind_proxy: false,
+                };
+                Self::append_constructor_args(&mut contract);
+                Self::use_proxy_deploy_code(&mut contract, proxy_deploy_codes);
+                contracts.push(contract);
+            }
         }
 
         Self { contracts, abis }
     }
+
+    /// Deploy the contracts named in `proxied` (or all if it contains "all")
+    /// behind a proxy of `kind`. The proxy takes the ABI of its implementation,
+    /// which is only called through the proxy. Upgradeable contracts can be
+    /// initialized with the setup calls of a deployment script.
+    pub fn add_proxies(&mut self, proxied: &[String], kind: ProxyKind, state: &mut EVMFuzzState) {
+        let mut proxies: Vec<ContractInfo> = vec![];
+        for contract in &mut self.contracts {
+            let name = contract.short_name();
+            let is_proxied = proxied.iter().any(|proxied| {
+                proxied == "all" || *proxied == name || name.ends_with(&format!(":{}", proxied))
+            });
+            if !is_proxied || contract.abi.iter().all(|abi| abi.is_constructor) {
+                continue;
+            }
+            contract.behind_proxy = true;
+
+            let mut target = contract.deployed_address;
+            if kind == ProxyKind::Beacon {
+                let beacon_abi = Self::parse_abi_str(
+                    r#"[{"type":"function","name":"implementation","inputs":[],"outputs":[{"name":"","type":"address"}],"stateMutability":"view"}]"#,
+                );
+                let beacon = ContractInfo {
+                    name: format!("{}Beacon", name),
+                    code: proxy::beacon_creation_code(contract.deployed_address),
+                    abi: beacon_abi,
+                    is_code_deployed: false,
+                    constructor_args: proxy::address_arg(contract.deployed_address),
+                    deployed_address: generate_random_address(state),
+                    source_map: None,
+                    build_artifact: None,
+                    link_references: vec![],
+                    behind_proxy: true,
+                };
+                target = beacon.deployed_address;
+                proxies.push(beacon);
+            }
+
+            let abi = contract
+                .abi
+                .iter()
+                .filter(|abi| !abi.is_constructor)
+                .cloned()
+                .collect_vec();
+            self.abis.push(ABIInfo {
+                source: format!("{}Proxy", name),
+                abi: abi.clone(),
+            });
+            proxies.push(ContractInfo {
+                name: format!("{}Proxy", name),
+                code: proxy::proxy_creation_code(kind, target),
+                abi,
+                is_code_deployed: false,
+                constructor_args: proxy::address_arg(target),
+                deployed_address: generate_random_address(state),
+                source_map: None,
+                build_artifact: None,
+                link_references: vec![],
+                behind_proxy: false,
+            });
+        }
+        self.contracts.extend(proxies);
+    }
+}
+
+/// Write the addresses of the libraries at the placeholders of unlinked bytecode
+fn link_libraries(
+    code: &mut [u8],
+    link_references: &[LinkReference],
+    library_addresses: &HashMap<String, EVMAddress>,
+) {
+    for link in link_references {
+        let address = library_addresses
+            .get(&link.library)
+            .unwrap_or_else(|| panic!("library {} not found", link.library));
+        code[link.start..link.start + 20].copy_from_slice(&address.0);
+    }
 }
 
 type ContractSourceMap = HashMap<usize, SourceMapLocation>;
@@ -898,4 +1220,59 @@ mod tests {
         assert_eq!(abi_cfg.function_name, "constructor");
         assert_eq!(abi_cfg.abi, "(address)");
     }
+
+    #[test]
+    fn test_link_libraries() {
+        let mut code = vec![0x73; 24];
+        let library = EVMAddress::from_str("0x1111111111111111111111111111111111111111").unwrap();
+        link_libraries(
+            &mut code,
+            &[LinkReference {
+                library: "src/Lib.sol:Lib".to_string(),
+                start: 1,
+            }],
+            &HashMap::from([("src/Lib.sol:Lib".to_string(), library)]),
+        );
+        assert_eq!(code[0], 0x73);
+        assert_eq!(code[1..21], library.0);
+        assert_eq!(code[21..], [0x73; 3]);
+    }
+
+    #[test]
+    fn test_add_proxies() {
+        let mut state = FuzzState::new(0, None);
+        let implementation = ContractInfo {
+            name: "tests/Vault*".to_string(),
+            code: vec![0x00],
+            abi: ContractLoader::parse_abi_str(
+                r#"[{"type":"function","name":"deposit","inputs":[],"outputs":[],"stateMutability":"payable"},
+                    {"type":"constructor","inputs":[],"stateMutability":"nonpayable"}]"#,
+            ),
+            is_code_deployed: false,
+            constructor_args: vec![],
+            deployed_address: generate_random_address(&mut state),
+            source_map: None,
+            build_artifact: None,
+            link_references: vec![],
+            behind_proxy: false,
+        };
+        let mut loader = ContractLoader {
+            contracts: vec![implementation.clone()],
+            abis: vec![],
+        };
+        loader.add_proxies(&["Vault".to_string()], ProxyKind::Beacon, &mut state);
+
+        let names = loader
+            .contracts
+            .iter()
+            .map(|c| c.name.clone())
+            .collect_vec();
+        assert_eq!(names, vec!["tests/Vault*", "VaultBeacon", "VaultProxy"]);
+        let (beacon, proxy) = (&loader.contracts[1], &loader.contracts[2]);
+        assert!(loader.contracts[0].behind_proxy && beacon.behind_proxy && !proxy.behind_proxy);
+        assert!(beacon.code.ends_with(&implementation.deployed_address.0));
+        assert!(proxy.code.ends_with(&beacon.deployed_address.0));
+        assert_eq!(proxy.abi.len(), 1);
+        assert_eq!(proxy.abi[0].function_name, "deposit");
+    }
 }
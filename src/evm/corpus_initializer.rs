@@ -7,6 +7,10 @@ use crate::evm::input::{ConciseEVMInput, EVMInput, EVMInputTy};
 use crate::evm::mutator::AccessPattern;
 
 use crate::evm::onchain::onchain::BLACKLIST_ADDR;
+use crate::evm::oracles::echidna::{
+    is_foundry_invariant, InvariantTargets, EXCLUDE_CONTRACTS_SELECTOR, TARGET_CONTRACTS_SELECTOR,
+    TARGET_SELECTORS_SELECTOR,
+};
 use crate::evm::types::{
     fixed_address, EVMAddress, EVMFuzzState, EVMInfantStateState, EVMStagedVMState,
     ProjectSourceMapTy, EVMU256,
@@ -21,6 +25,7 @@ use libafl::corpus::{Corpus, Testcase};
 
 use crate::dump_txn;
 use crate::evm::blaz::builder::BuildJobResult;
+use crate::evm::blaz::deployment_script::{parse_amount, resolve_address, DeploymentScript};
 use crate::evm::onchain::abi_decompiler::fetch_abi_heimdall;
 use crate::evm::onchain::flashloan::register_borrow_txn;
 use crate::evm::presets::presets::Preset;
@@ -34,9 +39,10 @@ use itertools::Itertools;
 
 use crypto::sha3::Sha3Mode::Keccak256;
 use libafl::prelude::HasMetadata;
-use libafl::schedulers::Scheduler;
+use libafl::schedulers::{RemovableScheduler, Scheduler};
 use libafl::state::HasCorpus;
 use libafl_bolts::impl_serdeany;
+use revm_interpreter::InstructionResult;
 use revm_primitives::Bytecode;
 use serde::{Deserialize, Serialize};
 use std::cell::RefCell;
@@ -63,6 +69,9 @@ where
     #[cfg(feature = "use_presets")]
     presets: Vec<&'a dyn Preset<EVMInput, EVMFuzzState, EVMState, SC>>,
     work_dir: String,
+    deployment_script: Option<DeploymentScript>,
+    /// Only fuzz the targets of Foundry invariant tests
+    invariant_targets: bool,
 }
 
 pub struct EVMInitializationArtifacts {
@@ -97,6 +106,7 @@ impl ABIMap {
 }
 
 #[macro_export]
+/// `$host` is a `&mut FuzzHost`
 macro_rules! handle_contract_insertion {
     ($state: expr, $host: expr, $deployed_address: expr, $abi: expr) => {
         let (is_erc20, is_pair) = match $host.flashloan_middleware {
@@ -112,13 +122,25 @@ macro_rules! handle_contract_insertion {
             register_borrow_txn(scheduler, $state, $deployed_address);
         }
         if is_pair {
-            let mut mid = $host
-                .flashloan_middleware
-                .as_ref()
-                .unwrap()
+            let middleware = $host.flashloan_middleware.clone().unwrap();
+            let mut mid = middleware.deref().borrow_mut();
+            mid.on_pair_insertion($host, $state, $deployed_address);
+        }
+        let venue = match $host.flashloan_middleware {
+            Some(ref middleware) => middleware
                 .deref()
-                .borrow_mut();
-            mid.on_pair_insertion(&$host, $state, $deployed_address);
+                .borrow_mut()
+                .on_provider_insertion(&$deployed_address, &$abi),
+            None => None,
+        };
+        if let Some(venue) = venue {
+            let scheduler = $host.scheduler.clone();
+            $crate::evm::onchain::flashloan::register_flashloan_txn(
+                scheduler,
+                $state,
+                $deployed_address,
+                venue,
+            );
         }
     };
 }
@@ -144,7 +166,7 @@ macro_rules! add_input_to_corpus {
 
 impl<'a, SC, ISC> EVMCorpusInitializer<'a, SC, ISC>
 where
-    SC: Scheduler<State = EVMFuzzState> + Clone + 'static,
+    SC: RemovableScheduler<State = EVMFuzzState> + Clone + 'static,
     ISC: Scheduler<State = EVMInfantStateState>,
 {
     pub fn new(
@@ -162,9 +184,19 @@ where
             #[cfg(feature = "use_presets")]
             presets: vec![],
             work_dir,
+            deployment_script: None,
+            invariant_targets: false,
         }
     }
 
+    pub fn set_deployment_script(&mut self, script: DeploymentScript) {
+        self.deployment_script = Some(script);
+    }
+
+    pub fn set_invariant_targets(&mut self, invariant_targets: bool) {
+        self.invariant_targets = invariant_targets;
+    }
+
     #[cfg(feature = "use_presets")]
     pub fn register_preset(
         &mut self,
@@ -180,6 +212,7 @@ where
         loader: &mut ContractLoader,
     ) -> EVMInitializationArtifacts {
         self.state.metadata_map_mut().insert(ABIMap::new());
+        self.executor.host.known_contracts = loader.contracts.clone();
         self.initialize_contract(loader);
         #[cfg(feature = "attacker_is_caller")]
         self.setup_specific_caller(self.state.txn.clone().unwrap().caller);
@@ -282,11 +315,15 @@ where
                     .insert(contract.deployed_address, build_artifact.clone());
             }
 
+            self.executor
+                .host
+                .classify_contract(contract.deployed_address, self.state);
+
             #[cfg(feature = "flashloan_v2")]
             {
                 handle_contract_insertion!(
                     self.state,
-                    self.executor.host,
+                    &mut self.executor.host,
                     contract.deployed_address,
                     contract.abi.clone()
                 );
@@ -302,6 +339,11 @@ where
                 continue;
             }
 
+            // called through its proxy
+            if contract.behind_proxy {
+                continue;
+            }
+
             for abi in contract.abi.clone() {
                 self.add_abi(&abi, contract.deployed_address, &mut artifacts);
             }
@@ -331,8 +373,134 @@ where
         self.state.metadata_map_mut().insert(ABIMap::new());
         self.setup_default_callers();
         self.setup_contract_callers();
-        self.initialize_contract(loader);
-        self.initialize_corpus(loader)
+        self.executor.host.known_contracts = loader.contracts.clone();
+        match self.deployment_script.clone() {
+            Some(script) => {
+                let names = script.arrange(loader, self.executor.deployer, self.state);
+                self.initialize_contract(loader);
+                self.run_deployment_script(&script, &names);
+            }
+            None => self.initialize_contract(loader),
+        }
+        let artifacts = self.initialize_corpus(loader);
+        if self.invariant_targets {
+            self.retain_invariant_targets(loader);
+        }
+        artifacts
+    }
+
+    /// Drop the transactions to contracts and functions that the invariant
+    /// tests do not target
+    pub fn retain_invariant_targets(&mut self, loader: &ContractLoader) {
+        let mut targets = InvariantTargets::default();
+        for contract in &loader.contracts {
+            let has = |selector: [u8; 4]| contract.abi.iter().any(|abi| abi.function == selector);
+            // Foundry invariant tests inherit the hint functions from `StdInvariant`
+            let is_test = contract.abi.iter().any(is_foundry_invariant)
+                && [
+                    TARGET_CONTRACTS_SELECTOR,
+                    TARGET_SELECTORS_SELECTOR,
+                    EXCLUDE_CONTRACTS_SELECTOR,
+                ]
+                .into_iter()
+                .any(has);
+            if !is_test {
+                continue;
+            }
+            targets.tests.insert(contract.deployed_address);
+            let mut hint = |selector: [u8; 4]| {
+                has(selector)
+                    .then(|| {
+                        self.executor.host.probe_static_call(
+                            contract.deployed_address,
+                            Bytes::from(selector.to_vec()),
+                            self.state,
+                        )
+                    })
+                    .flatten()
+            };
+            let target_contracts = hint(TARGET_CONTRACTS_SELECTOR);
+            let target_selectors = hint(TARGET_SELECTORS_SELECTOR);
+            let exclude_contracts = hint(EXCLUDE_CONTRACTS_SELECTOR);
+            targets.add_hints(target_contracts, target_selectors, exclude_contracts);
+        }
+        if targets.tests.is_empty() {
+            return;
+        }
+
+        let mut next = self.state.corpus().first();
+        while let Some(id) = next {
+            next = self.state.corpus().next(id);
+            let input = self
+                .state
+                .corpus()
+                .get(id)
+                .expect("failed to get testcase")
+                .borrow()
+                .input()
+                .clone()
+                .expect("testcase without input");
+            let selector = input.data.as_ref().map_or([0; 4], |abi| abi.function);
+            if !targets.is_target(input.contract, selector) {
+                debug!(
+                    "Not fuzzing {:?} of {:?}, not targeted by the invariant tests",
+                    hex::encode(selector),
+                    input.contract
+                );
+                let testcase = self
+                    .state
+                    .corpus_mut()
+                    .remove(id)
+                    .expect("failed to remove testcase");
+                self.scheduler
+                    .on_remove(self.state, id, &Some(testcase))
+                    .expect("failed to call scheduler on_remove");
+            }
+        }
+    }
+
+    /// Set the balances and make the setup calls of the deployment script
+    pub fn run_deployment_script(
+        &mut self,
+        script: &DeploymentScript,
+        names: &HashMap<String, EVMAddress>,
+    ) {
+        for balance in &script.balances {
+            let address = resolve_address(&balance.address, names);
+            self.executor
+                .host
+                .evmstate
+                .set_balance(address, parse_amount(&balance.amount));
+        }
+        for call in &script.calls {
+            let from = match &call.from {
+                Some(from) => resolve_address(from, names),
+                None => self.executor.deployer,
+            };
+            let to = resolve_address(&call.to, names);
+            let value = call
+                .value
+                .as_ref()
+                .map(|v| parse_amount(v))
+                .unwrap_or_default();
+            info!("Setup call: {} on {}", call.function, call.to);
+            let vm_state = self.executor.host.evmstate.clone();
+            let res = self.executor.fast_call(
+                to,
+                Bytes::from(call.calldata(names)),
+                &vm_state,
+                self.state,
+                value,
+                from,
+            );
+            if !matches!(res.ret, InstructionResult::Return | InstructionResult::Stop) {
+                panic!(
+                    "Setup call {} on {} failed: {:?}",
+                    call.function, call.to, res.ret
+                );
+            }
+            self.executor.host.evmstate = res.new_state;
+        }
     }
 
     pub fn initialize_contract(&mut self, loader: &mut ContractLoader) {
@@ -462,11 +630,15 @@ where
                     .insert(contract.deployed_address, build_artifact.clone());
             }
 
+            self.executor
+                .host
+                .classify_contract(contract.deployed_address, self.state);
+
             #[cfg(feature = "flashloan_v2")]
             {
                 handle_contract_insertion!(
                     self.state,
-                    self.executor.host,
+                    &mut self.executor.host,
                     contract.deployed_address,
                     contract.abi.clone()
                 );
@@ -482,6 +654,11 @@ where
                 continue;
             }
 
+            // called through its proxy
+            if contract.behind_proxy {
+                continue;
+            }
+
             for abi in contract.abi.clone() {
                 self.add_abi(&abi, contract.deployed_address, &mut artifacts);
             }
@@ -21,7 +21,9 @@ use revm_interpreter::{
     BytecodeLocked, CallContext, CallInputs, CallScheme, Contract, CreateInputs, Gas, Host,
     InstructionResult, Interpreter, OpCode, SelfDestructResult,
 };
-use revm_primitives::{Bytecode, Env, LatestSpec, Spec, B256};
+use revm_primitives::{
+    create2_address, create_address, keccak256, Bytecode, CreateScheme, Env, LatestSpec, Spec, B256,
+};
 use std::cell::RefCell;
 use std::collections::hash_map::DefaultHasher;
 use std::collections::{HashMap, HashSet};
@@ -44,9 +46,13 @@ use crate::generic_vm::vm_state::VMStateT;
 use crate::input::VMInputT;
 
 use crate::evm::abi::{get_abi_type_boxed, register_abi_instance};
-use crate::evm::contract_utils::extract_sig_from_contract;
+use crate::evm::blaz::find_similar_contract;
+use crate::evm::chain_profile::ChainProfile;
+use crate::evm::contract_tags::{classify_code, probes, ContractTag, ContractTags};
+use crate::evm::contract_utils::{extract_sig_from_contract, ABIConfig, ContractInfo};
 use crate::evm::corpus_initializer::ABIMap;
 use crate::evm::onchain::abi_decompiler::fetch_abi_heimdall;
+use crate::evm::oracles::typed_bug::{TypedBug, TYPED_BUG_TOPIC};
 use crate::handle_contract_insertion;
 use crate::state::{HasCaller, HasCurrentInputIdx, HasHashToAddress, HasItyState};
 use crate::state_input::StagedVMState;
@@ -94,11 +100,46 @@ pub static mut CALL_UNTIL: u32 = u32::MAX;
 /// Shall we dump the contract calls
 pub static mut WRITE_RELATIONSHIPS: bool = false;
 
+/// Report `assert` failures and `AssertionFailed` events, as Echidna's
+/// assertion mode
+pub static mut ASSERTION_MODE: bool = false;
+
 const SCRIBBLE_EVENT_HEX: [u8; 32] = [
     0xb4, 0x26, 0x04, 0xcb, 0x10, 0x5a, 0x16, 0xc8, 0xf6, 0xdb, 0x8a, 0x41, 0xe6, 0xb0, 0x0c, 0x0c,
     0x1b, 0x48, 0x26, 0x46, 0x5e, 0x8b, 0xc5, 0x04, 0xb3, 0xeb, 0x3e, 0x88, 0xb3, 0xe6, 0xa4, 0xa0,
 ];
 
+/// Topics of `AssertionFailed()` and `AssertionFailed(uint256)`,
+/// `AssertionFailed(string)` is reported by the typed bug oracle as Scribble
+/// emits it
+const ASSERTION_FAILED_TOPICS: [&str; 2] = [
+    "f92d4a94d1d5014696dcfc65a0a061af97608eebd7fea0519ff4fdbca71bae9f",
+    "f7889b86ffab17fff0fcdf4cd268e14d338480cb7058f6fdfed3975f6524a6cf",
+];
+
+/// Address of a contract created while fuzzing, `nonces` holding the CREATE
+/// nonces of the creators, which start at 1 as they are contracts (EIP-161)
+fn created_address(nonces: &mut HashMap<EVMAddress, u64>, inputs: &CreateInputs) -> EVMAddress {
+    match inputs.scheme {
+        CreateScheme::Create => {
+            let nonce = nonces.entry(inputs.caller).or_insert(1);
+            *nonce += 1;
+            create_address(inputs.caller, *nonce - 1)
+        }
+        CreateScheme::Create2 { salt } => {
+            create2_address(inputs.caller, keccak256(&inputs.init_code), salt)
+        }
+    }
+}
+
+/// Whether a revert output is `Panic(0x01)`, raised by a failing `assert`
+pub fn is_assert_panic(output: &[u8]) -> bool {
+    output.len() == 36
+        && output[..4] == [0x4e, 0x48, 0x7b, 0x71]
+        && output[4..35].iter().all(|byte| *byte == 0)
+        && output[35] == 1
+}
+
 /// Check if address is precompile by having assumption
 /// that precompiles are in range of 1 to N.
 #[inline(always)]
@@ -145,7 +186,7 @@ where
     pub access_pattern: Rc<RefCell<AccessPattern>>,
 
     pub bug_hit: bool,
-    pub current_typed_bug: Vec<(String, (EVMAddress, usize))>,
+    pub current_typed_bug: Vec<(TypedBug, (EVMAddress, usize))>,
     pub call_count: u32,
 
     #[cfg(feature = "print_logs")]
@@ -156,6 +197,8 @@ where
     pub current_self_destructs: Vec<(EVMAddress, usize)>,
     // arbitrary calls
     pub current_arbitrary_calls: Vec<(EVMAddress, EVMAddress, usize)>,
+    // assertion failures in assertion mode
+    pub current_assertion_failures: Vec<(String, (EVMAddress, usize))>,
     // relations file handle
     relations_file: std::fs::File,
     // Filter duplicate relations
@@ -168,6 +211,8 @@ where
     pub spec_id: SpecId,
     /// Precompiles
     pub precompiles: Precompiles,
+    /// Semantics of the chain, e.g., its system contracts
+    pub chain_profile: ChainProfile,
 
     /// All SSTORE PCs that are for mapping (i.e., writing to multiple storage slots)
     pub mapping_sstore_pcs: HashSet<(EVMAddress, usize)>,
@@ -177,6 +222,19 @@ where
     pub leak_ctx: Vec<SinglePostExecution>,
 
     pub jumpi_trace: usize,
+
+    /// Contracts of the target, used to find the ABI of created contracts
+    pub known_contracts: Vec<ContractInfo>,
+    /// Known contract most similar to each init code hash, as the lookup is
+    /// too slow to be repeated for each created contract
+    similar_contracts: HashMap<B256, Option<usize>>,
+    /// Hash of the runtime code of the contracts created while fuzzing. The
+    /// code is shared by all states, so a state creating a contract with
+    /// another code at the same address must fail rather than replace it.
+    created_code: HashMap<EVMAddress, B256>,
+
+    /// Slots read from the given address, recorded while probing a static call
+    pub read_slots: Option<(EVMAddress, Vec<EVMU256>)>,
 }
 
 impl<VS, I, S, SC> Debug for FuzzHost<VS, I, S, SC>
@@ -240,6 +298,7 @@ where
             setcode_data: self.setcode_data.clone(),
             current_self_destructs: self.current_self_destructs.clone(),
             current_arbitrary_calls: self.current_arbitrary_calls.clone(),
+            current_assertion_failures: self.current_assertion_failures.clone(),
             relations_file: self.relations_file.try_clone().unwrap(),
             relations_hash: self.relations_hash.clone(),
             current_typed_bug: self.current_typed_bug.clone(),
@@ -247,10 +306,15 @@ where
             work_dir: self.work_dir.clone(),
             spec_id: self.spec_id,
             precompiles: Precompiles::default(),
+            chain_profile: self.chain_profile.clone(),
             leak_ctx: self.leak_ctx.clone(),
             mapping_sstore_pcs: self.mapping_sstore_pcs.clone(),
             mapping_sstore_pcs_to_slot: self.mapping_sstore_pcs_to_slot.clone(),
             jumpi_trace: self.jumpi_trace,
+            known_contracts: self.known_contracts.clone(),
+            similar_contracts: self.similar_contracts.clone(),
+            created_code: self.created_code.clone(),
+            read_slots: None,
         }
     }
 }
@@ -307,6 +371,7 @@ where
             setcode_data: HashMap::new(),
             current_self_destructs: Default::default(),
             current_arbitrary_calls: Default::default(),
+            current_assertion_failures: Default::default(),
             relations_file: std::fs::File::create(format!("{}/relations.log", workdir)).unwrap(),
             relations_hash: HashSet::new(),
             current_typed_bug: Default::default(),
@@ -314,10 +379,15 @@ where
             work_dir: workdir,
             spec_id: SpecId::LATEST,
             precompiles: Default::default(),
+            chain_profile: Default::default(),
             leak_ctx: vec![],
             mapping_sstore_pcs: Default::default(),
             mapping_sstore_pcs_to_slot: Default::default(),
             jumpi_trace: 37,
+            known_contracts: vec![],
+            similar_contracts: HashMap::new(),
+            created_code: HashMap::new(),
+            read_slots: None,
         }
     }
 
@@ -471,33 +541,192 @@ where
         );
     }
 
+    /// ABI of a contract created by CREATE or CREATE2, taken from the known
+    /// contract with the most similar bytecode, or else from the function
+    /// signatures we know of (decompiling it if we know too few of them)
+    fn created_contract_abi(
+        &mut self,
+        init_code: &Bytes,
+        runtime_code: &Bytes,
+        state: &S,
+    ) -> Vec<ABIConfig> {
+        let known_contracts = &self.known_contracts;
+        let similar = *self
+            .similar_contracts
+            .entry(keccak256(init_code))
+            .or_insert_with(|| {
+                find_similar_contract(init_code, known_contracts).and_then(|contract| {
+                    known_contracts
+                        .iter()
+                        .position(|known| std::ptr::eq(known, contract))
+                })
+            });
+        if let Some(contract) = similar.map(|idx| &self.known_contracts[idx]) {
+            debug!("Created contract is an instance of {}", contract.name);
+            return contract.abi.clone();
+        }
+
+        let contract_code_str = hex::encode(runtime_code);
+        let sigs = extract_sig_from_contract(&contract_code_str);
+        let mut unknown_sigs: usize = 0;
+        let mut parsed_abi = vec![];
+        for sig in &sigs {
+            if let Some(abi) = state.metadata_map().get::<ABIMap>().unwrap().get(sig) {
+                parsed_abi.push(abi.clone());
+            } else {
+                unknown_sigs += 1;
+            }
+        }
+
+        // decompiling is too slow to be done while fuzzing
+        if unsafe { IN_DEPLOY } && unknown_sigs >= sigs.len() / 30 {
+            debug!("Too many unknown function signature for newly created contract, we are going to decompile this contract using Heimdall");
+            parsed_abi = fetch_abi_heimdall(contract_code_str)
+                .iter()
+                .map(|abi| {
+                    if let Some(known_abi) = state
+                        .metadata_map()
+                        .get::<ABIMap>()
+                        .unwrap()
+                        .get(&abi.function)
+                    {
+                        known_abi
+                    } else {
+                        abi
+                    }
+                })
+                .cloned()
+                .collect_vec();
+        }
+        parsed_abi
+    }
+
+    fn report_typed_bug(&mut self, bug: TypedBug, address: EVMAddress) {
+        if unsafe { PANIC_ON_BUG } {
+            panic!("target bug found: {}", bug.describe());
+        }
+        self.current_typed_bug.push((bug, (address, self._pc)));
+    }
+
+    /// Output of a static call to `address`, None if it failed.
+    /// Middlewares are not run and the state, coverage and traces are left untouched.
+    pub fn probe_static_call(
+        &mut self,
+        address: EVMAddress,
+        data: Bytes,
+        state: &mut S,
+    ) -> Option<Vec<u8>> {
+        let code = self.code.get(&address)?.clone();
+        let call = Contract::new_with_context_analyzed(
+            data,
+            code,
+            &CallContext {
+                address,
+                caller: Default::default(),
+                code_address: address,
+                apparent_value: Default::default(),
+                scheme: CallScheme::StaticCall,
+            },
+        );
+        let mut interp = Interpreter::new_with_memory_limit(call, 1e10 as u64, true, MEM_LIMIT);
+
+        // the probe is not part of the execution, so it neither changes the
+        // state nor credits coverage and bugs to it
+        let evmstate = self.evmstate.clone();
+        let middlewares_enabled = self.middlewares_enabled;
+        let is_fast_call_static = unsafe { IS_FAST_CALL_STATIC };
+        let (jumpi_trace, coverage_changed, jmp_coverage_changed, call_count, pc, bug_hit) = (
+            self.jumpi_trace,
+            self.coverage_changed,
+            self.jmp_coverage_changed,
+            self.call_count,
+            self._pc,
+            self.bug_hit,
+        );
+        let (typed_bugs, assertion_failures) = (
+            self.current_typed_bug.len(),
+            self.current_assertion_failures.len(),
+        );
+        self.middlewares_enabled = false;
+        unsafe {
+            IS_FAST_CALL_STATIC = true;
+        }
+        let ret = self.run_inspect(&mut interp, state);
+        unsafe {
+            IS_FAST_CALL_STATIC = is_fast_call_static;
+        }
+        self.middlewares_enabled = middlewares_enabled;
+        self.evmstate = evmstate;
+        self.jumpi_trace = jumpi_trace;
+        self.coverage_changed = coverage_changed;
+        self.jmp_coverage_changed = jmp_coverage_changed;
+        self.call_count = call_count;
+        self._pc = pc;
+        self.bug_hit = bug_hit;
+        self.current_typed_bug.truncate(typed_bugs);
+        self.current_assertion_failures.truncate(assertion_failures);
+
+        if ret == InstructionResult::Return || ret == InstructionResult::Stop {
+            Some(interp.return_value().to_vec())
+        } else {
+            None
+        }
+    }
+
+    /// Storage slots of `address` read (in order) by a static call to it.
     pub fn find_static_call_read_slot(
-        &self,
-        _address: EVMAddress,
-        _data: Bytes,
-        _state: &mut S,
+        &mut self,
+        address: EVMAddress,
+        data: Bytes,
+        state: &mut S,
     ) -> Vec<EVMU256> {
-        vec![]
-        // let call = Contract::new_with_context_not_cloned::<LatestSpec>(
-        //     data,
-        //     self.code.get(&address).expect("no code").clone(),
-        //     &CallContext {
-        //         address,
-        //         caller: Default::default(),
-        //         code_address: address,
-        //         apparent_value: Default::default(),
-        //         scheme: CallScheme::StaticCall,
-        //     },
-        // );
-        // let mut interp = Interpreter::new::<LatestSpec>(call, 1e10 as u64);
-        // let (ret, slots) =
-        //     interp.locate_slot::<FuzzHost<VS, I, S>, LatestSpec, S>(&mut self.clone(), state);
-        // if ret != Return::Revert {
-        //     slots
-        // } else {
-        //     vec![]
-        // }
+        self.read_slots = Some((address, vec![]));
+        let ret = self.probe_static_call(address, data, state);
+        let (_, slots) = self.read_slots.take().unwrap();
+        match ret {
+            Some(_) => slots,
+            None => vec![],
+        }
+    }
+
+    /// Tag the contract at `address` with the standards it implements and
+    /// record them in [`ContractTags`]
+    pub fn classify_contract(
+        &mut self,
+        address: EVMAddress,
+        state: &mut S,
+    ) -> HashSet<ContractTag> {
+        let code = match self.code.get(&address) {
+            Some(code) => code.clone(),
+            None => return HashSet::new(),
+        };
+        let mut tags = classify_code(code.bytecode());
+        for (tag, calls, check) in probes() {
+            if tags.contains(&tag) {
+                continue;
+            }
+            let outputs = calls
+                .into_iter()
+                .map(|data| self.probe_static_call(address, data, state))
+                .collect::<Option<Vec<_>>>();
+            if outputs.map_or(false, |outputs| check(&outputs)) {
+                tags.insert(tag);
+            }
+        }
+        debug!("contract {:?} tagged {:?}", address, tags);
+
+        if !state.has_metadata::<ContractTags>() {
+            state.metadata_map_mut().insert(ContractTags::default());
+        }
+        state
+            .metadata_map_mut()
+            .get_mut::<ContractTags>()
+            .unwrap()
+            .tags
+            .insert(address, tags.clone());
+        tags
     }
+
     pub fn write_relations(&mut self, caller: EVMAddress, target: EVMAddress, funtion_hash: Bytes) {
         if funtion_hash.len() < 0x4 {
             return;
@@ -678,7 +907,7 @@ where
                             &input.context,
                         ),
                         1e10 as u64,
-                        false,
+                        input.is_static,
                         MEM_LIMIT,
                     );
 
@@ -720,7 +949,7 @@ where
                     &input.context,
                 ),
                 input.gas_limit,
-                false,
+                input.is_static,
                 MEM_LIMIT,
             );
             let ret = self.run_inspect(&mut interp, state);
@@ -794,7 +1023,7 @@ macro_rules! invoke_middlewares {
                     $host.set_code(address.clone(), code.clone(), $state);
                 }
             }
-            
+
             if let Some(m) = $host.flashloan_middleware.clone() {
                 let mut middleware = m.deref().borrow_mut();
                 middleware.$invoke($interp, $host, $state $(, $arg)*);
@@ -1111,6 +1340,11 @@ where
     }
 
     fn sload(&mut self, address: EVMAddress, index: EVMU256) -> Option<(EVMU256, bool)> {
+        if let Some((target, slots)) = self.read_slots.as_mut() {
+            if *target == address {
+                slots.push(index);
+            }
+        }
         if let Some(account) = self.evmstate.get_mut(&address) {
             if let Some(slot) = account.get(&index) {
                 return Some((*slot, true));
@@ -1147,6 +1381,15 @@ where
     }
 
     fn log(&mut self, _address: EVMAddress, _topics: Vec<B256>, _data: Bytes) {
+        if unsafe { ASSERTION_MODE } {
+            if let Some(topic) = _topics.first() {
+                if ASSERTION_FAILED_TOPICS.contains(&hex::encode(topic.0).as_str()) {
+                    self.current_assertion_failures
+                        .push(("AssertionFailed".to_string(), (_address, self._pc)));
+                }
+            }
+        }
+
         // flag check
         if _topics.len() == 1 {
             let current_flag = _topics.last().unwrap().0;
@@ -1164,13 +1407,14 @@ where
                 || current_flag == SCRIBBLE_EVENT_HEX
             {
                 let data_string = String::from_utf8(_data[64..].to_vec()).unwrap();
-                if unsafe { PANIC_ON_BUG } {
-                    panic!("target bug found: {}", data_string);
+                self.report_typed_bug(
+                    TypedBug::new(data_string.trim_end_matches('\u{0}').to_string()),
+                    _address,
+                );
+            } else if current_flag == TYPED_BUG_TOPIC {
+                if let Some(bug) = TypedBug::decode(&_data) {
+                    self.report_typed_bug(bug, _address);
                 }
-                self.current_typed_bug.push((
-                    data_string.trim_end_matches('\u{0}').to_string(),
-                    (_address, self._pc),
-                ));
             }
         }
 
@@ -1205,118 +1449,123 @@ where
         inputs: &mut CreateInputs,
         state: &mut S,
     ) -> (InstructionResult, Option<EVMAddress>, Gas, Bytes) {
-        if unsafe { IN_DEPLOY } {
+        let r_addr = if unsafe { IN_DEPLOY } {
             // todo: use nonce + hash instead
-            let r_addr = generate_random_address(state);
-            let mut interp = Interpreter::new_with_memory_limit(
-                Contract::new_with_context(
-                    Bytes::new(),
-                    Bytecode::new_raw(inputs.init_code.clone()),
-                    &CallContext {
-                        address: r_addr,
-                        caller: inputs.caller,
-                        code_address: r_addr,
-                        apparent_value: inputs.value,
-                        scheme: CallScheme::Call,
-                    },
-                ),
-                1e10 as u64,
-                false,
-                MEM_LIMIT,
+            generate_random_address(state)
+        } else {
+            // contracts created while fuzzing need stable addresses so that
+            // transactions to them can be replayed
+            created_address(&mut self.evmstate.nonces, inputs)
+        };
+        // the contracts created in this state are marked by their nonce, as
+        // the code of the host is shared by all states
+        if self.evmstate.nonces.contains_key(&r_addr) {
+            return (
+                InstructionResult::CreateCollision,
+                None,
+                Gas::new(0),
+                Bytes::new(),
             );
-            let ret = self.run_inspect(&mut interp, state);
-            if ret == InstructionResult::Continue {
-                let runtime_code = interp.return_value();
-                self.set_code(r_addr, Bytecode::new_raw(runtime_code.clone()), state);
+        }
+        let is_new = !self.code.contains_key(&r_addr);
+        // the address holds a contract not created while fuzzing
+        if !is_new && !self.created_code.contains_key(&r_addr) {
+            return (
+                InstructionResult::CreateCollision,
+                None,
+                Gas::new(0),
+                Bytes::new(),
+            );
+        }
+        let mut interp = Interpreter::new_with_memory_limit(
+            Contract::new_with_context(
+                Bytes::new(),
+                Bytecode::new_raw(inputs.init_code.clone()),
+                &CallContext {
+                    address: r_addr,
+                    caller: inputs.caller,
+                    code_address: r_addr,
+                    apparent_value: inputs.value,
+                    scheme: CallScheme::Call,
+                },
+            ),
+            1e10 as u64,
+            false,
+            MEM_LIMIT,
+        );
+        let ret = self.run_inspect(&mut interp, state);
+        if ret == InstructionResult::Continue {
+            let runtime_code = interp.return_value();
+            let code_hash = keccak256(&runtime_code);
+            // another state created a contract with another code there
+            if *self.created_code.entry(r_addr).or_insert(code_hash) != code_hash {
+                return (
+                    InstructionResult::CreateCollision,
+                    None,
+                    Gas::new(0),
+                    Bytes::new(),
+                );
+            }
+            // contracts start at nonce 1 (EIP-161)
+            self.evmstate.nonces.insert(r_addr, 1);
+            self.set_code(r_addr, Bytecode::new_raw(runtime_code.clone()), state);
+            if is_new {
+                // now we build & insert abi
+                let parsed_abi = self.created_contract_abi(&inputs.init_code, &runtime_code, state);
+                state.add_address(&r_addr);
+                self.classify_contract(r_addr, state);
+                // notify flashloan and blacklisting flashloan addresses
+                #[cfg(feature = "flashloan_v2")]
                 {
-                    // now we build & insert abi
-                    let contract_code_str = hex::encode(runtime_code.clone());
-                    let sigs = extract_sig_from_contract(&contract_code_str);
-                    let mut unknown_sigs: usize = 0;
-                    let mut parsed_abi = vec![];
-                    for sig in &sigs {
-                        if let Some(abi) = state.metadata_map().get::<ABIMap>().unwrap().get(sig) {
-                            parsed_abi.push(abi.clone());
-                        } else {
-                            unknown_sigs += 1;
-                        }
-                    }
-
-                    if unknown_sigs >= sigs.len() / 30 {
-                        debug!("Too many unknown function signature for newly created contract, we are going to decompile this contract using Heimdall");
-                        let abis = fetch_abi_heimdall(contract_code_str)
-                            .iter()
-                            .map(|abi| {
-                                if let Some(known_abi) = state
-                                    .metadata_map()
-                                    .get::<ABIMap>()
-                                    .unwrap()
-                                    .get(&abi.function)
-                                {
-                                    known_abi
-                                } else {
-                                    abi
-                                }
-                            })
-                            .cloned()
-                            .collect_vec();
-                        parsed_abi = abis;
-                    }
-                    // notify flashloan and blacklisting flashloan addresses
-                    #[cfg(feature = "flashloan_v2")]
-                    {
-                        handle_contract_insertion!(state, self, r_addr, parsed_abi);
-                    }
+                    handle_contract_insertion!(state, self, r_addr, parsed_abi);
+                }
 
-                    parsed_abi
-                        .iter()
-                        .filter(|v| !v.is_constructor)
-                        .for_each(|abi| {
-                            #[cfg(not(feature = "fuzz_static"))]
-                            if abi.is_static {
-                                return;
-                            }
+                parsed_abi
+                    .iter()
+                    .filter(|v| !v.is_constructor)
+                    .for_each(|abi| {
+                        #[cfg(not(feature = "fuzz_static"))]
+                        if abi.is_static {
+                            return;
+                        }
 
-                            let mut abi_instance = get_abi_type_boxed(&abi.abi);
-                            abi_instance.set_func_with_signature(
-                                abi.function,
-                                &abi.function_name,
-                                &abi.abi,
-                            );
-                            register_abi_instance(r_addr, abi_instance.clone(), state);
-
-                            let input = EVMInput {
-                                caller: state.get_rand_caller(),
-                                contract: r_addr,
-                                data: Some(abi_instance),
-                                sstate: StagedVMState::new_uninitialized(),
-                                sstate_idx: 0,
-                                txn_value: if abi.is_payable {
-                                    Some(EVMU256::ZERO)
-                                } else {
-                                    None
-                                },
-                                step: false,
-
-                                env: Default::default(),
-                                access_pattern: Rc::new(RefCell::new(AccessPattern::new())),
-                                #[cfg(feature = "flashloan_v2")]
-                                liquidation_percent: 0,
-                                #[cfg(feature = "flashloan_v2")]
-                                input_type: EVMInputTy::ABI,
-                                direct_data: Default::default(),
-                                randomness: vec![0],
-                                repeat: 1,
-                            };
-                            add_corpus(self, state, &input);
-                        });
-                }
-                (Continue, Some(r_addr), Gas::new(0), runtime_code)
-            } else {
-                (ret, Some(r_addr), Gas::new(0), Bytes::new())
+                        let mut abi_instance = get_abi_type_boxed(&abi.abi);
+                        abi_instance.set_func_with_signature(
+                            abi.function,
+                            &abi.function_name,
+                            &abi.abi,
+                        );
+                        register_abi_instance(r_addr, abi_instance.clone(), state);
+
+                        let input = EVMInput {
+                            caller: state.get_rand_caller(),
+                            contract: r_addr,
+                            data: Some(abi_instance),
+                            sstate: StagedVMState::new_uninitialized(),
+                            sstate_idx: 0,
+                            txn_value: if abi.is_payable {
+                                Some(EVMU256::ZERO)
+                            } else {
+                                None
+                            },
+                            step: false,
+
+                            env: Default::default(),
+                            access_pattern: Rc::new(RefCell::new(AccessPattern::new())),
+                            #[cfg(feature = "flashloan_v2")]
+                            liquidation_percent: 0,
+                            #[cfg(feature = "flashloan_v2")]
+                            input_type: EVMInputTy::ABI,
+                            direct_data: Default::default(),
+                            randomness: vec![0],
+                            repeat: 1,
+                        };
+                        add_corpus(self, state, &input);
+                    });
             }
+            (Continue, Some(r_addr), Gas::new(0), runtime_code)
         } else {
-            (InstructionResult::Revert, None, Gas::new(0), Bytes::new())
+            (ret, Some(r_addr), Gas::new(0), Bytes::new())
         }
     }
 
@@ -1351,7 +1600,12 @@ where
             };
         }
 
-        let res = if is_precompile(input.contract, self.precompiles.len()) {
+        let system_call = self
+            .chain_profile
+            .system_call(input.contract, &input.input, &self.env);
+        let res = if let Some(out) = system_call {
+            (InstructionResult::Return, Gas::new(0), out)
+        } else if is_precompile(input.contract, self.precompiles.len()) {
             self.call_precompile(input, state)
         } else if unsafe { IS_FAST_CALL_STATIC || IS_FAST_CALL } {
             self.call_forbid_control_leak(input, state)
@@ -1359,6 +1613,22 @@ where
             self.call_allow_control_leak(input, interp, output_info, state)
         };
 
+        // the innermost failing `assert`, at the call to it, as the panic
+        // bubbles up the callers
+        if unsafe { ASSERTION_MODE }
+            && res.0 == Revert
+            && is_assert_panic(&res.2)
+            && !self
+                .current_assertion_failures
+                .iter()
+                .any(|(reason, _)| reason == "Panic(0x01)")
+        {
+            self.current_assertion_failures.push((
+                "Panic(0x01)".to_string(),
+                (input.contract, interp.program_counter()),
+            ));
+        }
+
         let ret_buffer = res.2.clone();
 
         unsafe {
@@ -1368,6 +1638,7 @@ where
                         interp,
                         self,
                         state,
+                        res.0,
                         &ret_buffer,
                     );
                 }
@@ -1376,3 +1647,117 @@ where
         res
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::evm::types::EVMFuzzState;
+    use crate::state::FuzzState;
+    use libafl::prelude::StdScheduler;
+    use std::str::FromStr;
+
+    fn inputs(caller: &str, scheme: CreateScheme, init_code: &str) -> CreateInputs {
+        CreateInputs {
+            caller: EVMAddress::from_str(caller).unwrap(),
+            scheme,
+            value: EVMU256::ZERO,
+            init_code: Bytes::from(hex::decode(init_code).unwrap()),
+            gas_limit: u64::MAX,
+        }
+    }
+
+    #[test]
+    fn test_created_address() {
+        let address = |address: &str| EVMAddress::from_str(address).unwrap();
+        let mut nonces = HashMap::new();
+
+        // contracts start creating at nonce 1
+        let create = inputs(
+            "0x6ac7ea33f8831ea9dcc53393aaa88b25a785dbf0",
+            CreateScheme::Create,
+            "",
+        );
+        assert_eq!(
+            created_address(&mut nonces, &create),
+            address("0x343c43a37d37dff08ae8c4a11544c718abb4fcf8")
+        );
+        assert_eq!(
+            created_address(&mut nonces, &create),
+            address("0xf778b86fa74e846c4f0a1fbd1335fe81c00a0c91")
+        );
+
+        // EIP-1014 examples
+        for (caller, salt, init_code, expected) in [
+            (
+                "0x0000000000000000000000000000000000000000",
+                0_u64,
+                "00",
+                "0x4d1a2e2bb4f88f0250f26ffff098b0b30b26bf38",
+            ),
+            (
+                "0xdeadbeef00000000000000000000000000000000",
+                0,
+                "00",
+                "0xb928f69bb1d91cd65274e3c79d8986362984fda3",
+            ),
+            (
+                "0x00000000000000000000000000000000deadbeef",
+                0xcafebabe,
+                "deadbeef",
+                "0x60f3f640a8508fc6a86d45df051962668e1e8ac7",
+            ),
+            (
+                "0x0000000000000000000000000000000000000000",
+                0,
+                "",
+                "0xe33c0c7f7df4809055c3eba6c09cfe4baf1bd9e0",
+            ),
+        ] {
+            let create2 = inputs(
+                caller,
+                CreateScheme::Create2 {
+                    salt: EVMU256::from(salt),
+                },
+                init_code,
+            );
+            assert_eq!(created_address(&mut nonces, &create2), address(expected));
+        }
+        // CREATE2 does not use the nonce
+        assert_eq!(nonces.len(), 1);
+    }
+
+    #[test]
+    fn test_create_collision() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut state: EVMFuzzState = FuzzState::new(0, None);
+        let mut host: FuzzHost<EVMState, EVMInput, EVMFuzzState, StdScheduler<EVMFuzzState>> =
+            FuzzHost::new(
+                StdScheduler::new(),
+                dir.path().to_str().unwrap().to_string(),
+            );
+        let mut create2 = inputs(
+            "0x0000000000000000000000000000000000000000",
+            CreateScheme::Create2 {
+                salt: EVMU256::ZERO,
+            },
+            "00",
+        );
+        // created by an earlier transaction on this state
+        let created = EVMAddress::from_str("0x4d1a2e2bb4f88f0250f26ffff098b0b30b26bf38").unwrap();
+        host.evmstate.nonces.insert(created, 1);
+
+        let (ret, address, _, _) = host.create(&mut create2, &mut state);
+        assert_eq!(ret, InstructionResult::CreateCollision);
+        assert_eq!(address, None);
+
+        // the code at the address was not created while fuzzing, other
+        // states rely on it
+        host.evmstate.nonces.clear();
+        let code = Bytecode::new_raw(Bytes::from(vec![0x60, 0x01]));
+        host.set_code(created, code, &mut state);
+        let (ret, address, _, _) = host.create(&mut create2, &mut state);
+        assert_eq!(ret, InstructionResult::CreateCollision);
+        assert_eq!(address, None);
+        assert_eq!(host.code[&created].bytecode()[..2], [0x60, 0x01]);
+    }
+}
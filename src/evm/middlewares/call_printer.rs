@@ -10,7 +10,7 @@ use crate::evm::srcmap::parser::{
 use crate::evm::types::{
     as_u64, convert_u256_to_h160, is_zero, EVMAddress, ProjectSourceMapTy, EVMU256,
 };
-use crate::evm::vm::IN_DEPLOY;
+use crate::evm::vm::{IN_DEPLOY, IS_FAST_CALL_STATIC};
 use crate::generic_vm::vm_state::VMStateT;
 use crate::input::VMInputT;
 use crate::state::{HasCaller, HasCurrentInputIdx, HasItyState};
@@ -20,7 +20,7 @@ use libafl::inputs::Input;
 use libafl::prelude::{HasCorpus, HasMetadata, State};
 use libafl::schedulers::Scheduler;
 use revm_interpreter::opcode::{INVALID, JUMPDEST, JUMPI, REVERT, STOP};
-use revm_interpreter::Interpreter;
+use revm_interpreter::{InstructionResult, Interpreter};
 use revm_primitives::Bytecode;
 use serde::{Deserialize, Serialize};
 use serde_json;
@@ -141,7 +141,7 @@ impl CallPrinter {
         json_file.write_all(json.as_bytes()).unwrap();
     }
 
-    fn translate_address(&self, a: EVMAddress) -> String {
+    pub fn translate_address(&self, a: EVMAddress) -> String {
         self.address_to_name
             .get(&a)
             .unwrap_or(&format!("{:?}", a))
@@ -169,6 +169,10 @@ where
         host: &mut FuzzHost<VS, I, S, SC>,
         state: &mut S,
     ) {
+        // the static calls of the oracles are not part of the transaction
+        if IS_FAST_CALL_STATIC {
+            return;
+        }
         if self.entry {
             self.entry = false;
             let code_address = interp.contract.address;
@@ -321,8 +325,12 @@ where
         interp: &mut Interpreter,
         host: &mut FuzzHost<VS, I, S, SC>,
         state: &mut S,
+        result: InstructionResult,
         by: &Bytes,
     ) {
+        if IS_FAST_CALL_STATIC {
+            return;
+        }
         self.offsets += 1;
         let l = self.results.data.len();
         self.results.data[l - self.offsets].1.results = hex::encode(by);
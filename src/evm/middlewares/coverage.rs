@@ -3,6 +3,7 @@ use crate::evm::bytecode_iterator::{all_bytecode, walk_bytecode};
 use crate::evm::host::FuzzHost;
 use crate::evm::input::{ConciseEVMInput, EVMInput, EVMInputT};
 use crate::evm::middlewares::middleware::{Middleware, MiddlewareType};
+use crate::evm::middlewares::source_coverage::SourceCoverage;
 use crate::evm::srcmap::parser::SourceMapAvailability::Available;
 use crate::evm::srcmap::parser::{
     decode_instructions, pretty_print_source_map, pretty_print_source_map_single,
@@ -233,6 +234,14 @@ impl Coverage {
     }
 
     pub fn record_instruction_coverage(&mut self) {
+        let report = self.coverage_report();
+        report.dump_file(self.work_dir.clone());
+        report.summarize();
+        SourceCoverage::new(self).dump_file(&self.work_dir);
+    }
+
+    /// Instruction and branch coverage of the contracts executed so far
+    pub fn coverage_report(&mut self) -> CoverageReport {
         let mut report = CoverageReport::new();
 
         /// Figure out covered and not covered instructions
@@ -295,8 +304,7 @@ impl Coverage {
 
         // cleanup, remove small contracts
         report.coverage.retain(|_, v| v.total_instructions > 10);
-        report.dump_file(self.work_dir.clone());
-        report.summarize();
+        report
     }
 }
 
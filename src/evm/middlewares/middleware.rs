@@ -18,7 +18,7 @@ use std::clone::Clone;
 use std::fmt::Debug;
 
 use crate::evm::types::{EVMAddress, EVMU256};
-use revm_interpreter::Interpreter;
+use revm_interpreter::{InstructionResult, Interpreter};
 use revm_primitives::Bytecode;
 use std::time::Duration;
 
@@ -34,6 +34,7 @@ pub enum MiddlewareType {
     Sha3TaintAnalysis,
     CallPrinter,
     Reentrancy,
+    SpotPrice,
 }
 
 #[derive(Clone, Debug, Hash, PartialEq, Eq, Serialize, Deserialize, Copy)]
@@ -105,6 +106,7 @@ where
         interp: &mut Interpreter,
         host: &mut FuzzHost<VS, I, S, SC>,
         state: &mut S,
+        result: InstructionResult,
         ret: &Bytes,
     ) {
     }
@@ -3,3 +3,5 @@ pub mod coverage;
 pub mod middleware;
 pub mod reentrancy;
 pub mod sha3_bypass;
+pub mod source_coverage;
+pub mod spot_price;
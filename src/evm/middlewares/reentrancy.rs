@@ -29,6 +29,45 @@ pub struct ReentrancyData {
     pub reads: HashMap<(EVMAddress, EVMU256), Vec<u32>>,
     pub need_writes: HashMap<(EVMAddress, EVMU256), Vec<u32>>,
     pub found: HashSet<(EVMAddress, EVMU256)>,
+    /// Slots read by view (static) calls while control is leaked, with the
+    /// depth and (code address, pc) of the read
+    pub view_reads: HashMap<(EVMAddress, EVMU256), Vec<(u32, (EVMAddress, usize))>>,
+    /// Read-only reentrancy: (contract, slot, read site, write site), where the
+    /// view call read the slot before an outer frame wrote it
+    pub found_read_only: HashSet<(
+        EVMAddress,
+        EVMU256,
+        (EVMAddress, usize),
+        (EVMAddress, usize),
+    )>,
+}
+
+impl ReentrancyData {
+    /// Records a read of `key` at `site` by a view call at `depth`
+    fn record_view_read(
+        &mut self,
+        key: (EVMAddress, EVMU256),
+        depth: u32,
+        site: (EVMAddress, usize),
+    ) {
+        let view_entry = self.view_reads.entry(key).or_default();
+        if !view_entry.contains(&(depth, site)) {
+            view_entry.push((depth, site));
+        }
+    }
+
+    /// Records a write of `key` at `site`, the view reads deeper than `depth`
+    /// having been made while this frame was leaking control
+    fn record_write(&mut self, key: (EVMAddress, EVMU256), depth: u32, site: (EVMAddress, usize)) {
+        if let Some(view_reads) = self.view_reads.get(&key) {
+            let found = view_reads
+                .iter()
+                .filter(|(read_depth, _)| *read_depth > depth)
+                .map(|(_, read_site)| (key.0, key.1, *read_site, site))
+                .collect::<Vec<_>>();
+            self.found_read_only.extend(found);
+        }
+    }
 }
 
 fn merge_sorted_vec_dedup(dst: &mut Vec<u32>, another_one: &Vec<u32>) {
@@ -78,6 +117,7 @@ fn merge_sorted_vec_dedup(dst: &mut Vec<u32>, another_one: &Vec<u32>) {
 }
 
 // Reentrancy: Read, Read, Write
+// Read-only reentrancy: Read (in a view call while control is leaked), Write (in an outer frame)
 impl<I, VS, S, SC> Middleware<VS, I, S, SC> for ReentrancyTracer
 where
     I: Input + VMInputT<VS, EVMAddress, EVMAddress, ConciseEVMInput> + EVMInputT + 'static,
@@ -103,6 +143,16 @@ where
                 let depth = host.evmstate.post_execution.len() as u32;
                 let slot_idx = interp.stack.peek(0).unwrap();
 
+                // the view call may return stale state if an outer frame writes the slot later
+                if depth > 0 && interp.is_static {
+                    let site = (interp.contract.code_address, interp.program_counter());
+                    host.evmstate.reentrancy_metadata.record_view_read(
+                        (interp.contract.address, slot_idx),
+                        depth,
+                        site,
+                    );
+                }
+
                 // set up reads
                 let entry = host
                     .evmstate
@@ -147,6 +197,12 @@ where
             0x55 => {
                 let depth = host.evmstate.post_execution.len() as u32;
                 let slot_idx = interp.stack.peek(0).unwrap();
+                let site = (interp.contract.code_address, interp.program_counter());
+                host.evmstate.reentrancy_metadata.record_write(
+                    (interp.contract.address, slot_idx),
+                    depth,
+                    site,
+                );
                 let write_entry = host
                     .evmstate
                     .reentrancy_metadata
@@ -183,6 +239,11 @@ where
         evm_state: &mut EVMState,
     ) {
         if !is_step {
+            // view reads only matter to the frames that were leaking control
+            // when they happened, which have all finished by now
+            if evm_state.post_execution.is_empty() {
+                evm_state.reentrancy_metadata.view_reads.clear();
+            }
             return;
         }
         // otherwise, we clean up the writes and reads with depth larger than current depth
@@ -207,4 +268,27 @@ mod test {
         merge_sorted_vec_dedup(&mut vec2, &vec1);
         assert_eq!(vec2, vec![1, 2, 3, 4, 5, 6, 7, 8, 10]);
     }
+
+    #[test]
+    fn test_read_only_reentrancy() {
+        let pool = EVMAddress::repeat_byte(0x11);
+        let oracle = EVMAddress::repeat_byte(0x22);
+        let slot = EVMU256::from(1);
+        let mut data = ReentrancyData::default();
+
+        // the pool leaks control at depth 1, the callback reads its reserves
+        // through a view call at depth 2 before the pool updates them
+        data.record_view_read((pool, slot), 2, (oracle, 10));
+        data.record_view_read((pool, slot), 2, (oracle, 10));
+        data.record_write((pool, EVMU256::from(2)), 1, (pool, 20));
+        assert!(data.found_read_only.is_empty());
+        data.record_write((pool, slot), 2, (pool, 20));
+        assert!(data.found_read_only.is_empty());
+        data.record_write((pool, slot), 1, (pool, 30));
+        assert_eq!(
+            data.found_read_only.into_iter().collect::<Vec<_>>(),
+            vec![(pool, slot, (oracle, 10), (pool, 30))]
+        );
+        assert_eq!(data.view_reads[&(pool, slot)].len(), 1);
+    }
 }
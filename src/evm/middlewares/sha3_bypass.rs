@@ -11,7 +11,7 @@ use libafl::inputs::Input;
 use libafl::prelude::{HasCorpus, HasMetadata, State};
 use libafl::schedulers::Scheduler;
 use revm_interpreter::opcode::JUMPI;
-use revm_interpreter::Interpreter;
+use revm_interpreter::{InstructionResult, Interpreter};
 use revm_primitives::Bytecode;
 use std::cell::RefCell;
 use std::collections::{HashMap, HashSet};
@@ -393,6 +393,7 @@ where
         interp: &mut Interpreter,
         host: &mut FuzzHost<VS, I, S, SC>,
         state: &mut S,
+        result: InstructionResult,
         by: &Bytes,
     ) {
         self.pop_ctx();
@@ -0,0 +1,481 @@
+//! Line, function and branch coverage of the source files, joined from the
+//! PC coverage of the contracts and their source maps, written as an LCOV file
+//! and a self-contained HTML report.
+use crate::evm::middlewares::coverage::Coverage;
+use crate::evm::srcmap::parser::read_source_file;
+use crate::evm::types::EVMAddress;
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FunctionCoverage {
+    pub name: String,
+    pub line_start: usize,
+    pub line_end: usize,
+    pub hit: bool,
+}
+
+/// Coverage of the two sides of a JUMPI
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BranchCoverage {
+    pub line: usize,
+    pub address: EVMAddress,
+    pub pc: usize,
+    pub jumped: bool,
+    pub fell_through: bool,
+}
+
+impl BranchCoverage {
+    fn hits(&self) -> usize {
+        self.jumped as usize + self.fell_through as usize
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct FileCoverage {
+    pub source: String,
+    /// Instrumented lines and whether they are hit
+    pub lines: BTreeMap<usize, bool>,
+    pub functions: Vec<FunctionCoverage>,
+    pub branches: Vec<BranchCoverage>,
+}
+
+impl FileCoverage {
+    fn lines_hit(&self) -> usize {
+        self.lines.values().filter(|hit| **hit).count()
+    }
+
+    fn functions_hit(&self) -> usize {
+        self.functions
+            .iter()
+            .filter(|function| function.hit)
+            .count()
+    }
+
+    fn branches_hit(&self) -> usize {
+        self.branches.iter().map(|branch| branch.hits()).sum()
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct SourceCoverage {
+    pub files: BTreeMap<String, FileCoverage>,
+}
+
+/// Source with the comments and string literals blanked out, keeping offsets
+fn strip_comments(source: &str) -> String {
+    let mut stripped = source.as_bytes().to_vec();
+    let mut i = 0;
+    while i < stripped.len() {
+        let end = match (stripped[i], stripped.get(i + 1).copied()) {
+            (b'/', Some(b'/')) => (i..stripped.len())
+                .find(|j| stripped[*j] == b'\n')
+                .unwrap_or(stripped.len()),
+            (b'/', Some(b'*')) => (i + 2..stripped.len())
+                .find(|j| stripped[*j - 1] == b'*' && stripped[*j] == b'/' && *j > i + 2)
+                .map_or(stripped.len(), |j| j + 1),
+            (quote @ (b'"' | b'\''), _) => (i + 1..stripped.len())
+                .find(|j| stripped[*j] == quote && stripped[*j - 1] != b'\\')
+                .map_or(stripped.len(), |j| j + 1),
+            _ => {
+                i += 1;
+                continue;
+            }
+        };
+        stripped[i..end]
+            .iter_mut()
+            .filter(|byte| **byte != b'\n')
+            .for_each(|byte| *byte = b' ');
+        i = end;
+    }
+    String::from_utf8(stripped).expect("stripped source is utf-8")
+}
+
+/// Functions, modifiers, constructors, fallbacks and receives with a body,
+/// as (name, first line, last line)
+fn scan_functions(source: &str) -> Vec<(String, usize, usize)> {
+    let code = strip_comments(source);
+    let is_ident = |c: char| c.is_ascii_alphanumeric() || c == '_' || c == '$';
+    let line_of = |pos: usize| code[..pos].matches('\n').count() + 1;
+    let mut functions = vec![];
+    for keyword in ["function", "modifier", "constructor", "fallback", "receive"] {
+        for (start, _) in code.match_indices(keyword) {
+            let rest = &code[start + keyword.len()..];
+            if code[..start].chars().last().map_or(false, is_ident)
+                || rest.chars().next().map_or(true, is_ident)
+            {
+                continue;
+            }
+            let name = match keyword {
+                "function" | "modifier" => rest
+                    .trim_start()
+                    .chars()
+                    .take_while(|c| is_ident(*c))
+                    .collect::<String>(),
+                _ => keyword.to_string(),
+            };
+            // function types have no name
+            if name.is_empty() {
+                continue;
+            }
+            let body = match rest.find(|c: char| c == '{' || c == ';') {
+                Some(pos) if rest.as_bytes()[pos] == b'{' => start + keyword.len() + pos,
+                _ => continue,
+            };
+            let mut depth = 0;
+            let end = code[body..].char_indices().find_map(|(pos, c)| {
+                match c {
+                    '{' => depth += 1,
+                    '}' => depth -= 1,
+                    _ => {}
+                }
+                (depth == 0).then_some(body + pos)
+            });
+            if let Some(end) = end {
+                functions.push((name, line_of(start), line_of(end)));
+            }
+        }
+    }
+    functions.sort_by_key(|(_, line_start, _)| *line_start);
+    functions
+}
+
+fn percent(hit: usize, total: usize) -> String {
+    if total == 0 {
+        "-".to_string()
+    } else {
+        format!("{:.2}%", (hit * 100) as f64 / total as f64)
+    }
+}
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+const HTML_STYLE: &str = "body{font-family:sans-serif;margin:2em}\
+table{border-collapse:collapse;margin-bottom:1em}\
+td,th{border:1px solid #ccc;padding:2px 8px;text-align:left}\
+pre{margin:0}.src td{border:none;padding:0 8px;font-family:monospace;white-space:pre}\
+.hit{background:#dfd}.miss{background:#fdd}.partial{background:#ffd}";
+
+impl SourceCoverage {
+    pub fn new(coverage: &Coverage) -> Self {
+        let mut files: BTreeMap<String, FileCoverage> = BTreeMap::new();
+        let mut unavailable = vec![];
+        let no_sources = vec![];
+        let no_pcs = Default::default();
+
+        for (address, all_pcs) in &coverage.total_instr_set {
+            let sources = coverage.sources.get(address).unwrap_or(&no_sources);
+            let covered = coverage.pc_coverage.get(address).unwrap_or(&no_pcs);
+            let mut line_of = |pc: usize| -> Option<(String, usize)> {
+                let info = coverage.pc_info.get(&(*address, pc))?;
+                if unavailable.contains(&info.file) {
+                    return None;
+                }
+                if !files.contains_key(&info.file) {
+                    match read_source_file(&info.file, sources) {
+                        Some(source) => {
+                            files.insert(
+                                info.file.clone(),
+                                FileCoverage {
+                                    source,
+                                    ..Default::default()
+                                },
+                            );
+                        }
+                        None => {
+                            unavailable.push(info.file.clone());
+                            return None;
+                        }
+                    }
+                }
+                Some((info.file.clone(), info.line_start))
+            };
+
+            let mut lines = vec![];
+            for pc in all_pcs {
+                if let Some((file, line)) = line_of(*pc) {
+                    lines.push((file, line, covered.contains(pc)));
+                }
+            }
+            let jumpis = coverage.total_jumpi_set.get(address).unwrap_or(&no_pcs);
+            let mut branches = vec![];
+            for pc in jumpis {
+                if let Some((file, line)) = line_of(*pc) {
+                    branches.push((file, line, *pc));
+                }
+            }
+
+            for (file, line, hit) in lines {
+                *files.get_mut(&file).unwrap().lines.entry(line).or_default() |= hit;
+            }
+            let taken = coverage.jumpi_coverage.get(address);
+            let was_taken = |pc: usize, condition: bool| {
+                taken.map_or(false, |taken| taken.contains(&(pc, condition)))
+            };
+            for (file, line, pc) in branches {
+                files.get_mut(&file).unwrap().branches.push(BranchCoverage {
+                    line,
+                    address: *address,
+                    pc,
+                    // the condition recorded is whether the JUMPI falls through
+                    jumped: was_taken(pc, false),
+                    fell_through: was_taken(pc, true),
+                });
+            }
+        }
+
+        for file in files.values_mut() {
+            file.branches.sort_by_key(|branch| (branch.line, branch.pc));
+            file.functions = scan_functions(&file.source)
+                .into_iter()
+                .filter_map(|(name, line_start, line_end)| {
+                    let mut lines = file.lines.range(line_start..=line_end).peekable();
+                    // not compiled into any of the contracts
+                    lines.peek()?;
+                    Some(FunctionCoverage {
+                        name,
+                        line_start,
+                        line_end,
+                        hit: lines.any(|(_, hit)| *hit),
+                    })
+                })
+                .collect();
+        }
+        Self { files }
+    }
+
+    pub fn to_lcov(&self) -> String {
+        let mut s = String::from("TN:\n");
+        for (name, file) in &self.files {
+            s.push_str(&format!("SF:{}\n", name));
+            for function in &file.functions {
+                s.push_str(&format!("FN:{},{}\n", function.line_start, function.name));
+            }
+            for function in &file.functions {
+                s.push_str(&format!(
+                    "FNDA:{},{}\n",
+                    function.hit as usize, function.name
+                ));
+            }
+            s.push_str(&format!("FNF:{}\n", file.functions.len()));
+            s.push_str(&format!("FNH:{}\n", file.functions_hit()));
+            for (block, branch) in file.branches.iter().enumerate() {
+                for (side, taken) in [branch.jumped, branch.fell_through].iter().enumerate() {
+                    let taken = match (branch.hits(), taken) {
+                        // the JUMPI is never reached
+                        (0, _) => "-".to_string(),
+                        (_, taken) => (*taken as usize).to_string(),
+                    };
+                    s.push_str(&format!(
+                        "BRDA:{},{},{},{}\n",
+                        branch.line, block, side, taken
+                    ));
+                }
+            }
+            s.push_str(&format!("BRF:{}\n", file.branches.len() * 2));
+            s.push_str(&format!("BRH:{}\n", file.branches_hit()));
+            for (line, hit) in &file.lines {
+                s.push_str(&format!("DA:{},{}\n", line, *hit as usize));
+            }
+            s.push_str(&format!("LF:{}\n", file.lines.len()));
+            s.push_str(&format!("LH:{}\n", file.lines_hit()));
+            s.push_str("end_of_record\n");
+        }
+        s
+    }
+
+    pub fn to_html(&self) -> String {
+        let mut s = format!(
+            "<!DOCTYPE html><html><head><meta charset=\"utf-8\"><title>Coverage</title>\
+             <style>{}</style></head><body><h1>Coverage</h1>",
+            HTML_STYLE
+        );
+        s.push_str(
+            "<table><tr><th>File</th><th>Lines</th><th>Functions</th><th>Branches</th></tr>",
+        );
+        for (idx, (name, file)) in self.files.iter().enumerate() {
+            s.push_str(&format!(
+                "<tr><td><a href=\"#file{}\">{}</a></td><td>{} ({}/{})</td><td>{} ({}/{})</td><td>{} ({}/{})</td></tr>",
+                idx,
+                escape_html(name),
+                percent(file.lines_hit(), file.lines.len()),
+                file.lines_hit(),
+                file.lines.len(),
+                percent(file.functions_hit(), file.functions.len()),
+                file.functions_hit(),
+                file.functions.len(),
+                percent(file.branches_hit(), file.branches.len() * 2),
+                file.branches_hit(),
+                file.branches.len() * 2,
+            ));
+        }
+        s.push_str("</table>");
+
+        for (idx, (name, file)) in self.files.iter().enumerate() {
+            s.push_str(&format!(
+                "<h2 id=\"file{}\">{}</h2>",
+                idx,
+                escape_html(name)
+            ));
+
+            s.push_str(
+                "<h3>Functions</h3><table><tr><th>Function</th><th>Lines</th><th>Hit</th></tr>",
+            );
+            for function in &file.functions {
+                s.push_str(&format!(
+                    "<tr class=\"{}\"><td>{}</td><td><a href=\"#file{}L{}\">{}-{}</a></td><td>{}</td></tr>",
+                    if function.hit { "hit" } else { "miss" },
+                    escape_html(&function.name),
+                    idx,
+                    function.line_start,
+                    function.line_start,
+                    function.line_end,
+                    if function.hit { "yes" } else { "no" },
+                ));
+            }
+            s.push_str("</table>");
+
+            let uncovered = file
+                .branches
+                .iter()
+                .filter(|branch| branch.hits() < 2)
+                .collect::<Vec<_>>();
+            if !uncovered.is_empty() {
+                s.push_str("<h3>Uncovered branches</h3><table><tr><th>Line</th><th>Contract</th><th>PC</th><th>Never</th></tr>");
+                for branch in uncovered {
+                    let never = match (branch.jumped, branch.fell_through) {
+                        (false, false) => "reached",
+                        (false, true) => "jumped",
+                        _ => "fell through",
+                    };
+                    s.push_str(&format!(
+                        "<tr><td><a href=\"#file{}L{}\">{}</a></td><td>{:?}</td><td>{}</td><td>{}</td></tr>",
+                        idx, branch.line, branch.line, branch.address, branch.pc, never
+                    ));
+                }
+                s.push_str("</table>");
+            }
+
+            let mut branches_by_line: HashMap<usize, (usize, usize)> = HashMap::new();
+            for branch in &file.branches {
+                let entry = branches_by_line.entry(branch.line).or_default();
+                entry.0 += branch.hits();
+                entry.1 += 2;
+            }
+            s.push_str("<h3>Source</h3><table class=\"src\">");
+            for (line_idx, line) in file.source.lines().enumerate() {
+                let line_number = line_idx + 1;
+                let class = match (
+                    file.lines.get(&line_number),
+                    branches_by_line.get(&line_number),
+                ) {
+                    (Some(true), Some((hit, total))) if hit < total => "partial",
+                    (Some(true), _) => "hit",
+                    (Some(false), _) => "miss",
+                    (None, _) => "",
+                };
+                s.push_str(&format!(
+                    "<tr id=\"file{}L{}\" class=\"{}\"><td>{}</td><td>{}</td></tr>",
+                    idx,
+                    line_number,
+                    class,
+                    line_number,
+                    escape_html(line)
+                ));
+            }
+            s.push_str("</table>");
+        }
+        s.push_str("</body></html>");
+        s
+    }
+
+    /// Overwrite `lcov.info` and `index.html` in `work_dir`
+    pub fn dump_file(&self, work_dir: &str) {
+        if self.files.is_empty() {
+            return;
+        }
+        fs::write(format!("{}/lcov.info", work_dir), self.to_lcov()).expect("failed to write lcov");
+        fs::write(format!("{}/index.html", work_dir), self.to_html())
+            .expect("failed to write coverage report");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::evm::srcmap::parser::SourceMapWithCode;
+    use std::collections::HashSet;
+
+    const SOURCE: &str = "contract C {
+    // function commented() {}
+    function f(uint x) public {
+        if (x > 1) {
+            g();
+        }
+    }
+    function g() internal {
+        string memory s = \"}\";
+    }
+    function h() external;
+}
+";
+
+    #[test]
+    fn test_scan_functions() {
+        assert_eq!(
+            scan_functions(SOURCE),
+            vec![("f".to_string(), 3, 7), ("g".to_string(), 8, 10)]
+        );
+    }
+
+    #[test]
+    fn test_source_coverage() {
+        let address = EVMAddress::from_slice(&[0x11; 20]);
+        let mut coverage = Coverage::new(HashMap::new(), HashMap::new(), "work_dir".to_string());
+        coverage
+            .sources
+            .insert(address, vec![("C.sol".to_string(), SOURCE.to_string())]);
+        // pc 0 on line 3, JUMPI at pc 1 on line 4, pc 2 on line 5, pc 3 on line 9
+        for (pc, line) in [(0, 3), (1, 4), (2, 5), (3, 9)] {
+            coverage.pc_info.insert(
+                (address, pc),
+                SourceMapWithCode {
+                    file: "C.sol".to_string(),
+                    line_start: line,
+                    line_end: line,
+                    code: "".to_string(),
+                },
+            );
+        }
+        coverage
+            .total_instr_set
+            .insert(address, HashSet::from([0, 1, 2, 3]));
+        coverage.total_jumpi_set.insert(address, HashSet::from([1]));
+        coverage.pc_coverage.insert(address, HashSet::from([0, 1]));
+        coverage
+            .jumpi_coverage
+            .insert(address, HashSet::from([(1, true)]));
+
+        let report = SourceCoverage::new(&coverage);
+        let file = &report.files["C.sol"];
+        assert_eq!(file.lines_hit(), 2);
+        assert_eq!(file.functions_hit(), 1);
+        assert_eq!(file.branches_hit(), 1);
+
+        let lcov = report.to_lcov();
+        assert!(lcov.contains("SF:C.sol\n"));
+        assert!(lcov.contains("FNDA:1,f\nFNDA:0,g\n"));
+        assert!(lcov.contains("BRDA:4,0,0,0\nBRDA:4,0,1,1\n"));
+        assert!(lcov.contains("DA:5,0\n"));
+        assert!(lcov.contains("LF:4\nLH:2\n"));
+
+        let html = report.to_html();
+        assert!(html.contains("<td>jumped</td>"));
+        assert!(html.contains("string memory s = &quot;}&quot;;"));
+    }
+}
@@ -0,0 +1,206 @@
+use crate::evm::contract_tags::{ContractTag, ContractTags};
+use crate::evm::host::FuzzHost;
+use crate::evm::input::{ConciseEVMInput, EVMInputT};
+use crate::evm::middlewares::middleware::{Middleware, MiddlewareType};
+use crate::evm::oracles::amm::{selector, AMMKind, AMMPools};
+use crate::evm::types::{as_u64, convert_u256_to_h160, EVMAddress};
+use crate::evm::vm::{is_reverted_or_control_leak, EVMState, IS_FAST_CALL_STATIC};
+use crate::generic_vm::vm_state::VMStateT;
+use crate::input::VMInputT;
+use crate::state::{HasCaller, HasCurrentInputIdx, HasItyState};
+use bytes::Bytes;
+use libafl::inputs::Input;
+use libafl::prelude::{HasCorpus, HasMetadata, State};
+use libafl::schedulers::Scheduler;
+use revm_interpreter::{InstructionResult, Interpreter};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fmt::Debug;
+
+/// A read of the state of an AMM pool by another contract
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Hash)]
+pub struct SpotPriceRead {
+    /// Contract reading the price
+    pub consumer: EVMAddress,
+    /// Selector of the consumer function doing the read
+    pub function: [u8; 4],
+    pub pool: EVMAddress,
+    /// Contract called, the pool or one of its tokens for `balanceOf(pool)`
+    pub target: EVMAddress,
+    pub calldata: Vec<u8>,
+    pub output: Vec<u8>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct SpotPriceData {
+    /// First output of each (target, calldata) read in the sequence
+    pub baseline: HashMap<(EVMAddress, Vec<u8>), Vec<u8>>,
+    /// Reads done by the current transaction
+    pub reads: Vec<SpotPriceRead>,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct SpotPriceTracer {
+    balance_of: [u8; 4],
+    call_depth: usize,
+    /// Reads whose call has not returned yet, by the call depth of the frame
+    /// making them
+    pending: HashMap<usize, SpotPriceRead>,
+}
+
+impl SpotPriceTracer {
+    pub fn new() -> Self {
+        Self {
+            balance_of: selector("balanceOf(address)").to_vec().try_into().unwrap(),
+            call_depth: 0,
+            pending: HashMap::new(),
+        }
+    }
+
+    /// The pool whose price is read by calling `target` with `calldata`
+    fn price_read_pool<S: HasMetadata>(
+        &self,
+        state: &S,
+        target: EVMAddress,
+        calldata: &[u8],
+    ) -> Option<EVMAddress> {
+        let kind = |addr: &EVMAddress| {
+            let pools = state.metadata_map().get::<AMMPools>();
+            if let Some(pool) = pools.and_then(|pools| pools.pools.get(addr)) {
+                return Some(pool.kind);
+            }
+            let tags = state.metadata_map().get::<ContractTags>()?;
+            if tags.has(addr, ContractTag::UniswapV3Pool) {
+                Some(AMMKind::ConcentratedLiquidity)
+            } else if tags.has(addr, ContractTag::UniswapV2Pair) {
+                Some(AMMKind::ConstantProduct)
+            } else {
+                None
+            }
+        };
+
+        if calldata[0..4] == self.balance_of && calldata.len() >= 36 {
+            let owner = EVMAddress::from_slice(&calldata[16..36]);
+            return kind(&owner).map(|_| owner);
+        }
+        kind(&target)?
+            .price_getters()
+            .iter()
+            .any(|getter| calldata[0..4] == getter[..])
+            .then_some(target)
+    }
+}
+
+// Records the AMM prices read by other contracts during the transaction
+impl<I, VS, S, SC> Middleware<VS, I, S, SC> for SpotPriceTracer
+where
+    I: Input + VMInputT<VS, EVMAddress, EVMAddress, ConciseEVMInput> + EVMInputT + 'static,
+    VS: VMStateT,
+    S: State
+        + HasCaller<EVMAddress>
+        + HasCorpus
+        + HasItyState<EVMAddress, EVMAddress, VS, ConciseEVMInput>
+        + HasMetadata
+        + HasCurrentInputIdx
+        + Debug
+        + Clone,
+    SC: Scheduler<State = S> + Clone,
+{
+    unsafe fn on_step(
+        &mut self,
+        interp: &mut Interpreter,
+        _host: &mut FuzzHost<VS, I, S, SC>,
+        state: &mut S,
+    ) {
+        let (arg_offset, arg_len) = match *interp.instruction_pointer {
+            0xf1 | 0xf2 => (interp.stack.peek(3).unwrap(), interp.stack.peek(4).unwrap()),
+            0xf4 | 0xfa => (interp.stack.peek(2).unwrap(), interp.stack.peek(3).unwrap()),
+            _ => return,
+        };
+        // a call returns to this depth, replacing any read whose call did not
+        let depth = self.call_depth;
+        self.call_depth += 1;
+        self.pending.remove(&depth);
+        if !matches!(*interp.instruction_pointer, 0xf1 | 0xfa) {
+            return;
+        }
+
+        let (arg_offset, arg_len) = (as_u64(arg_offset) as usize, as_u64(arg_len) as usize);
+        match arg_offset.checked_add(arg_len) {
+            Some(end) if arg_len >= 4 && end <= interp.memory.len() => {}
+            _ => return,
+        }
+        let calldata = interp.memory.get_slice(arg_offset, arg_len).to_vec();
+        let target = convert_u256_to_h160(interp.stack.peek(1).unwrap());
+        let consumer = interp.contract.address;
+
+        let pool = match self.price_read_pool(state, target, &calldata) {
+            Some(pool) if pool != consumer => pool,
+            _ => return,
+        };
+        let mut function = [0; 4];
+        if interp.contract.input.len() >= 4 {
+            function.copy_from_slice(&interp.contract.input[0..4]);
+        }
+        self.pending.insert(
+            depth,
+            SpotPriceRead {
+                consumer,
+                function,
+                pool,
+                target,
+                calldata,
+                output: vec![],
+            },
+        );
+    }
+
+    unsafe fn on_return(
+        &mut self,
+        interp: &mut Interpreter,
+        host: &mut FuzzHost<VS, I, S, SC>,
+        _state: &mut S,
+        result: InstructionResult,
+        ret: &Bytes,
+    ) {
+        // the static calls of the oracles are not part of the transaction
+        if IS_FAST_CALL_STATIC {
+            return;
+        }
+        // on_return is called on the frame making the call
+        self.call_depth = self.call_depth.saturating_sub(1);
+        let mut read = match self.pending.remove(&self.call_depth) {
+            Some(read) if read.consumer == interp.contract.address => read,
+            _ => return,
+        };
+        // a reverted call returns the revert reason
+        if is_reverted_or_control_leak(&result) || ret.is_empty() {
+            return;
+        }
+        read.output = ret.to_vec();
+        let data = &mut host.evmstate.spot_price_metadata;
+        data.baseline
+            .entry((read.target, read.calldata.clone()))
+            .or_insert(read.output.clone());
+        data.reads.push(read);
+    }
+
+    #[allow(unused_variables)]
+    unsafe fn before_execute(
+        &mut self,
+        interp: Option<&mut Interpreter>,
+        host: &mut FuzzHost<VS, I, S, SC>,
+        state: &mut S,
+        is_step: bool,
+        data: &mut Bytes,
+        evm_state: &mut EVMState,
+    ) {
+        self.call_depth = 0;
+        self.pending.clear();
+        evm_state.spot_price_metadata.reads.clear();
+    }
+
+    fn get_type(&self) -> MiddlewareType {
+        MiddlewareType::SpotPrice
+    }
+}
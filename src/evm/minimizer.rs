@@ -1,9 +1,12 @@
 use crate::evm::host::CALL_UNTIL;
 use crate::evm::input::{ConciseEVMInput, EVMInput};
-use crate::evm::types::{EVMAddress, EVMFuzzExecutor, EVMFuzzState, EVMQueueExecutor};
+use crate::evm::types::{
+    simplify_u256, EVMAddress, EVMFuzzExecutor, EVMFuzzState, EVMQueueExecutor,
+};
 use crate::evm::vm::{EVMExecutor, EVMState};
 use crate::feedback::IcyFeedback;
 use crate::feedback::OracleFeedback;
+use crate::fuzzer::REPLAY;
 use crate::generic_vm::vm_executor::{ExecutionResult, GenericVM};
 use crate::generic_vm::vm_state::VMStateT;
 use crate::input::VMInputT;
@@ -69,6 +72,124 @@ impl EVMMinimizer {
         }
         vec![]
     }
+
+    /// Replay the call sequence from the state of its first transaction,
+    /// skipping the transaction at index `skip`, and determine whether the
+    /// bug is still reproduced
+    fn replay<F>(
+        &mut self,
+        state: &mut EVMFuzzState,
+        txs: &[(EVMInput, u32)],
+        skip: Option<usize>,
+        reproduces: &mut F,
+    ) -> bool
+    where
+        F: FnMut(&mut EVMFuzzState, &EVMInput) -> bool,
+    {
+        let mut current_state = txs[0].0.sstate.clone();
+        let mut is_solution = false;
+
+        for (i, item) in txs.iter().enumerate() {
+            if Some(i) == skip {
+                continue;
+            }
+
+            // skip when there is no post execution but the tx is step
+            if item.0.is_step() && !current_state.state.has_post_execution() {
+                continue;
+            }
+
+            let (mut tx, call_leak) = item.clone();
+            unsafe {
+                CALL_UNTIL = call_leak;
+            }
+            tx.sstate = current_state.clone();
+            let res = {
+                let mut executor = self.evm_executor_ref.deref().borrow_mut();
+                executor.execute(&tx, state)
+            };
+
+            state.set_execution_result(res.clone());
+            is_solution |= reproduces(state, &tx);
+            current_state = state.get_execution_result().new_state.clone();
+            let reverted = state.get_execution_result().reverted;
+            if reverted {
+                break;
+            }
+        }
+        is_solution
+    }
+
+    /// Shrink the arguments of each remaining transaction as long as the bug
+    /// is still reproduced, so that the PoC carries round numbers and a single
+    /// attacker instead of random-looking values
+    fn simplify<F>(
+        &mut self,
+        state: &mut EVMFuzzState,
+        txs: &mut Vec<(EVMInput, u32)>,
+        reproduces: &mut F,
+    ) where
+        F: FnMut(&mut EVMFuzzState, &EVMInput) -> bool,
+    {
+        let attacker = Self::get_attacker(txs);
+        for idx in 0..txs.len() {
+            // candidates are strictly simpler than the current transaction,
+            // so this reaches a fixpoint
+            let mut simplified = false;
+            while !simplified {
+                simplified = true;
+                for candidate in Self::simplify_candidates(&txs[idx].0, attacker) {
+                    let original = std::mem::replace(&mut txs[idx].0, candidate);
+                    if self.replay(state, txs, None, reproduces) {
+                        simplified = false;
+                        break;
+                    }
+                    txs[idx].0 = original;
+                }
+            }
+        }
+    }
+
+    /// The caller used by most transactions (first seen on ties)
+    fn get_attacker(txs: &[(EVMInput, u32)]) -> Option<EVMAddress> {
+        let callers = txs
+            .iter()
+            .filter(|(tx, _)| !tx.step)
+            .map(|(tx, _)| tx.caller)
+            .collect_vec();
+        let counts = callers.iter().counts();
+        // `max_by_key` returns the last maximum, hence the reverse
+        callers.iter().rev().max_by_key(|c| counts[c]).cloned()
+    }
+
+    /// Simpler variants of a transaction, each differing in one position:
+    /// a shrunk ABI arg, a removed or rounded value, or the attacker as caller
+    fn simplify_candidates(tx: &EVMInput, attacker: Option<EVMAddress>) -> Vec<EVMInput> {
+        let mut candidates = vec![];
+        if let Some(data) = &tx.data {
+            for abi in data.simplify_candidates() {
+                let mut candidate = tx.clone();
+                candidate.data = Some(abi);
+                candidates.push(candidate);
+            }
+        }
+        if let Some(value) = tx.txn_value {
+            for v in simplify_u256(value) {
+                let mut candidate = tx.clone();
+                candidate.txn_value = Some(v);
+                candidates.push(candidate);
+            }
+        }
+        match attacker {
+            Some(attacker) if !tx.step && tx.caller != attacker => {
+                let mut candidate = tx.clone();
+                candidate.caller = attacker;
+                candidates.push(candidate);
+            }
+            _ => {}
+        }
+        candidates
+    }
 }
 
 type EVMOracleFeedback<'a> = OracleFeedback<
@@ -134,44 +255,22 @@ impl<E: libafl::executors::HasObservers>
                 .map(|ci| ci.to_input(last_sstate.clone())),
         );
         assert!(txs.len() >= 1);
+        let mut reproduces = |state: &mut EVMFuzzState, tx: &EVMInput| {
+            objective.reproduces(state, tx, &bug_idx_needed)
+        };
+        // replaying reports the execution result and the call trace of each
+        // replayed transaction, which re-executing would overwrite
+        if unsafe { REPLAY } {
+            return txs
+                .into_iter()
+                .map(|(tx, call_leak)| ConciseEVMInput::from_input_with_call_leak(&tx, call_leak))
+                .collect_vec();
+        }
         let mut minimized = false;
         while !minimized {
             minimized = true;
             for try_skip in 0..(txs.len()) {
-                let mut current_state = txs[0].0.sstate.clone();
-                let mut is_solution = false;
-
-                for (i, item) in txs.iter().enumerate() {
-                    if i == try_skip {
-                        continue;
-                    }
-
-                    // skip when there is no post execution but the tx is step
-                    if item.0.is_step() && !current_state.state.has_post_execution() {
-                        continue;
-                    }
-
-                    let (mut tx, call_leak) = item.clone();
-                    unsafe {
-                        CALL_UNTIL = call_leak;
-                    }
-                    tx.sstate = current_state.clone();
-                    let res = {
-                        let mut executor = self.evm_executor_ref.deref().borrow_mut();
-                        executor.execute(&tx, state)
-                    };
-
-                    state.set_execution_result(res.clone());
-                    let trial_is_solution = objective.reproduces(state, &tx, &bug_idx_needed);
-                    is_solution |= trial_is_solution;
-                    current_state = state.get_execution_result().new_state.clone();
-                    let reverted = state.get_execution_result().reverted;
-                    if reverted {
-                        break;
-                    }
-                }
-
-                if is_solution {
+                if self.replay(state, &txs, Some(try_skip), &mut reproduces) {
                     txs = txs
                         .into_iter()
                         .enumerate()
@@ -183,6 +282,7 @@ impl<E: libafl::executors::HasObservers>
                 }
             }
         }
+        self.simplify(state, &mut txs, &mut reproduces);
 
         txs.into_iter()
             .map(|(tx, call_leak)| ConciseEVMInput::from_input_with_call_leak(&tx, call_leak))
@@ -253,44 +353,22 @@ impl<E: libafl::executors::HasObservers>
                 .map(|ci| ci.to_input(last_sstate.clone())),
         );
         assert!(txs.len() >= 1);
+        let mut reproduces = |state: &mut EVMFuzzState, tx: &EVMInput| {
+            objective.reproduces(state, tx, &bug_idx_needed)
+        };
+        // replaying reports the execution result and the call trace of each
+        // replayed transaction, which re-executing would overwrite
+        if unsafe { REPLAY } {
+            return txs
+                .into_iter()
+                .map(|(tx, call_leak)| ConciseEVMInput::from_input_with_call_leak(&tx, call_leak))
+                .collect_vec();
+        }
         let mut minimized = false;
         while !minimized {
             minimized = true;
             for try_skip in 0..(txs.len()) {
-                let mut current_state = txs[0].0.sstate.clone();
-                let mut is_solution = false;
-
-                for (i, item) in txs.iter().enumerate() {
-                    if i == try_skip {
-                        continue;
-                    }
-
-                    // skip when there is no post execution but the tx is step
-                    if item.0.is_step() && !current_state.state.has_post_execution() {
-                        continue;
-                    }
-
-                    let (mut tx, call_leak) = item.clone();
-                    unsafe {
-                        CALL_UNTIL = call_leak;
-                    }
-                    tx.sstate = current_state.clone();
-                    let res = {
-                        let mut executor = self.evm_executor_ref.deref().borrow_mut();
-                        executor.execute(&tx, state)
-                    };
-
-                    state.set_execution_result(res.clone());
-                    let trial_is_solution = objective.reproduces(state, &tx, &bug_idx_needed);
-                    is_solution |= trial_is_solution;
-                    current_state = state.get_execution_result().new_state.clone();
-                    let reverted = state.get_execution_result().reverted;
-                    if reverted {
-                        break;
-                    }
-                }
-
-                if is_solution {
+                if self.replay(state, &txs, Some(try_skip), &mut reproduces) {
                     txs = txs
                         .into_iter()
                         .enumerate()
@@ -302,6 +380,7 @@ impl<E: libafl::executors::HasObservers>
                 }
             }
         }
+        self.simplify(state, &mut txs, &mut reproduces);
 
         txs.into_iter()
             .map(|(tx, call_leak)| ConciseEVMInput::from_input_with_call_leak(&tx, call_leak))
@@ -2,8 +2,11 @@ pub mod abi;
 pub mod blaz;
 pub mod bytecode_analyzer;
 pub mod bytecode_iterator;
+pub mod chain_profile;
+pub mod checkpoint;
 pub mod concolic;
 pub mod config;
+pub mod contract_tags;
 pub mod contract_utils;
 pub mod corpus_initializer;
 pub mod cov_stage;
@@ -12,43 +15,60 @@ pub mod host;
 pub mod input;
 pub mod middlewares;
 pub mod minimizer;
+pub mod multicore;
 pub mod mutator;
 pub mod onchain;
 pub mod oracle;
 pub mod oracles;
 pub mod presets;
 pub mod producers;
+pub mod proxy;
+pub mod replay;
+pub mod scribble;
+pub mod seed_corpus;
 pub mod solution;
 pub mod srcmap;
+pub mod summary;
 pub mod types;
 pub mod uniswap;
 pub mod utils;
 pub mod vm;
 
+use crate::fuzzer::StopConditions;
 use crate::fuzzers::evm_fuzzer::evm_fuzzer;
+use crate::fuzzers::icy_fuzzer::icy_fuzzer;
 use crate::oracle::{Oracle, Producer};
 use crate::state::FuzzState;
 use blaz::builder::{BuildJob, BuildJobResult};
+use blaz::deployment_script::DeploymentScript;
 use blaz::offchain_artifacts::OffChainArtifact;
 use blaz::offchain_config::OffchainConfig;
+use chain_profile::ChainProfile;
 use clap::Parser;
 use config::{Config, FuzzerTypes, StorageFetchingMode};
 use contract_utils::ContractLoader;
 use ethers::types::Transaction;
 use input::{ConciseEVMInput, EVMInput};
 use onchain::endpoints::{Chain, OnChainConfig};
+use onchain::fetch_lists::{parse_addresses, FetchLists};
 use onchain::flashloan::DummyPriceOracle;
+use onchain::flashloan_venue::FlashloanVenue;
+use oracles::amm::AMMInvariantOracle;
 use oracles::erc20::IERC20OracleFlashloan;
+use oracles::erc4626::ERC4626Oracle;
 use oracles::icy_bug::IcyBugOracle;
 use oracles::v2_pair::PairBalanceOracle;
 use producers::erc20::ERC20Producer;
 use producers::icy_producer::IcyProducer;
+use scribble::ScribbleMetadata;
 use serde::Deserialize;
 use std::cell::RefCell;
 use std::collections::HashMap;
 use std::collections::HashSet;
+use std::path::Path;
 use std::rc::Rc;
 use std::str::FromStr;
+use std::time::Duration;
 use types::{EVMAddress, EVMFuzzState, EVMU256};
 use vm::EVMState;
 
@@ -84,7 +104,7 @@ struct RPCCall {
 
 /// CLI for ItyFuzz for EVM smart contracts
 #[derive(Parser, Debug)]
-#[command(author, version, about, long_about = None)]
+#[command(author, version, about, long_about = None, args_override_self = true)]
 pub struct EvmArgs {
     /// Glob pattern / address to find contracts
     #[arg(short, long)]
@@ -99,14 +119,27 @@ pub struct EvmArgs {
     #[arg(long, default_value = "")]
     constructor_args: String,
 
-    /// Target type (glob, address) (Default: Automatically infer from target)
+    /// Target type (glob, address, foundry, hardhat) (Default: Automatically infer from target)
     #[arg(long)]
     target_type: Option<String>,
 
-    /// Fuzzer type
+    /// Infer the target type of a local target from the project it belongs to
+    /// (foundry, hardhat) instead of loading it as a glob (Default: false)
+    #[arg(long, default_value = "false")]
+    infer_project_type: bool,
+
+    /// Fuzzer type (cmp, icy)
     #[arg(long, default_value = "cmp")]
     fuzzer_type: String,
 
+    /// ICY - Hash of the target transaction, whose bug the campaign reproduces (requires onchain)
+    #[arg(long)]
+    target_txn_hash: Option<String>,
+
+    /// ICY - Enable the oracle comparing the campaign with the target transaction (Default: false)
+    #[arg(long, default_value = "false")]
+    icy_oracle: bool,
+
     /// Enable onchain
     #[arg(short, long, default_value = "false")]
     onchain: bool,
@@ -147,6 +180,24 @@ pub struct EvmArgs {
     #[arg(long, default_value = "onebyone")]
     onchain_storage_fetching: String,
 
+    /// Onchain - Never fuzz nor fetch the storage of these addresses, separated by comma
+    #[arg(long, default_value = "")]
+    onchain_denylist: String,
+
+    /// Onchain - Always fetch the storage of these addresses, separated by comma, overriding the
+    /// default and learned blacklists
+    #[arg(long, default_value = "")]
+    onchain_allowlist: String,
+
+    /// Onchain - JSON file of the denylists and allowlists of each chain
+    #[arg(long, default_value = "")]
+    onchain_fetch_lists: String,
+
+    /// Onchain - Stop fetching the storage of an address after N distinct slots fetched without
+    /// new coverage or bug, 0 to disable (Default: 0)
+    #[arg(long, default_value = "0")]
+    onchain_learn_threshold: usize,
+
     /// Enable Concolic (Experimental)
     #[arg(long, default_value = "false")]
     concolic: bool,
@@ -167,6 +218,11 @@ pub struct EvmArgs {
     #[arg(long, default_value = "dummy")]
     flashloan_price_oracle: String,
 
+    /// Borrow from real venues instead of free capital, comma separated
+    /// (aave,balancer,uniswapv3,uniswapv2) or "all" (Default: none)
+    #[arg(long, default_value = "")]
+    flashloan_venues: String,
+
     /// Enable ierc20 oracle
     #[arg(short, long, default_value = "false")]
     ierc20_oracle: bool,
@@ -175,6 +231,20 @@ pub struct EvmArgs {
     #[arg(short, long, default_value = "false")]
     pair_oracle: bool,
 
+    /// Check the invariants of AMM pools (constant product, concentrated
+    /// liquidity and stable swap), requires flashloan (Default: false)
+    #[arg(long, default_value = "false")]
+    amm_oracle: bool,
+
+    /// Check the share accounting of ERC4626 vaults (Default: false)
+    #[arg(long, default_value = "false")]
+    erc4626_oracle: bool,
+
+    /// Factor a share price must grow by in one transaction to be considered
+    /// manipulated by the ERC4626 oracle (Default: 2)
+    #[arg(long, default_value = "2")]
+    erc4626_share_price_jump: u64,
+
     /// Panic when a typed_bug() is called (Default: false)
     #[arg(long, default_value = "false")]
     panic_on_bug: bool,
@@ -187,16 +257,42 @@ pub struct EvmArgs {
     #[arg(long, default_value = "false")]
     reentrancy_oracle: bool,
 
+    /// Detect contracts consuming a manipulated AMM spot price (Default: false)
+    #[arg(long, default_value = "false")]
+    spot_price_oracle: bool,
+
+    /// Price move (in percent) considered a manipulation by the spot price
+    /// oracle (Default: 10)
+    #[arg(long, default_value = "10")]
+    spot_price_threshold: f64,
+
     #[arg(long, default_value = "true")]
     arbitrary_external_call_oracle: bool,
 
+    /// Check the Echidna properties (`echidna_*`) and Foundry invariants
+    /// (`invariant_*`), fuzzing the targets set by Foundry's
+    /// `targetContracts`, `targetSelectors` and `excludeContracts`
     #[arg(long, default_value = "true")]
     echidna_oracle: bool,
 
+    /// Report failing `assert`s and `AssertionFailed` events, as Echidna's
+    /// assertion mode (Default: false)
+    #[arg(long, default_value = "false")]
+    assertion_mode: bool,
+
     ///Enable oracle for detecting whether bug() / typed_bug() is called
     #[arg(long, default_value = "true")]
     typed_bug_oracle: bool,
 
+    /// Report violated Scribble annotations, instrumenting and rebuilding the
+    /// Foundry or Hardhat project of the target with `scribble` (Default: false)
+    #[arg(long, default_value = "false")]
+    scribble: bool,
+
+    /// Instrumentation metadata of artifacts already instrumented by Scribble
+    #[arg(long, default_value = "")]
+    scribble_metadata: String,
+
     /// Setting any string here will enable state comparison oracle.
     /// This arg holds file path pointing to state comparison oracle's desired state
     #[arg(long, default_value = "")]
@@ -210,10 +306,35 @@ pub struct EvmArgs {
     #[arg(long)]
     replay_file: Option<String>,
 
+    /// Seed the campaign with the replayable traces (e.g., `*_replayable` of a previous
+    /// work dir) or Foundry call sequences in this directory or glob
+    #[arg(long)]
+    seed_corpus: Option<String>,
+
     /// Path of work dir, saves corpus, logs, and other stuffs
     #[arg(long, default_value = "work_dir")]
     work_dir: String,
 
+    /// Save a checkpoint of the campaign in the work dir every N seconds, 0 to disable
+    #[arg(long, default_value = "0")]
+    checkpoint_interval: u64,
+
+    /// Resume the campaign checkpointed in this work dir, with its original arguments
+    #[arg(long)]
+    resume: Option<String>,
+
+    /// Number of worker processes, sharing their corpus and bugs
+    #[arg(long, default_value = "1")]
+    cores: usize,
+
+    /// Seconds between corpus synchronizations of the workers
+    #[arg(long, default_value = "30")]
+    sync_interval: u64,
+
+    /// Set by the supervisor of a multi-core campaign on its workers
+    #[arg(long, hide = true)]
+    worker_id: Option<usize>,
+
     /// Write contract relationship to files
     #[arg(long, default_value = "false")]
     write_relationship: bool,
@@ -222,6 +343,18 @@ pub struct EvmArgs {
     #[arg(long, default_value = "false")]
     run_forever: bool,
 
+    /// Stop the campaign after N seconds
+    #[arg(long)]
+    time_limit: Option<u64>,
+
+    /// Stop the campaign after N executions
+    #[arg(long)]
+    max_execs: Option<usize>,
+
+    /// Stop the campaign after N executions without new coverage
+    #[arg(long)]
+    plateau_execs: Option<u32>,
+
     /// random seed
     #[arg(long, default_value = "1667840158231589000")]
     seed: u64,
@@ -240,9 +373,9 @@ pub struct EvmArgs {
     #[arg(long, default_value = "")]
     base_path: String,
 
-    /// Spec ID
-    #[arg(long, default_value = "Latest")]
-    spec_id: String,
+    /// Spec ID, defaults to the latest spec supported by the chain
+    #[arg(long)]
+    spec_id: Option<String>,
 
     /// Builder URL. If specified, will use this builder to build contracts instead of using
     /// bins and abis.
@@ -268,6 +401,20 @@ pub struct EvmArgs {
     /// Offchain Config File. If specified, will deploy based on offchain config file.
     #[arg(long, default_value = "")]
     offchain_config_file: String,
+
+    /// Deploy these contracts (names separated by comma, or "all") behind a proxy, calls being
+    /// routed through the proxy (Default: none)
+    #[arg(long, default_value = "")]
+    proxy_contracts: String,
+
+    /// Kind of the proxies of --proxy-contracts (transparent, uups, beacon)
+    #[arg(long, default_value = "transparent")]
+    proxy_kind: String,
+
+    /// Deployment script (JSON or TOML) listing the ordered deployments, setup calls
+    /// and initial balances of offchain targets
+    #[arg(long, default_value = "")]
+    deployment_script: String,
 }
 
 enum EVMTargetType {
@@ -275,9 +422,59 @@ enum EVMTargetType {
     Address,
     ArtifactAndProxy,
     Config,
+    /// Foundry `out` directory
+    Foundry,
+    /// Hardhat `artifacts` directory
+    Hardhat,
+}
+
+/// Infer the target type of a local directory from the project it belongs to
+fn infer_local_target_type(target: &str) -> EVMTargetType {
+    let project_root = Path::new(target).parent().unwrap_or(Path::new("."));
+    if project_root.join("foundry.toml").exists() {
+        EVMTargetType::Foundry
+    } else if project_root.join("hardhat.config.js").exists()
+        || project_root.join("hardhat.config.ts").exists()
+    {
+        EVMTargetType::Hardhat
+    } else {
+        EVMTargetType::Glob
+    }
 }
 
-pub fn evm_main(args: EvmArgs) {
+/// `argv` is the command line `args` were parsed from, its first item being
+/// parsed as the binary name. It is saved to resume the campaign.
+pub fn evm_main(mut args: EvmArgs, argv: Vec<String>) {
+    let resume = args.resume.is_some();
+    if let Some(work_dir) = args.resume.take() {
+        let worker_id = args.worker_id;
+        args = EvmArgs::parse_from(checkpoint::load_args(&work_dir));
+        args.work_dir = work_dir;
+        args.worker_id = worker_id;
+    } else if args.checkpoint_interval > 0 && args.worker_id.is_none() {
+        checkpoint::save_args(&args.work_dir, &argv);
+    }
+    if args.scribble && args.scribble_metadata.is_empty() {
+        args.scribble_metadata = scribble::metadata_path(&args.target);
+        // instrumented once, before the workers are spawned
+        if args.worker_id.is_none() {
+            scribble::instrument(&args.target, infer_local_target_type(&args.target));
+        }
+    }
+    let mut sync_dir = None;
+    if args.cores > 1 {
+        match args.worker_id {
+            None => {
+                multicore::supervise(&args.work_dir, args.cores, args.run_forever);
+                return;
+            }
+            Some(worker) => {
+                sync_dir = Some(multicore::sync_dir(&args.work_dir));
+                args.work_dir = multicore::worker_dir(&args.work_dir, worker);
+                args.seed += worker as u64;
+            }
+        }
+    }
     let target = args.target.clone();
     let work_dir = args.work_dir.clone();
 
@@ -285,6 +482,8 @@ pub fn evm_main(args: EvmArgs) {
         Some(v) => match v.as_str() {
             "glob" => EVMTargetType::Glob,
             "address" => EVMTargetType::Address,
+            "foundry" => EVMTargetType::Foundry,
+            "hardhat" => EVMTargetType::Hardhat,
             _ => {
                 panic!("Invalid target type")
             }
@@ -292,12 +491,15 @@ pub fn evm_main(args: EvmArgs) {
         None => {
             if args.target.starts_with("0x") {
                 EVMTargetType::Address
+            } else if args.infer_project_type {
+                infer_local_target_type(&args.target)
             } else {
                 EVMTargetType::Glob
             }
         }
     };
 
+    let mut chain_profile = ChainProfile::new(args.chain_type.as_ref().and_then(Chain::from_str));
     let mut onchain = if args.onchain {
         match args.chain_type {
             Some(chain_str) => {
@@ -321,6 +523,10 @@ pub fn evm_main(args: EvmArgs) {
         None
     };
 
+    if let Some(onchain) = onchain.as_mut() {
+        chain_profile.fetch_l1_info(onchain);
+    }
+
     solution::init_cli_args(target, work_dir, &onchain);
     let onchain_clone = onchain.clone();
 
@@ -342,7 +548,7 @@ pub fn evm_main(args: EvmArgs) {
         IERC20OracleFlashloan::new(erc20_producer.clone())
     }));
 
-    let mut icy_oracle = Rc::new(RefCell::new(IcyBugOracle::new(icy_producer.clone()))); // nasty implementation(todo@a3yip6)
+    let icy_oracle = Rc::new(RefCell::new(IcyBugOracle::new(icy_producer.clone()))); // nasty implementation(todo@a3yip6)
 
     // let harness_code = "oracle_harness()";
     // let mut harness_hash: [u8; 4] = [0; 4];
@@ -399,6 +605,16 @@ pub fn evm_main(args: EvmArgs) {
         oracles.push(Rc::new(RefCell::new(PairBalanceOracle::new())));
     }
 
+    if args.amm_oracle {
+        oracles.push(Rc::new(RefCell::new(AMMInvariantOracle::new())));
+    }
+
+    if args.erc4626_oracle {
+        oracles.push(Rc::new(RefCell::new(ERC4626Oracle::new(
+            args.erc4626_share_price_jump,
+        ))));
+    }
+
     if args.ierc20_oracle {
         oracles.push(flashloan_oracle.clone());
     }
@@ -407,8 +623,40 @@ pub fn evm_main(args: EvmArgs) {
         producers.push(erc20_producer);
     }
 
+    if args.icy_oracle {
+        oracles.push(icy_oracle.clone());
+        producers.push(icy_producer);
+    }
+
+    let fuzzer_type = FuzzerTypes::from_str(args.fuzzer_type.as_str()).expect("unknown fuzzer");
+    let txn = match args.target_txn_hash {
+        Some(hash) => Some(
+            onchain
+                .as_mut()
+                .expect("onchain is required for the target transaction")
+                .fetch_transaction_by_hash(hash)
+                .expect("failed to fetch the target transaction"),
+        ),
+        None => {
+            if let FuzzerTypes::ICY = fuzzer_type {
+                panic!("ICY fuzzer requires --target-txn-hash");
+            }
+            None
+        }
+    };
+
     let is_onchain = onchain.is_some();
-    let mut state: EVMFuzzState = FuzzState::new(args.seed, None);
+    let mut onchain_fetch_lists = FetchLists {
+        deny: parse_addresses(&args.onchain_denylist),
+        allow: parse_addresses(&args.onchain_allowlist),
+    };
+    if let (false, Some(onchain)) = (args.onchain_fetch_lists.is_empty(), &onchain) {
+        onchain_fetch_lists.extend(FetchLists::from_file(
+            &args.onchain_fetch_lists,
+            &onchain.chain_name,
+        ));
+    }
+    let mut state: EVMFuzzState = FuzzState::new(args.seed, txn);
 
     let mut proxy_deploy_codes: Vec<String> = vec![];
 
@@ -491,8 +739,19 @@ pub fn evm_main(args: EvmArgs) {
         None
     };
 
-    let config = Config {
-        fuzzer_type: FuzzerTypes::from_str(args.fuzzer_type.as_str()).expect("unknown fuzzer"),
+    let deployment_script = if args.deployment_script.len() > 0 {
+        Some(
+            DeploymentScript::from_file(args.deployment_script)
+                .expect("failed to parse deployment script"),
+        )
+    } else {
+        None
+    };
+
+    let flashloan_venues = FlashloanVenue::parse_list(&args.flashloan_venues);
+
+    let mut config = Config {
+        fuzzer_type,
         contract_loader: match target_type {
             EVMTargetType::Glob => ContractLoader::from_glob(
                 args.target.as_str(),
@@ -500,18 +759,28 @@ pub fn evm_main(args: EvmArgs) {
                 &proxy_deploy_codes,
                 &constructor_args_map,
             ),
+            EVMTargetType::Foundry => ContractLoader::from_foundry_out(
+                args.target.as_str(),
+                &mut state,
+                &constructor_args_map,
+            ),
+            EVMTargetType::Hardhat => ContractLoader::from_hardhat_artifacts(
+                args.target.as_str(),
+                &mut state,
+                &constructor_args_map,
+            ),
             EVMTargetType::Config => ContractLoader::from_config(
                 &offchain_artifacts.expect("offchain artifacts is required for config target type"),
                 &offchain_config.expect("offchain config is required for config target type"),
             ),
 
-            EVMTargetType::ArtifactAndProxy => {
-                // ContractLoader::from_artifacts_and_proxy(
-                //     &offchain_artifacts.expect("offchain artifacts is required for artifact and proxy target type"),
-                //     &proxy_deploy_codes,
-                // )
-                todo!("Artifact and proxy is not supported yet")
-            }
+            EVMTargetType::ArtifactAndProxy => ContractLoader::from_offchain_artifacts(
+                &offchain_artifacts
+                    .expect("offchain artifacts is required for artifact and proxy target type"),
+                &mut state,
+                &proxy_deploy_codes,
+                &constructor_args_map,
+            ),
             EVMTargetType::Address => {
                 if onchain.is_none() {
                     panic!("Onchain is required for address target type");
@@ -533,6 +802,19 @@ pub fn evm_main(args: EvmArgs) {
                         }
                     }
                 }
+                if args.flashloan {
+                    if let Some(chain) = Chain::from_str(&onchain.as_ref().unwrap().chain_name) {
+                        for venue in &flashloan_venues {
+                            for address in venue.known_addresses(&chain) {
+                                let address = format!("{:?}", address);
+                                if args_target.find(&address) == None {
+                                    args_target.push_str(",");
+                                    args_target.push_str(&address);
+                                }
+                            }
+                        }
+                    }
+                }
                 let addresses: Vec<EVMAddress> = args_target
                     .split(",")
                     .map(|s| EVMAddress::from_str(s).unwrap())
@@ -559,7 +841,8 @@ pub fn evm_main(args: EvmArgs) {
         oracle: oracles,
         producers,
         flashloan: args.flashloan,
-        is_icy_oracle: false,
+        flashloan_venues,
+        is_icy_oracle: args.icy_oracle,
         price_oracle: match args.flashloan_price_oracle.as_str() {
             "onchain" => {
                 Box::new(onchain_clone.expect("onchain unavailable but used for flashloan"))
@@ -574,11 +857,19 @@ pub fn evm_main(args: EvmArgs) {
         } else {
             None
         },
+        onchain_fetch_lists,
+        onchain_learn_threshold: args.onchain_learn_threshold,
         replay_file: args.replay_file,
+        seed_corpus: args.seed_corpus,
         flashloan_oracle,
         icy_oracle,
         selfdestruct_oracle: args.selfdestruct_oracle,
         reentrancy_oracle: args.reentrancy_oracle,
+        spot_price_oracle: if args.spot_price_oracle {
+            Some(args.spot_price_threshold)
+        } else {
+            None
+        },
         state_comp_matching: if args.state_comp_oracle.len() > 0 {
             Some(args.state_comp_matching)
         } else {
@@ -590,14 +881,30 @@ pub fn evm_main(args: EvmArgs) {
             None
         },
         work_dir: args.work_dir,
+        checkpoint_interval: args.checkpoint_interval,
+        resume,
+        worker_id: args.worker_id,
+        sync_dir,
+        sync_interval: args.sync_interval,
         write_relationship: args.write_relationship,
         run_forever: args.run_forever,
+        stop_conditions: StopConditions {
+            time_limit: args.time_limit.map(Duration::from_secs),
+            max_execs: args.max_execs,
+            plateau_execs: args.plateau_execs,
+        },
         sha3_bypass: args.sha3_bypass,
         base_path: args.base_path,
         echidna_oracle: args.echidna_oracle,
+        assertion_mode: args.assertion_mode,
         panic_on_bug: args.panic_on_bug,
-        spec_id: args.spec_id,
+        spec_id: args
+            .spec_id
+            .unwrap_or_else(|| chain_profile.spec_id.to_string()),
+        chain_profile,
         typed_bug: args.typed_bug_oracle,
+        scribble: (!args.scribble_metadata.is_empty())
+            .then(|| ScribbleMetadata::from_file(&args.scribble_metadata)),
         selfdestruct_bug: args.selfdestruct_oracle,
         arbitrary_external_call: args.arbitrary_external_call_oracle,
         builder,
@@ -605,10 +912,25 @@ pub fn evm_main(args: EvmArgs) {
             EVMTargetType::Glob => Some(args.target),
             _ => None,
         },
+        deployment_script,
     };
 
+    let proxy_contracts = args
+        .proxy_contracts
+        .split(',')
+        .filter(|name| !name.is_empty())
+        .map(|name| name.to_string())
+        .collect::<Vec<_>>();
+    if !proxy_contracts.is_empty() {
+        let proxy_kind = proxy::parse_proxy_kind(&args.proxy_kind).expect("unknown proxy kind");
+        config
+            .contract_loader
+            .add_proxies(&proxy_contracts, proxy_kind, &mut state);
+    }
+
     match config.fuzzer_type {
         FuzzerTypes::CMP => evm_fuzzer(config, &mut state),
+        FuzzerTypes::ICY => icy_fuzzer(config, &mut state),
         // FuzzerTypes::BASIC => basic_fuzzer(config)
         _ => {}
     }
@@ -0,0 +1,302 @@
+//! Multi-core campaigns: `--cores N` spawns N worker processes, each fuzzing
+//! in `{work_dir}/worker_{i}` with its own seed.
+//!
+//! Workers share their new corpus entries and known bugs through
+//! `{work_dir}/sync/{i}/`. An entry carries the VM state its transaction was
+//! executed on, which is added to the infant state corpus of the importing
+//! workers. Onchain fetches are deduplicated by the RPC cache shared by all
+//! workers.
+//!
+//! LibAFL's `Launcher` and its LLMP event manager are not used: the fuzzers
+//! run on a `SimpleEventManager` with an executor shared through `Rc` by the
+//! stages and feedbacks, and corpus entries need the VM state they were
+//! executed on, which `NewTestcase` events do not carry. The file sync has
+//! limits:
+//! - entries reach the other workers with a latency of up to
+//!   `--sync-interval` seconds, as each worker polls the sync dir
+//! - sync files are never removed, the sync dir grows with the corpus of
+//!   the campaign and is left in the work dir once it ends
+use crate::evm::input::EVMInput;
+use crate::evm::types::{EVMFuzzExecutor, EVMFuzzState, EVMInfantStateState, EVMStagedVMState};
+use crate::input::VMInputT;
+use crate::oracle::BugMetadata;
+use crate::scheduler::SortedDroppingScheduler;
+use crate::state::HasItyState;
+use libafl::events::ProgressReporter;
+use libafl::prelude::{Corpus, CorpusId, HasCorpus, HasMetadata, ObserversTuple, Stage};
+use libafl::state::UsesState;
+use libafl::{Error, Evaluator};
+use libafl_bolts::impl_serdeany;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::process::{Child, Command};
+use std::thread::sleep;
+use std::time::{Duration, Instant};
+use tracing::{info, warn};
+
+pub fn worker_dir(work_dir: &str, worker: usize) -> String {
+    format!("{}/worker_{}", work_dir, worker)
+}
+
+pub fn sync_dir(work_dir: &str) -> String {
+    format!("{}/sync", work_dir)
+}
+
+#[derive(Serialize, Deserialize)]
+struct SyncedInput {
+    input: EVMInput,
+    /// Not serialized with the input
+    state: EVMStagedVMState,
+}
+
+/// Progress of the synchronization, kept in the fuzz state so that it
+/// survives a resumed worker
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct SyncMetadata {
+    /// Highest id of the corpus entries already exported or imported. Ids are
+    /// not contiguous as entries may be removed.
+    last_synced: Option<usize>,
+    /// Number of files exported by this worker
+    exported: usize,
+    /// Number of files imported from each worker
+    imported: HashMap<String, usize>,
+}
+
+impl_serdeany!(SyncMetadata);
+
+/// Stage exporting the new corpus entries and known bugs of this worker and
+/// importing those of the other workers every `interval`, disabled outside
+/// of multi-core campaigns
+pub struct SyncStage<OT> {
+    infant_scheduler: SortedDroppingScheduler<EVMInfantStateState>,
+    sync_dir: Option<String>,
+    worker: usize,
+    interval: Duration,
+    last_sync: Instant,
+    pub phantom: std::marker::PhantomData<OT>,
+}
+
+impl<OT> UsesState for SyncStage<OT> {
+    type State = EVMFuzzState;
+}
+
+impl<OT> SyncStage<OT> {
+    pub fn new(
+        infant_scheduler: SortedDroppingScheduler<EVMInfantStateState>,
+        sync_dir: Option<String>,
+        worker: usize,
+        interval: Duration,
+    ) -> Self {
+        if let Some(sync_dir) = &sync_dir {
+            fs::create_dir_all(format!("{}/{}", sync_dir, worker))
+                .expect("failed to create sync dir");
+        }
+        Self {
+            infant_scheduler,
+            sync_dir,
+            worker,
+            interval,
+            last_sync: Instant::now(),
+            phantom: std::marker::PhantomData,
+        }
+    }
+
+    fn sync_dir(&self) -> &str {
+        self.sync_dir.as_deref().unwrap()
+    }
+
+    fn write(&self, file: &str, data: Vec<u8>) {
+        let path = format!("{}/{}/{}", self.sync_dir(), self.worker, file);
+        // renamed once complete, so that other workers never read a partial file
+        fs::write(format!("{}.tmp", path), data).expect("failed to write sync file");
+        fs::rename(format!("{}.tmp", path), path).expect("failed to write sync file");
+    }
+
+    fn export(&self, sync: &mut SyncMetadata, state: &EVMFuzzState) -> Result<(), Error> {
+        let bugs = state.metadata_map().get::<BugMetadata>();
+        let mut next = state.corpus().first();
+        while let Some(id) = next {
+            next = state.corpus().next(id);
+            let idx = usize::from(id);
+            if sync.last_synced.map_or(false, |last| idx <= last) {
+                continue;
+            }
+            // solutions are shared as known bugs instead
+            if bugs.map_or(false, |bugs| bugs.corpus_idx_to_bug.contains_key(&idx)) {
+                continue;
+            }
+            let testcase = state.corpus().get(id)?.borrow();
+            // seeds are executed on the uninitialized state, which the
+            // importing workers cannot fuzz from
+            let input = match testcase.input() {
+                Some(input) if input.sstate.initialized => input,
+                _ => continue,
+            };
+            let synced = SyncedInput {
+                input: input.clone(),
+                state: input.sstate.clone(),
+            };
+            self.write(
+                &format!("{}.cbor", sync.exported),
+                serde_cbor::to_vec(&synced).expect("failed to serialize input"),
+            );
+            sync.exported += 1;
+        }
+        if let Some(bugs) = bugs {
+            self.write(
+                "bugs.cbor",
+                serde_cbor::to_vec(&bugs.known_bugs).expect("failed to serialize bugs"),
+            );
+        }
+        Ok(())
+    }
+
+    fn import<Z, EM>(
+        &mut self,
+        sync: &mut SyncMetadata,
+        fuzzer: &mut Z,
+        executor: &mut EVMFuzzExecutor<OT>,
+        state: &mut EVMFuzzState,
+        manager: &mut EM,
+    ) -> Result<(), Error>
+    where
+        Z: Evaluator<EVMFuzzExecutor<OT>, EM, State = EVMFuzzState>,
+        EM: UsesState<State = EVMFuzzState>,
+        OT: ObserversTuple<EVMFuzzState>,
+    {
+        let sync_dir = self.sync_dir().to_string();
+        let workers = fs::read_dir(&sync_dir)?
+            .filter_map(|entry| entry.ok())
+            .map(|entry| entry.file_name().to_string_lossy().to_string())
+            .filter(|worker| *worker != self.worker.to_string())
+            .collect::<Vec<_>>();
+
+        let mut imported = 0;
+        for worker in workers {
+            let dir = format!("{}/{}", sync_dir, worker);
+            if let Ok(data) = fs::read(format!("{}/bugs.cbor", dir)) {
+                if let Ok(known_bugs) = serde_cbor::from_slice::<HashSet<u64>>(&data) {
+                    if !state.has_metadata::<BugMetadata>() {
+                        state.metadata_map_mut().insert(BugMetadata::new());
+                    }
+                    let bugs = state.metadata_map_mut().get_mut::<BugMetadata>().unwrap();
+                    bugs.known_bugs.extend(known_bugs);
+                }
+            }
+
+            let next = sync.imported.entry(worker).or_insert(0);
+            while let Ok(data) = fs::read(format!("{}/{}.cbor", dir, next)) {
+                *next += 1;
+                let SyncedInput {
+                    mut input,
+                    state: vm_state,
+                } = match serde_cbor::from_slice(&data) {
+                    Ok(synced) => synced,
+                    Err(e) => {
+                        warn!("failed to deserialize synced input: {:?}", e);
+                        continue;
+                    }
+                };
+                let state_idx = state.add_infant_state(&vm_state, &mut self.infant_scheduler, 0);
+                input.set_staged_state(vm_state, state_idx);
+                fuzzer.evaluate_input_events(state, executor, manager, input, false)?;
+                imported += 1;
+            }
+        }
+        if imported > 0 {
+            info!(
+                "Worker {} imported {} corpus entries",
+                self.worker, imported
+            );
+        }
+        Ok(())
+    }
+}
+
+impl<EM, Z, OT> Stage<EVMFuzzExecutor<OT>, EM, Z> for SyncStage<OT>
+where
+    Z: Evaluator<EVMFuzzExecutor<OT>, EM, State = Self::State>,
+    EM: ProgressReporter + UsesState<State = Self::State>,
+    OT: ObserversTuple<Self::State>,
+{
+    fn perform(
+        &mut self,
+        fuzzer: &mut Z,
+        executor: &mut EVMFuzzExecutor<OT>,
+        state: &mut Self::State,
+        manager: &mut EM,
+        _corpus_idx: CorpusId,
+    ) -> Result<(), Error> {
+        if self.sync_dir.is_none() || self.last_sync.elapsed() < self.interval {
+            return Ok(());
+        }
+        let mut sync = state
+            .metadata_map()
+            .get::<SyncMetadata>()
+            .cloned()
+            .unwrap_or_default();
+        self.export(&mut sync, state)?;
+        self.import(&mut sync, fuzzer, executor, state, manager)?;
+        // imported entries are not exported again
+        sync.last_synced = state.corpus().last().map(usize::from);
+        state.metadata_map_mut().insert(sync);
+        self.last_sync = Instant::now();
+        Ok(())
+    }
+}
+
+/// Spawn `cores` workers running the current command line and wait for them.
+/// Unless `run_forever` is set, the campaign stops when a worker stops (i.e.,
+/// it found a bug). The bugs found by the workers are collected in
+/// `{work_dir}/vuln_info.jsonl`.
+pub fn supervise(work_dir: &str, cores: usize, run_forever: bool) {
+    fs::create_dir_all(work_dir).expect("failed to create work dir");
+    let exe = std::env::current_exe().expect("failed to locate ityfuzz binary");
+    let mut workers: Vec<Child> = (0..cores)
+        .map(|worker| {
+            Command::new(&exe)
+                .args(std::env::args().skip(1))
+                .args(["--worker-id", &worker.to_string()])
+                .spawn()
+                .expect("failed to spawn worker")
+        })
+        .collect();
+    info!("Started {} workers", cores);
+
+    let mut exited = vec![false; cores];
+    loop {
+        sleep(Duration::from_secs(1));
+        for (worker, child) in workers.iter_mut().enumerate() {
+            if exited[worker] {
+                continue;
+            }
+            if let Ok(Some(status)) = child.try_wait() {
+                info!("Worker {} exited ({})", worker, status);
+                exited[worker] = true;
+            }
+        }
+        let done = exited.iter().filter(|exited| **exited).count();
+        if done == cores || (!run_forever && done > 0) {
+            break;
+        }
+    }
+    for child in workers.iter_mut() {
+        let _ = child.kill();
+        let _ = child.wait();
+    }
+
+    let mut vuln_info = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(format!("{}/vuln_info.jsonl", work_dir))
+        .expect("failed to open vuln_info.jsonl");
+    for worker in 0..cores {
+        if let Ok(bugs) = fs::read(format!("{}/vuln_info.jsonl", worker_dir(work_dir, worker))) {
+            vuln_info
+                .write_all(&bugs)
+                .expect("failed to write vuln_info.jsonl");
+        }
+    }
+}
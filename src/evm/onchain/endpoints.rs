@@ -3,7 +3,8 @@ use crate::evm::abi::get_abi_type_boxed;
 use crate::evm::contract_utils::ContractLoader;
 use crate::evm::input::{EVMInput, EVMInputT, EVMInputTy};
 use crate::evm::mutator::AccessPattern;
-use crate::evm::types::{convert_u256_to_h160, EVMAddress, EVMU256};
+use crate::evm::onchain::proxy_resolver::{implementation_abis, resolve_proxy};
+use crate::evm::types::{EVMAddress, EVMU256};
 use crate::evm::uniswap::{
     get_uniswap_info, PairContext, PathContext, TokenContext, UniswapProvider,
 };
@@ -16,7 +17,7 @@ use retry::OperationResult;
 use retry::{delay::Fixed, retry_with_index};
 use revm_interpreter::analysis::to_analysed;
 use revm_primitives::Bytecode;
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 use serde_json::{json, Value};
 use std::cell::RefCell;
 use std::collections::hash_map::DefaultHasher;
@@ -27,12 +28,33 @@ use std::hash::{Hash, Hasher};
 use std::panic;
 use std::rc::Rc;
 use std::str::FromStr;
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::Arc;
 use std::time::Duration;
 use tracing::{debug, error, info, warn};
 
 const MAX_HOPS: u32 = 2; // Assuming the value of MAX_HOPS
 
+static RPC_CACHE_HITS: AtomicU64 = AtomicU64::new(0);
+static RPC_REQUESTS: AtomicU64 = AtomicU64::new(0);
+static RPC_FAILURES: AtomicU64 = AtomicU64::new(0);
+
+/// RPC and Etherscan requests of all the endpoints of the campaign
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct RPCStats {
+    pub cache_hits: u64,
+    pub requests: u64,
+    pub failures: u64,
+}
+
+pub fn rpc_stats() -> RPCStats {
+    RPCStats {
+        cache_hits: RPC_CACHE_HITS.load(Ordering::Relaxed),
+        requests: RPC_REQUESTS.load(Ordering::Relaxed),
+        failures: RPC_FAILURES.load(Ordering::Relaxed),
+    }
+}
+
 #[derive(Clone, Debug, Hash, PartialEq, Eq, Copy)]
 pub enum Chain {
     ETH,
@@ -300,8 +322,10 @@ impl OnChainConfig {
         key.hash(&mut hasher);
         let hash = hasher.finish().to_string();
         if let Ok(t) = self.rpc_cache.load(hash.as_str()) {
+            RPC_CACHE_HITS.fetch_add(1, Ordering::Relaxed);
             return Some(t);
         }
+        RPC_REQUESTS.fetch_add(1, Ordering::Relaxed);
         match retry_with_index(Fixed::from_millis(1000), |current_try| {
             if current_try > 5 {
                 return OperationResult::Err("did not succeed within 3 tries".to_string());
@@ -343,6 +367,7 @@ impl OnChainConfig {
                 Some(t)
             }
             Err(e) => {
+                RPC_FAILURES.fetch_add(1, Ordering::Relaxed);
                 error!("Error: {}", e);
                 None
             }
@@ -355,8 +380,10 @@ impl OnChainConfig {
         key.hash(&mut hasher);
         let hash = hasher.finish().to_string();
         if let Ok(t) = self.rpc_cache.load(hash.as_str()) {
+            RPC_CACHE_HITS.fetch_add(1, Ordering::Relaxed);
             return Some(t);
         }
+        RPC_REQUESTS.fetch_add(1, Ordering::Relaxed);
         match retry_with_index(Fixed::from_millis(100), |current_try| {
             if current_try > 3 {
                 return OperationResult::Err("did not succeed within 3 tries".to_string());
@@ -392,6 +419,7 @@ impl OnChainConfig {
                 Some(t)
             }
             Err(e) => {
+                RPC_FAILURES.fetch_add(1, Ordering::Relaxed);
                 error!("Error: {}", e);
                 None
             }
@@ -649,6 +677,13 @@ impl OnChainConfig {
         timestamp
     }
 
+    /// Numeric field of the forked block, e.g., `l1BlockNumber` on Arbitrum
+    pub fn fetch_blk_field(&self, field: &str) -> Option<EVMU256> {
+        let params = json!([self.block_number, false]);
+        let res = self._request("eth_getBlockByNumber".to_string(), params.to_string())?;
+        EVMU256::from_str(res.get(field)?.as_str()?).ok()
+    }
+
     pub fn fetch_blk_coinbase(&mut self) -> EVMAddress {
         if self.coinbase == None {
             self.coinbase = {
@@ -771,6 +806,16 @@ impl OnChainConfig {
         slot_value
     }
 
+    /// Output of a call to `address` at the forked block, None if it reverts
+    pub fn eth_call(&self, address: EVMAddress, data: &[u8]) -> Option<Vec<u8>> {
+        let params = json!([{
+            "to": format!("0x{:x}", address),
+            "data": format!("0x{}", hex::encode(data)),
+        }, self.block_number]);
+        let resp = self._request("eth_call".to_string(), params.to_string())?;
+        hex::decode(resp.as_str()?.trim_start_matches("0x")).ok()
+    }
+
     pub fn fetch_uniswap_path(&mut self, network: &str, token_address: EVMAddress) -> TokenContext {
         let token = format!("{:?}", token_address);
         let info: Info = self.find_path_subgraph(network, &token);
@@ -1230,37 +1275,26 @@ impl OnChainConfig {
                         break;
                     }
                 }
-                // nasty implementation (todo@a3yip6)
-                match txn.data {
-                    None => {
-                        let implementation_slot =
-                            "0x360894a13ba1a3210667c828492db98dca3e2076cc3735a920a3ca505d382bbc";
-                        let implementation_addr = convert_u256_to_h160(self.get_contract_slot(
-                            txn.contract,
-                            EVMU256::from_str(implementation_slot).unwrap(),
-                            false,
-                        ));
-                        let abi = self.fetch_abi(implementation_addr);
-                        if abi.is_some() {
-                            let abis = ContractLoader::parse_abi_str(&abi.unwrap());
-                            for abi in abis {
-                                if abi.function != &txn_func[..] {
-                                    continue;
-                                }
-                                let mut abi_instance = get_abi_type_boxed(&abi.abi);
-                                abi_instance.set_func_with_signature(
-                                    abi.function,
-                                    &abi.function_name,
-                                    &abi.abi,
-                                );
-                                abi_instance.set_bytes(txn_para);
-                                txn.data = Some(abi_instance);
-                                break;
-                            }
+                // functions only implemented behind the proxy
+                if txn.data.is_none() {
+                    let implementations = resolve_proxy(self, txn.contract)
+                        .map(|proxy| proxy.implementations)
+                        .unwrap_or_default();
+                    for implementation in implementations {
+                        let abis = implementation_abis(self, &implementation);
+                        if let Some(abi) = abis.iter().find(|abi| abi.function == txn_func[..]) {
+                            let mut abi_instance = get_abi_type_boxed(&abi.abi);
+                            abi_instance.set_func_with_signature(
+                                abi.function,
+                                &abi.function_name,
+                                &abi.abi,
+                            );
+                            abi_instance.set_bytes(txn_para);
+                            txn.data = Some(abi_instance);
+                            break;
                         }
                     }
-                    _ => {}
-                };
+                }
 
                 Some(txn)
             }
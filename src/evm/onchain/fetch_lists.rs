@@ -0,0 +1,78 @@
+//! Addresses the onchain middleware fetches storage from.
+//!
+//! The denylist and allowlist of a campaign come from `--onchain-denylist`,
+//! `--onchain-allowlist` and the per chain lists of `--onchain-fetch-lists`,
+//! a JSON file like `{"eth": {"deny": ["0x.."], "allow": ["0x.."]}}` whose
+//! `"*"` entry applies to all chains.
+use crate::evm::types::EVMAddress;
+use serde::Deserialize;
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::str::FromStr;
+
+#[derive(Clone, Debug, Default, Deserialize)]
+pub struct FetchLists {
+    /// Never fuzzed, their storage is stubbed
+    #[serde(default)]
+    pub deny: HashSet<EVMAddress>,
+    /// Never blacklisted, neither by default nor when learned unproductive
+    #[serde(default)]
+    pub allow: HashSet<EVMAddress>,
+}
+
+/// Comma separated addresses
+pub fn parse_addresses(addresses: &str) -> HashSet<EVMAddress> {
+    addresses
+        .split(',')
+        .filter(|address| !address.is_empty())
+        .map(|address| EVMAddress::from_str(address.trim()).expect("failed to parse address"))
+        .collect()
+}
+
+impl FetchLists {
+    /// Lists of `chain_name` in a `--onchain-fetch-lists` file
+    pub fn from_json(json: &str, chain_name: &str) -> Self {
+        let mut chains: HashMap<String, FetchLists> =
+            serde_json::from_str(json).expect("failed to parse onchain fetch lists");
+        let mut lists = chains.remove("*").unwrap_or_default();
+        if let Some(chain) = chains.remove(chain_name) {
+            lists.extend(chain);
+        }
+        lists
+    }
+
+    pub fn from_file(path: &str, chain_name: &str) -> Self {
+        let json = fs::read_to_string(path).expect("failed to read onchain fetch lists");
+        Self::from_json(&json, chain_name)
+    }
+
+    pub fn extend(&mut self, other: FetchLists) {
+        self.deny.extend(other.deny);
+        self.allow.extend(other.allow);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fetch_lists() {
+        let json = r#"{
+            "*": {"deny": ["0x0000000000000000000000000000000000000001"]},
+            "eth": {"allow": ["0x0000000000000000000000000000000000000002"]},
+            "bsc": {"deny": ["0x0000000000000000000000000000000000000003"]}
+        }"#;
+        let lists = FetchLists::from_json(json, "eth");
+        assert_eq!(
+            lists.deny,
+            parse_addresses("0x0000000000000000000000000000000000000001")
+        );
+        assert_eq!(
+            lists.allow,
+            parse_addresses("0x0000000000000000000000000000000000000002")
+        );
+        assert_eq!(FetchLists::from_json(json, "bsc").deny.len(), 2);
+        assert!(parse_addresses("").is_empty());
+    }
+}
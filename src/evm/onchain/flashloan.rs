@@ -9,6 +9,10 @@ use crate::evm::middlewares::middleware::CallMiddlewareReturn::ReturnSuccess;
 use crate::evm::middlewares::middleware::{Middleware, MiddlewareOp, MiddlewareType};
 use crate::evm::mutator::AccessPattern;
 use crate::evm::onchain::endpoints::{OnChainConfig, PriceOracle};
+#[cfg(feature = "flashloan_v2")]
+use crate::evm::onchain::flashloan_venue::{FlashloanRecord, FlashloanVenue};
+#[cfg(feature = "flashloan_v2")]
+use crate::evm::oracles::amm::{AMMKind, AMMPool, AMMPools};
 use crate::evm::oracles::icy_bug::IcyBugOracle;
 use crate::generic_vm::vm_state::VMStateT;
 use crate::input::VMInputT;
@@ -32,6 +36,8 @@ use std::fmt::Debug;
 use std::marker::PhantomData;
 use std::ops::Deref;
 
+#[cfg(feature = "flashloan_v2")]
+use crate::evm::contract_tags::{ContractTag, ContractTags};
 use crate::evm::contract_utils::ABIConfig;
 use crate::evm::onchain::onchain::OnChain;
 use crate::evm::oracles::erc20::IERC20OracleFlashloan;
@@ -72,6 +78,15 @@ where
     #[cfg(feature = "flashloan_v2")]
     pair_address: HashSet<EVMAddress>,
     #[cfg(feature = "flashloan_v2")]
+    pools: HashMap<EVMAddress, AMMKind>,
+    /// Slots holding the state of each pool, learnt from its view functions
+    #[cfg(feature = "flashloan_v2")]
+    pool_slots: HashMap<EVMAddress, HashSet<EVMU256>>,
+    #[cfg(feature = "flashloan_v2")]
+    venues: Vec<FlashloanVenue>,
+    #[cfg(feature = "flashloan_v2")]
+    providers: HashMap<EVMAddress, FlashloanVenue>,
+    #[cfg(feature = "flashloan_v2")]
     pub onchain_middlware: Rc<RefCell<OnChain<VS, I, S>>>,
     #[cfg(feature = "flashloan_v2")]
     pub unbound_tracker: HashMap<usize, HashSet<EVMAddress>>, // pc -> [address called]
@@ -152,6 +167,59 @@ where
         .expect("failed to call scheduler on_add");
 }
 
+/// Add a loan from a real venue to the corpus, received by a random caller
+#[cfg(feature = "flashloan_v2")]
+pub fn register_flashloan_txn<VS, I, S, SC>(
+    mut scheduler: SC,
+    state: &mut S,
+    provider: EVMAddress,
+    venue: FlashloanVenue,
+) where
+    I: Input + VMInputT<VS, EVMAddress, EVMAddress, ConciseEVMInput> + EVMInputT + 'static,
+    S: State
+        + HasCorpus
+        + HasItyState<EVMAddress, EVMAddress, VS, ConciseEVMInput>
+        + HasMetadata
+        + HasCaller<EVMAddress>
+        + Clone
+        + Debug
+        + UsesInput<Input = I>
+        + 'static,
+    VS: VMStateT + Default,
+    SC: Scheduler<State = S> + Clone,
+{
+    let receiver = state.get_rand_caller();
+    let mut tc = Testcase::new(
+        {
+            EVMInput {
+                input_type: EVMInputTy::ABI,
+                caller: receiver,
+                contract: provider,
+                data: Some(venue.borrow_abi(receiver)),
+                sstate: Default::default(),
+                sstate_idx: 0,
+                txn_value: None,
+                step: false,
+                env: Default::default(),
+                access_pattern: Rc::new(RefCell::new(AccessPattern::new())),
+                liquidation_percent: 0,
+                direct_data: Default::default(),
+                randomness: vec![0],
+                repeat: 1,
+            }
+        }
+        .as_any()
+        .downcast_ref::<I>()
+        .unwrap()
+        .clone(),
+    ) as Testcase<I>;
+    tc.set_exec_time(Duration::from_secs(0));
+    let idx = state.corpus_mut().add(tc).expect("failed to add");
+    scheduler
+        .on_add(state, idx)
+        .expect("failed to call scheduler on_add");
+}
+
 impl<VS, I, S> Flashloan<VS, I, S>
 where
     S: State
@@ -184,6 +252,7 @@ where
         onchain_middleware: Rc<RefCell<OnChain<VS, I, S>>>,
         flashloan_oracle: Rc<RefCell<IERC20OracleFlashloan>>,
         icy_oracle: Rc<RefCell<IcyBugOracle>>,
+        venues: Vec<FlashloanVenue>,
     ) -> Self {
         Self {
             phantom: PhantomData,
@@ -193,6 +262,10 @@ where
             endpoint,
             erc20_address: Default::default(),
             pair_address: Default::default(),
+            pools: Default::default(),
+            pool_slots: Default::default(),
+            venues,
+            providers: Default::default(),
             onchain_middlware: onchain_middleware,
             unbound_tracker: Default::default(),
             flashloan_oracle,
@@ -247,6 +320,14 @@ where
             .iter()
             .map(|x| x.function_name.clone())
             .collect::<HashSet<String>>();
+        // tags also cover contracts without ABI
+        let tags = state
+            .metadata_map()
+            .get::<ContractTags>()
+            .and_then(|tags| tags.tags.get(addr).cloned())
+            .unwrap_or_default();
+        let is_token = abi_signatures_token.iter().all(|x| abi_names.contains(x))
+            || tags.contains(&ContractTag::ERC20);
 
         let mut is_erc20 = false;
         let mut is_pair = false;
@@ -255,7 +336,7 @@ where
             let mut oracle = self.flashloan_oracle.deref().try_borrow_mut();
             // avoid delegate call on token -> make oracle borrow multiple times
             if oracle.is_ok() {
-                if abi_signatures_token.iter().all(|x| abi_names.contains(x)) {
+                if is_token {
                     oracle.unwrap().register_token(
                         addr.clone(),
                         self.endpoint
@@ -272,7 +353,7 @@ where
             let mut oracle = self.icy_oracle.deref().try_borrow_mut();
             // avoid delegate call on token -> make oracle borrow multiple times
             if oracle.is_ok() {
-                if abi_signatures_token.iter().all(|x| abi_names.contains(x)) {
+                if is_token {
                     oracle.unwrap().register_token(
                         addr.clone(),
                         self.endpoint
@@ -288,32 +369,68 @@ where
         }
 
         // if the contract is pair
-        if abi_signatures_pair.iter().all(|x| abi_names.contains(x)) {
+        if abi_signatures_pair.iter().all(|x| abi_names.contains(x))
+            || tags.contains(&ContractTag::UniswapV2Pair)
+        {
             self.pair_address.insert(addr.clone());
             debug!("pair detected @ address {:?}", addr);
             is_pair = true;
         }
 
+        if let Some(kind) = AMMKind::detect(&abi_names).or_else(|| AMMKind::from_tags(&tags)) {
+            debug!("{:?} pool detected @ address {:?}", kind, addr);
+            self.pools.insert(*addr, kind);
+            is_pair = true;
+        }
+
         (is_erc20, is_pair)
     }
 
+    /// Returns the venue of the contract if loans can be taken from it
+    #[cfg(feature = "flashloan_v2")]
+    pub fn on_provider_insertion(
+        &mut self,
+        addr: &EVMAddress,
+        abi: &Vec<ABIConfig>,
+    ) -> Option<FlashloanVenue> {
+        if self.providers.contains_key(addr) {
+            return None;
+        }
+        let abi_names = abi
+            .iter()
+            .map(|x| x.function_name.clone())
+            .collect::<HashSet<String>>();
+        let venue = FlashloanVenue::detect(&abi_names).filter(|v| self.venues.contains(v))?;
+        debug!("{} flashloan provider detected @ address {:?}", venue, addr);
+        self.providers.insert(*addr, venue);
+        Some(venue)
+    }
+
+    /// Learn the slots holding the state of a pool by probing its view
+    /// functions, so that forks with a different storage layout are handled
     #[cfg(feature = "flashloan_v2")]
     pub fn on_pair_insertion<SC>(
         &mut self,
-        host: &FuzzHost<VS, I, S, SC>,
+        host: &mut FuzzHost<VS, I, S, SC>,
         state: &mut S,
         pair: EVMAddress,
     ) where
         SC: Scheduler<State = S> + Clone,
     {
-        let slots = host.find_static_call_read_slot(
-            pair,
-            Bytes::from(vec![0x09, 0x02, 0xf1, 0xac]), // getReserves
-            state,
-        );
-        if slots.len() == 3 {
+        let kind = *self.pools.get(&pair).unwrap_or(&AMMKind::ConstantProduct);
+        let mut slots: Vec<EVMU256> = vec![];
+        for getter in kind.state_getters() {
+            for slot in host.find_static_call_read_slot(pair, getter, state) {
+                if !slots.contains(&slot) {
+                    slots.push(slot);
+                }
+            }
+        }
+        debug!("pool slots: {:?} {:?}", pair, slots);
+
+        if self.pair_address.contains(&pair) && !slots.is_empty() {
+            // reserves are read first by getReserves
             let slot = slots[0];
-            // debug!("pairslots: {:?} {:?}", pair, slot);
             self.flashloan_oracle
                 .deref()
                 .borrow_mut()
@@ -323,6 +440,18 @@ where
                 .borrow_mut()
                 .register_pair_reserve_slot(pair, slot);
         }
+        self.pool_slots
+            .insert(pair, slots.iter().cloned().collect());
+
+        if !state.has_metadata::<AMMPools>() {
+            state.metadata_map_mut().insert(AMMPools::default());
+        }
+        state
+            .metadata_map_mut()
+            .get_mut::<AMMPools>()
+            .unwrap()
+            .pools
+            .insert(pair, AMMPool { kind, slots });
     }
 }
 
@@ -531,7 +660,11 @@ where
             0x55 => {
                 if self.pair_address.contains(&interp.contract.address) {
                     let key = interp.stack.peek(0).unwrap();
-                    if key == EVMU256::from(8) {
+                    if self
+                        .pool_slots
+                        .get(&interp.contract.address)
+                        .map_or(false, |slots| slots.contains(&key))
+                    {
                         host.evmstate
                             .flashloan_data
                             .oracle_recheck_reserve
@@ -558,6 +691,37 @@ where
         }
 
         let call_target: EVMAddress = convert_u256_to_h160(interp.stack.peek(1).unwrap());
+
+        // venue calling back the receiver of a loan
+        if let Some(venue) = self.providers.get(&interp.contract.address) {
+            if *interp.instruction_pointer == 0xf1 && s.has_caller(&call_target) {
+                let offset = as_u64(interp.stack.peek(3).unwrap()) as usize;
+                let size = as_u64(interp.stack.peek(4).unwrap()) as usize;
+                let data = match offset.checked_add(size) {
+                    Some(end) if size >= 4 && end <= interp.memory.len() => {
+                        interp.memory.get_slice(offset, size)
+                    }
+                    _ => &[],
+                };
+                if data.len() >= 4 && data[0..4] == venue.callback_selector() {
+                    let depth = host.evmstate.post_execution.len();
+                    for (token, amount, fee) in venue.decode_callback(data) {
+                        host.evmstate
+                            .flashloan_data
+                            .flashloans
+                            .push(FlashloanRecord {
+                                venue: *venue,
+                                provider: interp.contract.address,
+                                token,
+                                amount,
+                                fee,
+                                depth,
+                            });
+                    }
+                }
+            }
+        }
+
         if self.erc20_address.contains(&call_target) {
             host.evmstate
                 .flashloan_data
@@ -600,6 +764,8 @@ pub struct FlashloanData {
     pub prev_reserves: HashMap<EVMAddress, (EVMU256, EVMU256)>,
     pub unliquidated_tokens: HashMap<EVMAddress, EVMU256>,
     pub extra_info: String,
+    /// Loans taken from real venues
+    pub flashloans: Vec<FlashloanRecord>,
 }
 
 #[cfg(feature = "flashloan_v2")]
@@ -613,6 +779,7 @@ impl FlashloanData {
             prev_reserves: Default::default(),
             unliquidated_tokens: Default::default(),
             extra_info: Default::default(),
+            flashloans: Default::default(),
         }
     }
 }
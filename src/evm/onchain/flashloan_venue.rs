@@ -0,0 +1,375 @@
+/// Real lending venues for flashloans
+///
+/// Instead of handing out free tokens, a loan is a call to the flash function
+/// of a venue with an attacker as receiver. The venue calls the attacker back,
+/// which leaks control, and its own code checks that the loan plus fee has
+/// been paid back once the callback returns.
+use crate::evm::abi::{get_abi_type_boxed, A256InnerType, AArray, BoxedABI, A256};
+use crate::evm::contract_utils::set_hash;
+use crate::evm::onchain::endpoints::Chain;
+use crate::evm::types::{checksum, EVMAddress, EVMU256};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum FlashloanVenue {
+    /// Aave V3 Pool `flashLoanSimple`
+    AaveV3,
+    /// Balancer Vault `flashLoan`
+    Balancer,
+    /// Uniswap V3 pool `flash`
+    UniswapV3,
+    /// Uniswap V2 pair flash swap
+    UniswapV2,
+}
+
+impl FromStr for FlashloanVenue {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "aave" | "aavev3" => Ok(FlashloanVenue::AaveV3),
+            "balancer" => Ok(FlashloanVenue::Balancer),
+            "uniswapv3" | "univ3" => Ok(FlashloanVenue::UniswapV3),
+            "uniswapv2" | "univ2" => Ok(FlashloanVenue::UniswapV2),
+            _ => Err(format!("unknown flashloan venue {}", s)),
+        }
+    }
+}
+
+impl Display for FlashloanVenue {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            FlashloanVenue::AaveV3 => "Aave V3",
+            FlashloanVenue::Balancer => "Balancer",
+            FlashloanVenue::UniswapV3 => "Uniswap V3",
+            FlashloanVenue::UniswapV2 => "Uniswap V2",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+impl FlashloanVenue {
+    pub fn all() -> Vec<Self> {
+        vec![
+            FlashloanVenue::AaveV3,
+            FlashloanVenue::Balancer,
+            FlashloanVenue::UniswapV3,
+            FlashloanVenue::UniswapV2,
+        ]
+    }
+
+    /// Parse a comma separated list of venues, `all` for every venue
+    pub fn parse_list(s: &str) -> Vec<Self> {
+        match s.trim() {
+            "" => vec![],
+            "all" => Self::all(),
+            _ => s
+                .split(',')
+                .map(|v| FlashloanVenue::from_str(v).unwrap_or_else(|e| panic!("{}", e)))
+                .collect(),
+        }
+    }
+
+    /// Guess the venue of a contract from the names of its functions
+    pub fn detect(abi_names: &HashSet<String>) -> Option<Self> {
+        let has = |names: &[&str]| names.iter().all(|n| abi_names.contains(*n));
+        if has(&["flashLoanSimple"]) {
+            Some(FlashloanVenue::AaveV3)
+        } else if has(&["flashLoan", "getPoolTokens"]) {
+            Some(FlashloanVenue::Balancer)
+        } else if has(&["flash", "swap"]) {
+            Some(FlashloanVenue::UniswapV3)
+        } else if has(&["skim", "sync", "swap"]) {
+            Some(FlashloanVenue::UniswapV2)
+        } else {
+            None
+        }
+    }
+
+    /// Well-known deployments of the venue. Uniswap pools are found among the
+    /// fuzzed contracts instead.
+    pub fn known_addresses(&self, chain: &Chain) -> Vec<EVMAddress> {
+        let address = match (self, chain) {
+            (FlashloanVenue::AaveV3, Chain::ETH) => "0x87870Bca3F3fD6335C3F4ce8392D69350B4fA4E2",
+            (
+                FlashloanVenue::AaveV3,
+                Chain::POLYGON
+                | Chain::ARBITRUM
+                | Chain::OPTIMISM
+                | Chain::AVALANCHE
+                | Chain::FANTOM,
+            ) => "0x794a61358D6845594F94dc1DB02A252b5b4814aD",
+            (FlashloanVenue::AaveV3, Chain::BASE) => "0xA238Dd80C259a72e81d7e4664a9801593F98d1c5",
+            (
+                FlashloanVenue::Balancer,
+                Chain::ETH
+                | Chain::POLYGON
+                | Chain::ARBITRUM
+                | Chain::OPTIMISM
+                | Chain::GNOSIS
+                | Chain::AVALANCHE
+                | Chain::BASE
+                | Chain::ZKEVM,
+            ) => "0xBA12222222228d8Ba445958a75a0704d566BF2C8",
+            _ => return vec![],
+        };
+        vec![EVMAddress::from_str(address).unwrap()]
+    }
+
+    /// Name and argument types of the function that takes the loan
+    pub fn borrow_signature(&self) -> (&'static str, &'static str) {
+        match self {
+            FlashloanVenue::AaveV3 => ("flashLoanSimple", "(address,address,uint256,bytes,uint16)"),
+            FlashloanVenue::Balancer => ("flashLoan", "(address,address[],uint256[],bytes)"),
+            FlashloanVenue::UniswapV3 => ("flash", "(address,uint256,uint256,bytes)"),
+            FlashloanVenue::UniswapV2 => ("swap", "(uint256,uint256,address,bytes)"),
+        }
+    }
+
+    /// Index of the receiver in the args of the borrow function
+    pub fn receiver_idx(&self) -> usize {
+        match self {
+            FlashloanVenue::UniswapV2 => 2,
+            _ => 0,
+        }
+    }
+
+    /// Selector of the callback the venue makes on the receiver
+    pub fn callback_selector(&self) -> [u8; 4] {
+        let signature = match self {
+            FlashloanVenue::AaveV3 => "executeOperation(address,uint256,uint256,address,bytes)",
+            FlashloanVenue::Balancer => "receiveFlashLoan(address[],uint256[],uint256[],bytes)",
+            FlashloanVenue::UniswapV3 => "uniswapV3FlashCallback(uint256,uint256,bytes)",
+            FlashloanVenue::UniswapV2 => "uniswapV2Call(address,uint256,uint256,bytes)",
+        };
+        let mut selector = [0; 4];
+        set_hash(signature, &mut selector);
+        selector
+    }
+
+    /// Venue whose borrow function has the given signature
+    pub fn from_signature(signature: &str) -> Option<Self> {
+        Self::all().into_iter().find(|venue| {
+            let (name, args) = venue.borrow_signature();
+            signature == format!("{}{}", name, args)
+        })
+    }
+
+    /// Calldata of a loan to `receiver`. The receiver is never mutated and,
+    /// for flash swaps, the callback data is non-empty so that the pair calls
+    /// back.
+    pub fn borrow_abi(&self, receiver: EVMAddress) -> BoxedABI {
+        let (name, args) = self.borrow_signature();
+        let mut abi = get_abi_type_boxed(args);
+        let mut selector = [0; 4];
+        set_hash(format!("{}{}", name, args).as_str(), &mut selector);
+        abi.set_func_with_signature(selector, name, args);
+
+        let args = abi
+            .get_mut()
+            .as_any()
+            .downcast_mut::<AArray>()
+            .expect("borrow args should be a tuple");
+        args.data[self.receiver_idx()] = BoxedABI::new(Box::new(A256 {
+            data: receiver.0.to_vec(),
+            is_address: true,
+            dont_mutate: true,
+            inner_type: A256InnerType::Address,
+        }));
+        if *self == FlashloanVenue::UniswapV2 {
+            let mut data = vec![0; 64];
+            data[31] = 1;
+            data[32] = 1;
+            args.data[3].get_mut().set_bytes(data);
+        }
+        abi
+    }
+
+    /// Loans in the calldata of a callback, as `(token, amount, fee)`.
+    /// Uniswap callbacks do not name the tokens, so they are reported as
+    /// `token0` / `token1` of the pool.
+    pub fn decode_callback(&self, data: &[u8]) -> Vec<(String, EVMU256, EVMU256)> {
+        let word = |idx: usize| -> Option<EVMU256> {
+            let start = 4 + idx * 32;
+            data.get(start..start + 32)
+                .map(|w| EVMU256::try_from_be_slice(w).unwrap())
+        };
+        let address = |w: EVMU256| checksum(&EVMAddress::from_slice(&w.to_be_bytes::<32>()[12..]));
+        // dynamic array whose head is the `idx`-th word
+        let array = |idx: usize| -> Vec<EVMU256> {
+            let offset = match word(idx) {
+                Some(offset) if offset < EVMU256::from(data.len()) => offset.as_limbs()[0] as usize,
+                _ => return vec![],
+            };
+            let len = word(offset / 32).map_or(0, |l| l.as_limbs()[0] as usize);
+            (0..len.min(data.len() / 32))
+                .map_while(|i| word(offset / 32 + 1 + i))
+                .collect()
+        };
+        // pairs take 0.3% of what is paid back, i.e. amount * 3 / 997 rounded up
+        let v2_fee =
+            |amount: EVMU256| (amount * EVMU256::from(3) + EVMU256::from(996)) / EVMU256::from(997);
+
+        let loans = match self {
+            FlashloanVenue::AaveV3 => match (word(0), word(1), word(2)) {
+                (Some(asset), Some(amount), Some(premium)) => {
+                    vec![(address(asset), amount, premium)]
+                }
+                _ => vec![],
+            },
+            FlashloanVenue::Balancer => {
+                let (tokens, amounts, fees) = (array(0), array(1), array(2));
+                tokens
+                    .into_iter()
+                    .zip(amounts)
+                    .zip(fees)
+                    .map(|((token, amount), fee)| (address(token), amount, fee))
+                    .collect()
+            }
+            FlashloanVenue::UniswapV3 => match (word(0), word(1)) {
+                // amounts are not passed to the callback, only the fees
+                (Some(fee0), Some(fee1)) => vec![
+                    ("token0".to_string(), EVMU256::ZERO, fee0),
+                    ("token1".to_string(), EVMU256::ZERO, fee1),
+                ],
+                _ => vec![],
+            },
+            FlashloanVenue::UniswapV2 => match (word(1), word(2)) {
+                (Some(amount0), Some(amount1)) => vec![
+                    ("token0".to_string(), amount0, v2_fee(amount0)),
+                    ("token1".to_string(), amount1, v2_fee(amount1)),
+                ],
+                _ => vec![],
+            },
+        };
+        loans
+            .into_iter()
+            .filter(|(_, amount, fee)| *amount > EVMU256::ZERO || *fee > EVMU256::ZERO)
+            .collect()
+    }
+}
+
+/// A loan taken during the execution
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct FlashloanRecord {
+    pub venue: FlashloanVenue,
+    pub provider: EVMAddress,
+    pub token: String,
+    pub amount: EVMU256,
+    pub fee: EVMU256,
+    /// Number of pending post executions when the venue called back, the
+    /// loan is outstanding until the execution returns below it
+    pub depth: usize,
+}
+
+impl Display for FlashloanRecord {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        if self.amount == EVMU256::ZERO {
+            write!(f, "{} of ", self.token)?;
+        } else {
+            write!(f, "{} of {} from ", self.amount, self.token)?;
+        }
+        write!(
+            f,
+            "{} @ {}, fee {}",
+            self.venue,
+            checksum(&self.provider),
+            self.fee
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_selectors() {
+        assert_eq!(
+            FlashloanVenue::AaveV3.callback_selector(),
+            [0x1b, 0x11, 0xd0, 0xff]
+        );
+        assert_eq!(
+            FlashloanVenue::Balancer.callback_selector(),
+            [0xf0, 0x4f, 0x27, 0x07]
+        );
+        assert_eq!(
+            FlashloanVenue::UniswapV3.callback_selector(),
+            [0xe9, 0xcb, 0xaf, 0xb0]
+        );
+        assert_eq!(
+            FlashloanVenue::UniswapV2.callback_selector(),
+            [0x10, 0xd1, 0xe8, 0x5c]
+        );
+
+        let receiver = EVMAddress::from_str("0x00000000000000000000000000000000000000aa").unwrap();
+        let abi = FlashloanVenue::UniswapV2.borrow_abi(receiver);
+        // swap(uint256,uint256,address,bytes)
+        assert_eq!(abi.function, [0x02, 0x2c, 0x0d, 0x9f]);
+        let bytes = abi.get_bytes();
+        assert_eq!(bytes[80..100], receiver.0);
+        assert_eq!(
+            FlashloanVenue::from_signature("flash(address,uint256,uint256,bytes)"),
+            Some(FlashloanVenue::UniswapV3)
+        );
+    }
+
+    #[test]
+    fn test_decode_callback() {
+        let word = |v: u64| EVMU256::from(v).to_be_bytes::<32>().to_vec();
+        let token = EVMAddress::from_str("0x00000000000000000000000000000000000000aa").unwrap();
+        let mut token_word = vec![0; 12];
+        token_word.extend_from_slice(&token.0);
+
+        // receiveFlashLoan([token], [1000], [1], "")
+        let data = [
+            FlashloanVenue::Balancer.callback_selector().to_vec(),
+            word(128),
+            word(192),
+            word(256),
+            word(320),
+            word(1),
+            token_word.clone(),
+            word(1),
+            word(1000),
+            word(1),
+            word(1),
+            word(0),
+        ]
+        .concat();
+        let loans = FlashloanVenue::Balancer.decode_callback(&data);
+        assert_eq!(
+            loans,
+            vec![(checksum(&token), EVMU256::from(1000), EVMU256::from(1))]
+        );
+
+        // executeOperation(token, 10000, 5, initiator, "")
+        let data = [
+            FlashloanVenue::AaveV3.callback_selector().to_vec(),
+            token_word,
+            word(10000),
+            word(5),
+        ]
+        .concat();
+        let loans = FlashloanVenue::AaveV3.decode_callback(&data);
+        assert_eq!(loans[0].1, EVMU256::from(10000));
+        assert_eq!(loans[0].2, EVMU256::from(5));
+
+        // uniswapV2Call(sender, 997, 0, data)
+        let data = [
+            FlashloanVenue::UniswapV2.callback_selector().to_vec(),
+            word(0),
+            word(997),
+            word(0),
+        ]
+        .concat();
+        let loans = FlashloanVenue::UniswapV2.decode_callback(&data);
+        assert_eq!(
+            loans,
+            vec![("token0".to_string(), EVMU256::from(997), EVMU256::from(3))]
+        );
+    }
+}
@@ -1,4 +1,7 @@
 pub mod abi_decompiler;
 pub mod endpoints;
+pub mod fetch_lists;
 pub mod flashloan;
+pub mod flashloan_venue;
 pub mod onchain;
+pub mod proxy_resolver;
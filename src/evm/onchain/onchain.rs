@@ -9,10 +9,12 @@ use crate::evm::middlewares::middleware::{add_corpus, Middleware, MiddlewareType
 use crate::evm::mutator::AccessPattern;
 use crate::evm::onchain::abi_decompiler::fetch_abi_heimdall;
 use crate::evm::onchain::endpoints::OnChainConfig;
-use crate::evm::vm::IS_FAST_CALL;
+use crate::evm::onchain::fetch_lists::FetchLists;
+use crate::evm::vm::{EVMState, IS_FAST_CALL};
 use crate::generic_vm::vm_state::VMStateT;
 use crate::handle_contract_insertion;
 use crate::input::VMInputT;
+use crate::oracle::BugMetadata;
 use crate::state::{HasCaller, HasItyState};
 use crate::state_input::StagedVMState;
 use crypto::digest::Digest;
@@ -32,19 +34,37 @@ use crate::evm::blaz::builder::{ArtifactInfoMetadata, BuildJob};
 use crate::evm::corpus_initializer::ABIMap;
 use crate::evm::onchain::flashloan::register_borrow_txn;
 use crate::evm::types::{convert_u256_to_h160, EVMAddress, EVMU256};
+use bytes::Bytes;
 use itertools::Itertools;
 use revm_interpreter::{Host, Interpreter};
 use revm_primitives::{Bytecode, U256};
 use std::rc::Rc;
 use std::str::FromStr;
 use std::sync::Arc;
-use tracing::debug;
+use tracing::{debug, info};
 
 pub static mut BLACKLIST_ADDR: Option<HashSet<EVMAddress>> = None;
 pub static mut WHITELIST_ADDR: Option<HashSet<EVMAddress>> = None;
 
 const UNBOUND_THRESHOLD: usize = 30;
 
+/// Routers and pools never fuzzed, their storage is still fetched
+fn default_blacklist() -> HashSet<EVMAddress> {
+    HashSet::from([
+        EVMAddress::from_str("0x3cb4ca3c9dc0e02d252098eebb3871ac7a43c54d").unwrap(),
+        EVMAddress::from_str("0x6aed013308d847cb87502d86e7d9720b17b4c1f2").unwrap(),
+        EVMAddress::from_str("0x5a58505a96d1dbf8df91cb21b54419fc36e93fde").unwrap(),
+        EVMAddress::from_str("0x88e6a0c2ddd26feeb64f039a2c41296fcb3f5640").unwrap(),
+        EVMAddress::from_str("0xa40cac1b04d7491bdfb42ccac97dff25e0efb09e").unwrap(),
+        // uniswap router
+        EVMAddress::from_str("0xca143ce32fe78f1f7019d7d551a6402fc5350c73").unwrap(),
+        EVMAddress::from_str("0x7a250d5630b4cf539739df2c5dacb4c659f2488d").unwrap(),
+        // pancake router
+        EVMAddress::from_str("0x6CD71A07E72C514f5d511651F6808c6395353968").unwrap(),
+        EVMAddress::from_str("0x10ed43c718714eb63d5aa57b78b54704e256024e").unwrap(),
+    ])
+}
+
 pub struct OnChain<VS, I, S>
 where
     I: Input + VMInputT<VS, EVMAddress, EVMAddress, ConciseEVMInput>,
@@ -58,6 +78,20 @@ where
     pub locs: HashMap<(EVMAddress, usize), HashSet<EVMU256>>,
     pub endpoint: OnChainConfig,
     pub blacklist: HashSet<EVMAddress>,
+    /// Blacklisted addresses whose storage is no longer fetched, reads
+    /// returning the cached value or zero
+    pub stubbed: HashSet<EVMAddress>,
+    pub allowlist: HashSet<EVMAddress>,
+    /// Distinct slots fetched from an address, without any execution fetching
+    /// them adding to the corpus or finding a bug, after which it is stubbed.
+    /// 0 to disable
+    pub learn_threshold: usize,
+    pub fetched_slots: HashMap<EVMAddress, usize>,
+    /// Addresses fetched by an execution adding to the corpus or finding a bug
+    pub productive: HashSet<EVMAddress>,
+    pub fetched_in_execution: HashSet<EVMAddress>,
+    /// Corpus entries and bugs found before the current execution
+    pub last_progress: usize,
     pub storage_fetching: StorageFetchingMode,
     pub storage_all: HashMap<EVMAddress, Arc<HashMap<String, EVMU256>>>,
     pub storage_dump: HashMap<EVMAddress, Arc<HashMap<EVMU256, EVMU256>>>,
@@ -88,19 +122,7 @@ where
 {
     pub fn new(endpoint: OnChainConfig, storage_fetching: StorageFetchingMode) -> Self {
         unsafe {
-            BLACKLIST_ADDR = Some(HashSet::from([
-                EVMAddress::from_str("0x3cb4ca3c9dc0e02d252098eebb3871ac7a43c54d").unwrap(),
-                EVMAddress::from_str("0x6aed013308d847cb87502d86e7d9720b17b4c1f2").unwrap(),
-                EVMAddress::from_str("0x5a58505a96d1dbf8df91cb21b54419fc36e93fde").unwrap(),
-                EVMAddress::from_str("0x88e6a0c2ddd26feeb64f039a2c41296fcb3f5640").unwrap(),
-                EVMAddress::from_str("0xa40cac1b04d7491bdfb42ccac97dff25e0efb09e").unwrap(),
-                // uniswap router
-                EVMAddress::from_str("0xca143ce32fe78f1f7019d7d551a6402fc5350c73").unwrap(),
-                EVMAddress::from_str("0x7a250d5630b4cf539739df2c5dacb4c659f2488d").unwrap(),
-                // pancake router
-                EVMAddress::from_str("0x6CD71A07E72C514f5d511651F6808c6395353968").unwrap(),
-                EVMAddress::from_str("0x10ed43c718714eb63d5aa57b78b54704e256024e").unwrap(),
-            ]));
+            BLACKLIST_ADDR = Some(default_blacklist());
         }
         Self {
             loaded_data: Default::default(),
@@ -109,19 +131,14 @@ where
             calls: Default::default(),
             locs: Default::default(),
             endpoint,
-            blacklist: HashSet::from([
-                EVMAddress::from_str("0x3cb4ca3c9dc0e02d252098eebb3871ac7a43c54d").unwrap(),
-                EVMAddress::from_str("0x6aed013308d847cb87502d86e7d9720b17b4c1f2").unwrap(),
-                EVMAddress::from_str("0x5a58505a96d1dbf8df91cb21b54419fc36e93fde").unwrap(),
-                EVMAddress::from_str("0x88e6a0c2ddd26feeb64f039a2c41296fcb3f5640").unwrap(),
-                EVMAddress::from_str("0xa40cac1b04d7491bdfb42ccac97dff25e0efb09e").unwrap(),
-                // uniswap router
-                EVMAddress::from_str("0xca143ce32fe78f1f7019d7d551a6402fc5350c73").unwrap(),
-                EVMAddress::from_str("0x7a250d5630b4cf539739df2c5dacb4c659f2488d").unwrap(),
-                // pancake router
-                EVMAddress::from_str("0x6CD71A07E72C514f5d511651F6808c6395353968").unwrap(),
-                EVMAddress::from_str("0x10ed43c718714eb63d5aa57b78b54704e256024e").unwrap(),
-            ]),
+            blacklist: default_blacklist(),
+            stubbed: Default::default(),
+            allowlist: Default::default(),
+            learn_threshold: 0,
+            fetched_slots: Default::default(),
+            productive: Default::default(),
+            fetched_in_execution: Default::default(),
+            last_progress: 0,
             storage_all: Default::default(),
             storage_dump: Default::default(),
             builder: None,
@@ -140,6 +157,71 @@ where
         }
         self.blacklist.insert(address);
     }
+
+    /// Blacklist `address` and stop fetching its storage
+    pub fn stub(&mut self, address: EVMAddress) {
+        self.add_blacklist(address);
+        self.stubbed.insert(address);
+    }
+
+    /// Apply the lists of the campaign, the allowlist overriding the default
+    /// blacklist
+    pub fn set_fetch_lists(&mut self, lists: FetchLists, learn_threshold: usize) {
+        for address in &lists.allow {
+            unsafe {
+                BLACKLIST_ADDR.as_mut().unwrap().remove(address);
+            }
+            self.blacklist.remove(address);
+        }
+        for address in lists.deny {
+            if !lists.allow.contains(&address) {
+                self.stub(address);
+            }
+        }
+        self.allowlist.extend(lists.allow);
+        self.learn_threshold = learn_threshold;
+    }
+
+    /// Slot of a stubbed address, as read before it was stubbed and zero if
+    /// it was never fetched
+    fn stubbed_slot(&mut self, address: EVMAddress, slot: EVMU256) -> EVMU256 {
+        match self.storage_dump.get(&address) {
+            Some(storage) => storage.get(&slot).cloned().unwrap_or_default(),
+            None => self.endpoint.get_contract_slot(address, slot, true),
+        }
+    }
+
+    fn on_fetch(&mut self, address: EVMAddress, slot: EVMU256) {
+        if self.learn_threshold == 0
+            || self.allowlist.contains(&address)
+            || self.productive.contains(&address)
+            || !self.loaded_data.insert((address, slot))
+        {
+            return;
+        }
+        *self.fetched_slots.entry(address).or_insert(0) += 1;
+        self.fetched_in_execution.insert(address);
+    }
+
+    /// Called with the corpus entries and bugs found so far, before each
+    /// execution
+    fn learn(&mut self, progress: usize) {
+        let fetched = std::mem::take(&mut self.fetched_in_execution);
+        if progress > self.last_progress {
+            self.productive.extend(fetched);
+        } else {
+            for address in fetched {
+                if self.fetched_slots[&address] >= self.learn_threshold {
+                    info!(
+                        "Stop fetching storage of {:?}, {} slots fetched without new coverage",
+                        address, self.fetched_slots[&address]
+                    );
+                    self.stub(address);
+                }
+            }
+        }
+        self.last_progress = progress;
+    }
 }
 
 pub fn keccak_hex(data: EVMU256) -> String {
@@ -224,6 +306,10 @@ where
                     }};
                     () => {};
                 }
+                if self.stubbed.contains(&address) {
+                    host.next_slot = self.stubbed_slot(address, slot_idx);
+                    return;
+                }
                 host.next_slot = match self.storage_fetching {
                     StorageFetchingMode::Dump => {
                         load_data!(fetch_storage_dump, storage_dump, slot_idx)
@@ -234,6 +320,7 @@ where
                         force_cache!(self.locs, slot_idx),
                     ),
                 };
+                self.on_fetch(address, slot_idx);
             }
             #[cfg(feature = "real_balance")]
             // BALANCE
@@ -269,8 +356,8 @@ where
             // NUMBER
             0x43 => {
                 if host.env().block.number == EVMU256::ZERO {
-                    host.env().block.number =
-                        EVMU256::from_str(&self.endpoint.block_number).unwrap();
+                    let fork_block_number = EVMU256::from_str(&self.endpoint.block_number).unwrap();
+                    host.env().block.number = host.chain_profile.block_number(fork_block_number);
                 }
             }
             #[cfg(feature = "real_block_env")]
@@ -444,6 +531,7 @@ where
                 }
                 let target = if is_proxy_call { caller } else { address_h160 };
                 state.add_address(&target);
+                host.classify_contract(target, state);
 
                 // notify flashloan and blacklisting flashloan addresses
                 #[cfg(feature = "flashloan_v2")]
@@ -514,7 +602,95 @@ where
         }
     }
 
+    unsafe fn before_execute(
+        &mut self,
+        interp: Option<&mut Interpreter>,
+        host: &mut FuzzHost<VS, I, S, SC>,
+        state: &mut S,
+        is_step: bool,
+        data: &mut Bytes,
+        evm_state: &mut EVMState,
+    ) {
+        if self.learn_threshold == 0 {
+            return;
+        }
+        let bugs = state
+            .metadata_map()
+            .get::<BugMetadata>()
+            .map_or(0, |bugs| bugs.found.len());
+        self.learn(state.corpus().count() + bugs);
+    }
+
     fn get_type(&self) -> MiddlewareType {
         MiddlewareType::OnChain
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::evm::onchain::endpoints::Chain;
+    use crate::evm::types::EVMFuzzState;
+
+    #[test]
+    fn test_learn_blacklist() {
+        let mut onchain = OnChain::<EVMState, EVMInput, EVMFuzzState>::new(
+            OnChainConfig::new(Chain::ETH, 1),
+            StorageFetchingMode::OneByOne,
+        );
+        let target = EVMAddress::from_slice(&[1; 20]);
+        let token = EVMAddress::from_slice(&[2; 20]);
+        let pool = EVMAddress::from_slice(&[3; 20]);
+        let router = EVMAddress::from_str("0x7a250d5630b4cf539739df2c5dacb4c659f2488d").unwrap();
+        onchain.set_fetch_lists(
+            FetchLists {
+                deny: HashSet::from([pool]),
+                allow: HashSet::from([target, router]),
+            },
+            2,
+        );
+        assert!(onchain.stubbed.contains(&pool));
+        assert!(!onchain.blacklist.contains(&router));
+
+        // executions fetching the token and the target find nothing new
+        for slot in [0, 1, 1] {
+            onchain.on_fetch(target, EVMU256::from(slot));
+            onchain.on_fetch(token, EVMU256::from(slot));
+            onchain.learn(0);
+        }
+        assert!(onchain.stubbed.contains(&token));
+        assert!(!onchain.stubbed.contains(&target));
+
+        // the pool was fetched by an execution adding to the corpus
+        onchain.stubbed.remove(&pool);
+        for (slot, progress) in [(0, 1), (1, 1), (2, 1)] {
+            onchain.on_fetch(pool, EVMU256::from(slot));
+            onchain.learn(progress);
+        }
+        assert!(onchain.productive.contains(&pool));
+        assert!(!onchain.stubbed.contains(&pool));
+    }
+
+    #[test]
+    fn test_stubbed_reads() {
+        let mut onchain = OnChain::<EVMState, EVMInput, EVMFuzzState>::new(
+            OnChainConfig::new(Chain::ETH, 1),
+            StorageFetchingMode::Dump,
+        );
+        let token = EVMAddress::from_slice(&[2; 20]);
+        let (fetched, unfetched) = (EVMU256::from(1), EVMU256::from(2));
+        onchain.storage_dump.insert(
+            token,
+            Arc::new(HashMap::from([(fetched, EVMU256::from(1000))])),
+        );
+        let before = onchain.storage_dump[&token][&fetched];
+
+        // reads of a stubbed address see the storage fetched before it was
+        // stubbed, without fetching anything new
+        onchain.stub(token);
+        assert_eq!(onchain.stubbed_slot(token, fetched), before);
+        assert_eq!(onchain.stubbed_slot(token, unfetched), EVMU256::ZERO);
+        let other = EVMAddress::from_slice(&[3; 20]);
+        assert_eq!(onchain.stubbed_slot(other, fetched), EVMU256::ZERO);
+    }
+}
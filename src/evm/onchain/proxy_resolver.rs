@@ -0,0 +1,345 @@
+//! Resolve the implementations of onchain proxies, so that the functions only
+//! implemented behind a proxy are called on the proxy.
+use crate::evm::contract_tags::{
+    minimal_clone_implementation, ProxyKind, EIP1822_PROXIABLE_SLOT, EIP1967_BEACON_SLOT,
+    EIP1967_IMPLEMENTATION_SLOT, OZ_LEGACY_IMPLEMENTATION_SLOT,
+};
+use crate::evm::contract_utils::{ABIConfig, ContractLoader};
+use crate::evm::onchain::endpoints::OnChainConfig;
+use crate::evm::proxy::IMPLEMENTATION_SELECTOR;
+use crate::evm::types::{convert_u256_to_h160, EVMAddress, EVMU256};
+use std::collections::HashSet;
+use tracing::debug;
+
+const FACETS_SELECTOR: [u8; 4] = [0x7a, 0x0e, 0xd6, 0x27]; // facets()
+
+/// Chain the proxies are resolved on
+pub trait ChainReader {
+    fn code(&mut self, address: EVMAddress) -> Vec<u8>;
+    fn storage(&mut self, address: EVMAddress, slot: EVMU256) -> EVMU256;
+    /// Output of the call, None if it reverts
+    fn static_call(&mut self, address: EVMAddress, data: Vec<u8>) -> Option<Vec<u8>>;
+}
+
+impl ChainReader for OnChainConfig {
+    fn code(&mut self, address: EVMAddress) -> Vec<u8> {
+        self.get_contract_code(address, false).bytes().to_vec()
+    }
+
+    fn storage(&mut self, address: EVMAddress, slot: EVMU256) -> EVMU256 {
+        self.get_contract_slot(address, slot, false)
+    }
+
+    fn static_call(&mut self, address: EVMAddress, data: Vec<u8>) -> Option<Vec<u8>> {
+        self.eth_call(address, &data)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Implementation {
+    pub address: EVMAddress,
+    /// Selectors routed to it by a diamond, None if it implements every call
+    pub selectors: Option<HashSet<[u8; 4]>>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ResolvedProxy {
+    pub kind: ProxyKind,
+    pub implementations: Vec<Implementation>,
+}
+
+impl ResolvedProxy {
+    fn single(kind: ProxyKind, address: EVMAddress) -> Self {
+        Self {
+            kind,
+            implementations: vec![Implementation {
+                address,
+                selectors: None,
+            }],
+        }
+    }
+}
+
+fn non_zero(address: EVMAddress) -> Option<EVMAddress> {
+    (!address.is_zero()).then_some(address)
+}
+
+fn slot_address<R: ChainReader>(
+    reader: &mut R,
+    address: EVMAddress,
+    slot: &str,
+) -> Option<EVMAddress> {
+    let slot = EVMU256::try_from_be_slice(&hex::decode(slot).unwrap()).unwrap();
+    non_zero(convert_u256_to_h160(reader.storage(address, slot)))
+}
+
+/// Facets returned by `facets()`, an ABI-encoded `(address, bytes4[])[]`
+pub fn decode_facets(out: &[u8]) -> Option<Vec<Implementation>> {
+    // offsets and lengths never exceed the output, which also rules out
+    // overflows below
+    let word = |pos: usize| -> Option<usize> {
+        let word = out.get(pos..pos + 32)?;
+        if word[..24].iter().any(|byte| *byte != 0) {
+            return None;
+        }
+        let value = u64::from_be_bytes(word[24..].try_into().unwrap()) as usize;
+        (value <= out.len()).then_some(value)
+    };
+    let array = word(0)?;
+    let items = array + 32;
+    let mut facets = vec![];
+    for i in 0..word(array)? {
+        let facet = items + word(items + i * 32)?;
+        let address = EVMAddress::from_slice(out.get(facet + 12..facet + 32)?);
+        let selectors = facet + word(facet + 32)?;
+        let mut facet_selectors = HashSet::new();
+        for j in 0..word(selectors)? {
+            let selector = selectors + 32 + j * 32;
+            facet_selectors.insert(<[u8; 4]>::try_from(out.get(selector..selector + 4)?).unwrap());
+        }
+        facets.push(Implementation {
+            address,
+            selectors: Some(facet_selectors),
+        });
+    }
+    Some(facets)
+}
+
+/// Implementations of `address` if it is a proxy
+pub fn resolve_proxy<R: ChainReader>(reader: &mut R, address: EVMAddress) -> Option<ResolvedProxy> {
+    let code = reader.code(address);
+    if let Some(implementation) = minimal_clone_implementation(&code) {
+        return Some(ResolvedProxy::single(
+            ProxyKind::MinimalClone,
+            implementation,
+        ));
+    }
+    // DELEGATECALL
+    if !code.contains(&0xf4) {
+        return None;
+    }
+
+    for (kind, slot) in [
+        (ProxyKind::EIP1967, EIP1967_IMPLEMENTATION_SLOT),
+        (ProxyKind::EIP1822, EIP1822_PROXIABLE_SLOT),
+        (ProxyKind::OZLegacy, OZ_LEGACY_IMPLEMENTATION_SLOT),
+    ] {
+        if let Some(implementation) = slot_address(reader, address, slot) {
+            return Some(ResolvedProxy::single(kind, implementation));
+        }
+    }
+    if let Some(beacon) = slot_address(reader, address, EIP1967_BEACON_SLOT) {
+        let implementation = reader
+            .static_call(beacon, IMPLEMENTATION_SELECTOR.to_vec())
+            .filter(|out| out.len() >= 32)
+            .and_then(|out| non_zero(EVMAddress::from_slice(&out[12..32])))?;
+        return Some(ResolvedProxy::single(ProxyKind::Beacon, implementation));
+    }
+
+    let facets = reader
+        .static_call(address, FACETS_SELECTOR.to_vec())
+        .and_then(|out| decode_facets(&out))?;
+    (!facets.is_empty()).then_some(ResolvedProxy {
+        kind: ProxyKind::Diamond,
+        implementations: facets,
+    })
+}
+
+/// Functions of the ABI of `implementation` reachable through the proxy
+pub fn implementation_abis(
+    onchain: &mut OnChainConfig,
+    implementation: &Implementation,
+) -> Vec<ABIConfig> {
+    let abi = match onchain.fetch_abi(implementation.address) {
+        Some(abi) => abi,
+        None => {
+            debug!(
+                "ABI not found for implementation {:?}",
+                implementation.address
+            );
+            return vec![];
+        }
+    };
+    ContractLoader::parse_abi_str(&abi)
+        .into_iter()
+        .filter(|abi| !abi.is_constructor)
+        .filter(|abi| {
+            implementation
+                .selectors
+                .as_ref()
+                .map_or(true, |selectors| selectors.contains(&abi.function))
+        })
+        .collect()
+}
+
+/// Add the functions of the implementations of `address`, if it is a proxy,
+/// missing from its ABI
+pub fn merge_implementation_abis(
+    onchain: &mut OnChainConfig,
+    address: EVMAddress,
+    abis: &mut Vec<ABIConfig>,
+) {
+    let proxy = match resolve_proxy(onchain, address) {
+        Some(proxy) => proxy,
+        None => return,
+    };
+    debug!("{:?} is a {:?} proxy", address, proxy.kind);
+    let mut known: HashSet<[u8; 4]> = abis.iter().map(|abi| abi.function).collect();
+    for implementation in &proxy.implementations {
+        for abi in implementation_abis(onchain, implementation) {
+            if known.insert(abi.function) {
+                abis.push(abi);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::evm::host::FuzzHost;
+    use crate::evm::input::{ConciseEVMInput, EVMInput};
+    use crate::evm::proxy::{beacon_creation_code, proxy_creation_code};
+    use crate::evm::types::{generate_random_address, EVMFuzzState};
+    use crate::evm::vm::{EVMExecutor, EVMState};
+    use crate::generic_vm::vm_executor::GenericVM;
+    use crate::state::FuzzState;
+    use bytes::Bytes;
+    use libafl::prelude::StdScheduler;
+    use revm_primitives::Bytecode;
+    use std::path::Path;
+
+    type LocalExecutor =
+        EVMExecutor<EVMInput, EVMFuzzState, EVMState, ConciseEVMInput, StdScheduler<EVMFuzzState>>;
+
+    /// Proxies deployed on a local executor
+    struct LocalChain {
+        executor: LocalExecutor,
+        state: EVMFuzzState,
+    }
+
+    impl LocalChain {
+        fn new() -> Self {
+            let mut state: EVMFuzzState = FuzzState::new(0, None);
+            let path = Path::new("work_dir");
+            if !path.exists() {
+                std::fs::create_dir(path).unwrap();
+            }
+            let executor = EVMExecutor::new(
+                FuzzHost::new(StdScheduler::new(), "work_dir".to_string()),
+                generate_random_address(&mut state),
+            );
+            Self { executor, state }
+        }
+
+        fn deploy(&mut self, code: Vec<u8>) -> EVMAddress {
+            let address = generate_random_address(&mut self.state);
+            self.executor
+                .deploy(
+                    Bytecode::new_raw(Bytes::from(code)),
+                    None,
+                    address,
+                    &mut self.state,
+                )
+                .unwrap()
+        }
+    }
+
+    impl ChainReader for LocalChain {
+        fn code(&mut self, address: EVMAddress) -> Vec<u8> {
+            self.executor
+                .host
+                .code
+                .get(&address)
+                .map(|code| code.bytecode().to_vec())
+                .unwrap_or_default()
+        }
+
+        fn storage(&mut self, address: EVMAddress, slot: EVMU256) -> EVMU256 {
+            self.executor
+                .host
+                .evmstate
+                .get(&address)
+                .and_then(|slots| slots.get(&slot).cloned())
+                .unwrap_or_default()
+        }
+
+        fn static_call(&mut self, address: EVMAddress, data: Vec<u8>) -> Option<Vec<u8>> {
+            self.executor
+                .host
+                .probe_static_call(address, Bytes::from(data), &mut self.state)
+        }
+    }
+
+    #[test]
+    fn test_resolve_proxy() {
+        let mut chain = LocalChain::new();
+        let implementation = EVMAddress::from_slice(&[0x11; 20]);
+        for kind in [ProxyKind::EIP1967, ProxyKind::EIP1822, ProxyKind::OZLegacy] {
+            let proxy = chain.deploy(proxy_creation_code(kind, implementation));
+            assert_eq!(
+                resolve_proxy(&mut chain, proxy),
+                Some(ResolvedProxy::single(kind, implementation))
+            );
+        }
+
+        let beacon = chain.deploy(beacon_creation_code(implementation));
+        let proxy = chain.deploy(proxy_creation_code(ProxyKind::Beacon, beacon));
+        assert_eq!(
+            resolve_proxy(&mut chain, proxy),
+            Some(ResolvedProxy::single(ProxyKind::Beacon, implementation))
+        );
+        // the beacon does not delegate calls
+        assert_eq!(resolve_proxy(&mut chain, beacon), None);
+
+        let clone = chain.deploy(
+            hex::decode(
+                "602d8060093d393df3363d3d373d3d3d363d7311111111111111111111111111111111111111115af43d82803e903d91602b57fd5bf3",
+            )
+            .unwrap(),
+        );
+        assert_eq!(
+            resolve_proxy(&mut chain, clone),
+            Some(ResolvedProxy::single(
+                ProxyKind::MinimalClone,
+                implementation
+            ))
+        );
+    }
+
+    #[test]
+    fn test_decode_facets() {
+        let word = |value: usize| {
+            let mut word = vec![0; 32];
+            word[24..].copy_from_slice(&(value as u64).to_be_bytes());
+            word
+        };
+        let selector = |sig: [u8; 4]| [sig.to_vec(), vec![0; 28]].concat();
+        // [(0x11.., [facets()]), (0x22.., [implementation(), facets()])]
+        let out = [
+            word(0x20),
+            word(2),
+            word(0x40),
+            word(0xc0),
+            [vec![0; 12], vec![0x11; 20]].concat(),
+            word(0x40),
+            word(1),
+            selector(FACETS_SELECTOR),
+            [vec![0; 12], vec![0x22; 20]].concat(),
+            word(0x40),
+            word(2),
+            selector(IMPLEMENTATION_SELECTOR),
+            selector(FACETS_SELECTOR),
+        ]
+        .concat();
+        let facets = decode_facets(&out).unwrap();
+        assert_eq!(facets.len(), 2);
+        assert_eq!(facets[0].address, EVMAddress::from_slice(&[0x11; 20]));
+        assert_eq!(
+            facets[1].selectors,
+            Some(HashSet::from([IMPLEMENTATION_SELECTOR, FACETS_SELECTOR]))
+        );
+        // truncated outputs are rejected
+        assert_eq!(decode_facets(&out[..out.len() - 32]), None);
+    }
+}
@@ -44,6 +44,9 @@ pub struct EVMBugResult {
     pub issue_source: Option<String>,
     pub sourcemap: Option<SourceMapLocation>,
     pub bug_idx: u64,
+    /// Reported by typed bug events
+    pub severity: Option<String>,
+    pub context: Vec<(String, String)>,
 }
 
 impl EVMBugResult {
@@ -55,6 +58,12 @@ impl EVMBugResult {
             "sourcemap": self.sourcemap,
             "issue_source": self.issue_source,
             "bug_idx": self.bug_idx,
+            "severity": self.severity,
+            "context": self
+                .context
+                .iter()
+                .map(|(name, value)| serde_json::json!({ "name": name, "value": value }))
+                .collect::<Vec<_>>(),
         })
     }
 
@@ -73,6 +82,8 @@ impl EVMBugResult {
             sourcemap,
             issue_source,
             bug_idx,
+            severity: None,
+            context: vec![],
         }
     }
 
@@ -89,9 +100,21 @@ impl EVMBugResult {
             issue_source: None,
             sourcemap: None,
             bug_idx,
+            severity: None,
+            context: vec![],
         }
     }
 
+    pub fn with_context(
+        mut self,
+        severity: Option<String>,
+        context: Vec<(String, String)>,
+    ) -> Self {
+        self.severity = severity;
+        self.context = context;
+        self
+    }
+
     pub fn push_to_output(&self) {
         unsafe {
             ORACLE_OUTPUT.push(self.to_value());
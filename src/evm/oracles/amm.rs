@@ -0,0 +1,373 @@
+use crate::evm::contract_tags::ContractTag;
+use crate::evm::contract_utils::set_hash;
+use crate::evm::input::{ConciseEVMInput, EVMInput};
+use crate::evm::oracle::EVMBugResult;
+use crate::evm::oracles::AMM_INVARIANT_BUG_IDX;
+use crate::evm::types::{as_u64, EVMAddress, EVMFuzzState, EVMOracleCtx, EVMU256, EVMU512};
+use crate::evm::vm::EVMState;
+use crate::oracle::Oracle;
+use crate::state::HasExecutionResult;
+use bytes::Bytes;
+use libafl::prelude::HasMetadata;
+use libafl_bolts::impl_serdeany;
+use revm_primitives::Bytecode;
+use serde::{Deserialize, Serialize};
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{HashMap, HashSet};
+use std::hash::{Hash, Hasher};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum AMMKind {
+    /// Constant product pair (Uniswap V2 and forks)
+    ConstantProduct,
+    /// Concentrated liquidity pool (Uniswap V3 and forks)
+    ConcentratedLiquidity,
+    /// Curve-style stable pool
+    StableSwap,
+}
+
+impl AMMKind {
+    /// Guess the kind of pool from the names of its functions
+    pub fn detect(abi_names: &HashSet<String>) -> Option<Self> {
+        let has = |names: &[&str]| names.iter().all(|n| abi_names.contains(*n));
+        if has(&["slot0", "liquidity", "swap"]) {
+            Some(AMMKind::ConcentratedLiquidity)
+        } else if has(&["get_virtual_price", "exchange"]) {
+            Some(AMMKind::StableSwap)
+        } else if has(&["getReserves", "swap"]) {
+            Some(AMMKind::ConstantProduct)
+        } else {
+            None
+        }
+    }
+
+    pub fn from_tags(tags: &HashSet<ContractTag>) -> Option<Self> {
+        if tags.contains(&ContractTag::UniswapV3Pool) {
+            Some(AMMKind::ConcentratedLiquidity)
+        } else if tags.contains(&ContractTag::UniswapV2Pair) {
+            Some(AMMKind::ConstantProduct)
+        } else {
+            None
+        }
+    }
+
+    /// View functions that read the state of the pool
+    pub fn state_getters(&self) -> Vec<Bytes> {
+        let getters: &[&str] = match self {
+            AMMKind::ConstantProduct => &["getReserves()"],
+            AMMKind::ConcentratedLiquidity => &["slot0()", "liquidity()"],
+            AMMKind::StableSwap => &["get_virtual_price()"],
+        };
+        getters.iter().map(|sig| selector(sig)).collect()
+    }
+
+    /// View functions returning the spot price (or what it is derived from)
+    pub fn price_getters(&self) -> Vec<Bytes> {
+        let getter = match self {
+            AMMKind::ConstantProduct => "getReserves()",
+            AMMKind::ConcentratedLiquidity => "slot0()",
+            AMMKind::StableSwap => "get_virtual_price()",
+        };
+        vec![selector(getter)]
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct AMMPool {
+    pub kind: AMMKind,
+    /// Slots read by the state getters, learnt by probing them. Empty if
+    /// probing failed, then any storage change of the pool is considered.
+    pub slots: Vec<EVMU256>,
+}
+
+impl AMMPool {
+    fn changed(&self, addr: &EVMAddress, pre: &EVMState, post: &EVMState) -> bool {
+        let (pre, post) = (pre.state.get(addr), post.state.get(addr));
+        if self.slots.is_empty() {
+            return pre != post;
+        }
+        self.slots
+            .iter()
+            .any(|slot| pre.and_then(|s| s.get(slot)) != post.and_then(|s| s.get(slot)))
+    }
+}
+
+/// AMM pools found among the contracts
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct AMMPools {
+    pub pools: HashMap<EVMAddress, AMMPool>,
+}
+
+impl_serdeany!(AMMPools);
+
+pub fn selector(signature: &str) -> Bytes {
+    let mut hash = [0; 4];
+    set_hash(signature, &mut hash);
+    Bytes::from(hash.to_vec())
+}
+
+pub(crate) fn word(out: &[u8], idx: usize) -> Option<EVMU256> {
+    out.get(idx * 32..idx * 32 + 32)
+        .map(|w| EVMU256::try_from_be_slice(w).unwrap())
+}
+
+pub(crate) fn to_f64(value: EVMU256) -> f64 {
+    value
+        .as_limbs()
+        .iter()
+        .rev()
+        .fold(0.0, |acc, limb| acc * 2f64.powi(64) + *limb as f64)
+}
+
+/// Slack for the float error of the tick computed from sqrtPriceX96
+const TICK_EPSILON: f64 = 1e-6;
+
+/// Whether `tick` is the tick of a Uniswap V3 pool at `sqrt_price`: the
+/// floor of its log, or the tick below when the price is exactly on the
+/// boundary crossed by a swap going down
+pub(crate) fn tick_matches(tick: i32, sqrt_price: EVMU256) -> bool {
+    let price = (to_f64(sqrt_price) / 2f64.powi(96)).powi(2);
+    let offset = price.ln() / 1.0001f64.ln() - tick as f64;
+    (-TICK_EPSILON..=1.0 + TICK_EPSILON).contains(&offset)
+}
+
+/// Checks that the invariant of each AMM pool touched by the transaction
+/// still holds:
+/// - constant product: k per LP share never decreases (fees only grow it)
+/// - concentrated liquidity: the tick matches sqrtPrice, and a price move
+///   brings in the token that got more expensive
+/// - stable swap: the virtual price never decreases
+pub struct AMMInvariantOracle {
+    balance_of: Bytes,
+}
+
+impl Default for AMMInvariantOracle {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl AMMInvariantOracle {
+    pub fn new() -> Self {
+        Self {
+            balance_of: selector("balanceOf(address)"),
+        }
+    }
+
+    fn check_constant_product(
+        &self,
+        ctx: &mut EVMOracleCtx<'_>,
+        pool: EVMAddress,
+    ) -> Option<String> {
+        let calls = vec![
+            (pool, selector("getReserves()")),
+            (pool, selector("totalSupply()")),
+        ];
+        let (pre, post) = (ctx.call_pre_batch(&calls), ctx.call_post_batch(&calls));
+        let pre_k = EVMU512::from(word(&pre[0], 0)?) * EVMU512::from(word(&pre[0], 1)?);
+        let post_k = EVMU512::from(word(&post[0], 0)?) * EVMU512::from(word(&post[0], 1)?);
+        let (pre_supply, post_supply) = (word(&pre[1], 0)?, word(&post[1], 0)?);
+        if pre_supply == EVMU256::ZERO || post_supply == EVMU256::ZERO {
+            return None;
+        }
+        let (pre_supply, post_supply) = (EVMU512::from(pre_supply), EVMU512::from(post_supply));
+
+        // k / supply^2, allowing 0.1% for the protocol fee minted to feeTo
+        let lhs = post_k
+            .checked_mul(pre_supply)?
+            .checked_mul(pre_supply)?
+            .checked_mul(EVMU512::from(1000))?;
+        let rhs = pre_k
+            .checked_mul(post_supply)?
+            .checked_mul(post_supply)?
+            .checked_mul(EVMU512::from(999))?;
+        if lhs < rhs {
+            Some(format!(
+                "k per share decreased, k: {} -> {}, total supply: {} -> {}",
+                pre_k, post_k, pre_supply, post_supply
+            ))
+        } else {
+            None
+        }
+    }
+
+    fn check_concentrated_liquidity(
+        &self,
+        ctx: &mut EVMOracleCtx<'_>,
+        pool: EVMAddress,
+    ) -> Option<String> {
+        let tokens = ctx.call_post_batch(&vec![
+            (pool, selector("token0()")),
+            (pool, selector("token1()")),
+        ]);
+        let token = |out: &Vec<u8>| {
+            word(out, 0).map(|w| EVMAddress::from_slice(&w.to_be_bytes::<32>()[12..]))
+        };
+        let (token0, token1) = (token(&tokens[0])?, token(&tokens[1])?);
+
+        let balance_of =
+            Bytes::from([self.balance_of.to_vec(), vec![0; 12], pool.0.to_vec()].concat());
+        let calls = vec![
+            (pool, selector("slot0()")),
+            (token0, balance_of.clone()),
+            (token1, balance_of),
+        ];
+        let (pre, post) = (ctx.call_pre_batch(&calls), ctx.call_post_batch(&calls));
+
+        let sqrt_price = word(&post[0], 0)?;
+        if sqrt_price == EVMU256::ZERO {
+            return None;
+        }
+        let tick = as_u64(word(&post[0], 1)?) as u32 as i32;
+        if !tick_matches(tick, sqrt_price) {
+            let price = (to_f64(sqrt_price) / 2f64.powi(96)).powi(2);
+            return Some(format!(
+                "tick {} does not match sqrtPriceX96 {} (tick {:.0})",
+                tick,
+                sqrt_price,
+                (price.ln() / 1.0001f64.ln()).floor()
+            ));
+        }
+
+        let pre_sqrt_price = word(&pre[0], 0)?;
+        let (pre0, pre1) = (word(&pre[1], 0)?, word(&pre[2], 0)?);
+        let (post0, post1) = (word(&post[1], 0)?, word(&post[2], 0)?);
+        if sqrt_price > pre_sqrt_price && post1 < pre1 {
+            Some(format!(
+                "price of token0 increased but token1 balance decreased: {} -> {}",
+                pre1, post1
+            ))
+        } else if sqrt_price < pre_sqrt_price && post0 < pre0 {
+            Some(format!(
+                "price of token0 decreased but token0 balance decreased: {} -> {}",
+                pre0, post0
+            ))
+        } else {
+            None
+        }
+    }
+
+    fn check_stable_swap(&self, ctx: &mut EVMOracleCtx<'_>, pool: EVMAddress) -> Option<String> {
+        let calls = vec![(pool, selector("get_virtual_price()"))];
+        let (pre, post) = (ctx.call_pre_batch(&calls), ctx.call_post_batch(&calls));
+        let (pre_price, post_price) = (word(&pre[0], 0)?, word(&post[0], 0)?);
+        // tolerate rounding of the invariant
+        if post_price < pre_price - pre_price / EVMU256::from(10_000_000_000u64) {
+            Some(format!(
+                "virtual price decreased: {} -> {}",
+                pre_price, post_price
+            ))
+        } else {
+            None
+        }
+    }
+}
+
+impl
+    Oracle<
+        EVMState,
+        EVMAddress,
+        Bytecode,
+        Bytes,
+        EVMAddress,
+        EVMU256,
+        Vec<u8>,
+        EVMInput,
+        EVMFuzzState,
+        ConciseEVMInput,
+    > for AMMInvariantOracle
+{
+    fn transition(&self, _ctx: &mut EVMOracleCtx<'_>, _stage: u64) -> u64 {
+        0
+    }
+
+    fn oracle(&self, ctx: &mut EVMOracleCtx<'_>, _stage: u64) -> Vec<u64> {
+        let pools = match ctx.fuzz_state.metadata_map().get::<AMMPools>() {
+            Some(pools) => pools.pools.clone(),
+            None => return vec![],
+        };
+
+        let mut violations = vec![];
+        for (addr, pool) in pools {
+            if !pool.changed(&addr, ctx.pre_state, &ctx.post_state) {
+                continue;
+            }
+            let violation = match pool.kind {
+                AMMKind::ConstantProduct => self.check_constant_product(ctx, addr),
+                AMMKind::ConcentratedLiquidity => self.check_concentrated_liquidity(ctx, addr),
+                AMMKind::StableSwap => self.check_stable_swap(ctx, addr),
+            };
+            if let Some(violation) = violation {
+                let mut hasher = DefaultHasher::new();
+                addr.hash(&mut hasher);
+                let bug_idx = hasher.finish() << (8 + AMM_INVARIANT_BUG_IDX);
+
+                EVMBugResult::new_simple(
+                    "amm_invariant".to_string(),
+                    bug_idx,
+                    format!("{:?} ({:?}), {}\n", addr, pool.kind, violation),
+                    ConciseEVMInput::from_input(ctx.input, ctx.fuzz_state.get_execution_result()),
+                )
+                .push_to_output();
+                violations.push(bug_idx);
+            }
+        }
+        violations
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_detect() {
+        let names = |names: &[&str]| names.iter().map(|n| n.to_string()).collect::<HashSet<_>>();
+        assert_eq!(
+            AMMKind::detect(&names(&["getReserves", "swap", "skim", "sync"])),
+            Some(AMMKind::ConstantProduct)
+        );
+        assert_eq!(
+            AMMKind::detect(&names(&["slot0", "liquidity", "swap", "flash"])),
+            Some(AMMKind::ConcentratedLiquidity)
+        );
+        assert_eq!(
+            AMMKind::detect(&names(&["get_virtual_price", "exchange", "balances"])),
+            Some(AMMKind::StableSwap)
+        );
+        assert_eq!(AMMKind::detect(&names(&["transfer", "approve"])), None);
+        assert_eq!(
+            AMMKind::from_tags(&HashSet::from([
+                ContractTag::ERC20,
+                ContractTag::UniswapV2Pair
+            ])),
+            Some(AMMKind::ConstantProduct)
+        );
+        // getReserves()
+        assert_eq!(
+            selector("getReserves()").to_vec(),
+            vec![0x09, 0x02, 0xf1, 0xac]
+        );
+    }
+
+    #[test]
+    fn test_to_f64() {
+        let q96 = EVMU256::from(1) << 96;
+        assert_eq!(to_f64(q96), 2f64.powi(96));
+        assert_eq!(to_f64(EVMU256::from(12345)), 12345.0);
+    }
+
+    #[test]
+    fn test_tick_matches() {
+        let q96 = EVMU256::from(1) << 96;
+        // on the boundary of tick 0, after a swap going up or down
+        assert!(tick_matches(0, q96));
+        assert!(tick_matches(-1, q96));
+        assert!(!tick_matches(1, q96));
+        assert!(!tick_matches(-2, q96));
+        // a price just below 1.0001^10 is within tick 9
+        let sqrt_price = q96 + (q96 / EVMU256::from(2000)) - EVMU256::from(1);
+        assert!(tick_matches(9, sqrt_price));
+        assert!(!tick_matches(10, sqrt_price));
+    }
+}
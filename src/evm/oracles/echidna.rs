@@ -1,4 +1,6 @@
+use crate::evm::contract_utils::ABIConfig;
 use crate::evm::input::{ConciseEVMInput, EVMInput};
+use crate::evm::onchain::proxy_resolver::decode_facets;
 use crate::evm::oracle::EVMBugResult;
 use crate::evm::oracles::{ECHIDNA_BUG_IDX, FUNCTION_BUG_IDX};
 use crate::evm::types::{EVMAddress, EVMFuzzState, EVMOracleCtx, EVMU256};
@@ -9,15 +11,113 @@ use crate::state::HasExecutionResult;
 use bytes::Bytes;
 use itertools::Itertools;
 use revm_primitives::Bytecode;
-use std::collections::HashMap;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{HashMap, HashSet};
+use std::hash::{Hash, Hasher};
+
+/// Echidna properties return false or revert when violated
+pub const ECHIDNA_PREFIX: &str = "echidna_";
+/// Foundry invariants revert when violated
+pub const INVARIANT_PREFIX: &str = "invariant_";
+
+/// Foundry `StdInvariant` functions setting the fuzzed contracts
+pub const TARGET_CONTRACTS_SELECTOR: [u8; 4] = [0x3f, 0x72, 0x86, 0xf4]; // targetContracts()
+pub const TARGET_SELECTORS_SELECTOR: [u8; 4] = [0x91, 0x6a, 0x17, 0xc6]; // targetSelectors()
+pub const EXCLUDE_CONTRACTS_SELECTOR: [u8; 4] = [0xe2, 0x0c, 0x9f, 0x71]; // excludeContracts()
+
+/// Whether the function is an Echidna property or a Foundry invariant
+pub fn is_property(abi: &ABIConfig) -> bool {
+    abi.abi == "()"
+        && (abi.function_name.starts_with(ECHIDNA_PREFIX)
+            || abi.function_name.starts_with(INVARIANT_PREFIX))
+}
+
+/// Whether the function is a Foundry invariant
+pub fn is_foundry_invariant(abi: &ABIConfig) -> bool {
+    abi.abi == "()" && abi.function_name.starts_with(INVARIANT_PREFIX)
+}
+
+/// ABI-encoded `address[]`
+fn decode_addresses(out: &[u8]) -> Option<Vec<EVMAddress>> {
+    let word = |pos: usize| -> Option<usize> {
+        let word = out.get(pos..pos + 32)?;
+        if word[..24].iter().any(|byte| *byte != 0) {
+            return None;
+        }
+        let value = u64::from_be_bytes(word[24..].try_into().unwrap()) as usize;
+        (value <= out.len()).then_some(value)
+    };
+    let array = word(0)?;
+    (0..word(array)?)
+        .map(|i| {
+            let item = array + 32 + i * 32;
+            out.get(item + 12..item + 32).map(EVMAddress::from_slice)
+        })
+        .collect()
+}
+
+/// Contracts and functions fuzzed in a Foundry invariant test, as set by its
+/// `targetContracts`, `targetSelectors` and `excludeContracts`
+#[derive(Clone, Debug, Default)]
+pub struct InvariantTargets {
+    pub contracts: HashSet<EVMAddress>,
+    pub selectors: HashMap<EVMAddress, HashSet<[u8; 4]>>,
+    pub excluded: HashSet<EVMAddress>,
+    /// Contracts with invariants, only fuzzed if targeted
+    pub tests: HashSet<EVMAddress>,
+}
+
+impl InvariantTargets {
+    /// Read the targets from the outputs of the hint functions of a test
+    pub fn add_hints(
+        &mut self,
+        target_contracts: Option<Vec<u8>>,
+        target_selectors: Option<Vec<u8>>,
+        exclude_contracts: Option<Vec<u8>>,
+    ) {
+        if let Some(contracts) = target_contracts.and_then(|out| decode_addresses(&out)) {
+            self.contracts.extend(contracts);
+        }
+        if let Some(facets) = target_selectors.and_then(|out| decode_facets(&out)) {
+            for facet in facets {
+                self.selectors
+                    .entry(facet.address)
+                    .or_default()
+                    .extend(facet.selectors.unwrap_or_default());
+            }
+        }
+        if let Some(contracts) = exclude_contracts.and_then(|out| decode_addresses(&out)) {
+            self.excluded.extend(contracts);
+        }
+    }
+
+    /// Whether calls to `selector` of `address` are fuzzed
+    pub fn is_target(&self, address: EVMAddress, selector: [u8; 4]) -> bool {
+        if self.excluded.contains(&address) {
+            return false;
+        }
+        if let Some(selectors) = self.selectors.get(&address) {
+            return selectors.contains(&selector);
+        }
+        if self.contracts.is_empty() && self.selectors.is_empty() {
+            return !self.tests.contains(&address);
+        }
+        self.contracts.contains(&address)
+    }
+}
 
 pub struct EchidnaOracle {
     pub batch_call_txs: Vec<(EVMAddress, Bytes)>,
     pub names: HashMap<Vec<u8>, String>,
+    pub address_to_name: HashMap<EVMAddress, String>,
 }
 
 impl EchidnaOracle {
-    pub fn new(echidna_funcs: Vec<(EVMAddress, Vec<u8>)>, names: HashMap<Vec<u8>, String>) -> Self {
+    pub fn new(
+        echidna_funcs: Vec<(EVMAddress, Vec<u8>)>,
+        names: HashMap<Vec<u8>, String>,
+        address_to_name: HashMap<EVMAddress, String>,
+    ) -> Self {
         Self {
             batch_call_txs: echidna_funcs
                 .iter()
@@ -27,6 +127,7 @@ impl EchidnaOracle {
                 })
                 .collect_vec(),
             names,
+            address_to_name,
         }
     }
 }
@@ -65,19 +166,24 @@ impl
         >,
         stage: u64,
     ) -> Vec<u64> {
-        ctx.call_post_batch(&self.batch_call_txs)
+        let mut bugs = ctx
+            .call_post_batch_with_status(&self.batch_call_txs)
             .iter()
-            .map(|out| out.iter().map(|x| *x == 0).all(|x| x))
             .enumerate()
-            .map(|(idx, x)| {
-                if x {
-                    let name = self
-                        .names
-                        .get(&self.batch_call_txs[idx].1.to_vec())
-                        .unwrap();
+            .map(|(idx, (out, success))| {
+                let name = self
+                    .names
+                    .get(&self.batch_call_txs[idx].1.to_vec())
+                    .unwrap();
+                let (bug_type, violated) = if name.starts_with(INVARIANT_PREFIX) {
+                    ("invariant", !success)
+                } else {
+                    ("echidna", !success || out.iter().all(|x| *x == 0))
+                };
+                if violated {
                     let bug_idx = (idx << 8) as u64 + ECHIDNA_BUG_IDX;
                     EVMBugResult::new(
-                        "echidna".to_string(),
+                        bug_type.to_string(),
                         bug_idx,
                         format!("{:?} violated", name),
                         ConciseEVMInput::from_input(
@@ -94,6 +200,84 @@ impl
                 }
             })
             .filter(|x| *x != 0)
-            .collect_vec()
+            .collect_vec();
+
+        // in assertion mode
+        for (reason, (address, pc)) in &ctx.post_state.assertion_failures {
+            let mut hasher = DefaultHasher::new();
+            (reason, address, pc).hash(&mut hasher);
+            let bug_idx = (hasher.finish() << 8) + ECHIDNA_BUG_IDX;
+            let name = self
+                .address_to_name
+                .get(address)
+                .cloned()
+                .unwrap_or(format!("{:?}", address));
+            EVMBugResult::new(
+                "assertion".to_string(),
+                bug_idx,
+                format!("{} in {} at pc {}", reason, name, pc),
+                ConciseEVMInput::from_input(ctx.input, ctx.fuzz_state.get_execution_result()),
+                None,
+                Some(name),
+            )
+            .push_to_output();
+            bugs.push(bug_idx);
+        }
+        bugs
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_invariant_targets() {
+        let address = |byte: u8| EVMAddress::from_slice(&[byte; 20]);
+        let word = |value: usize| {
+            let mut word = vec![0; 32];
+            word[24..].copy_from_slice(&(value as u64).to_be_bytes());
+            word
+        };
+        let addresses = |bytes: &[u8]| {
+            [
+                word(0x20),
+                word(bytes.len()),
+                bytes
+                    .iter()
+                    .flat_map(|byte| [vec![0; 12], vec![*byte; 20]].concat())
+                    .collect(),
+            ]
+            .concat()
+        };
+        // [(0x33.., [targetContracts()])]
+        let selectors = [
+            word(0x20),
+            word(1),
+            word(0x20),
+            [vec![0; 12], vec![0x33; 20]].concat(),
+            word(0x40),
+            word(1),
+            [TARGET_CONTRACTS_SELECTOR.to_vec(), vec![0; 28]].concat(),
+        ]
+        .concat();
+
+        let mut targets = InvariantTargets {
+            tests: HashSet::from([address(0x44)]),
+            ..Default::default()
+        };
+        assert!(targets.is_target(address(0x11), [0; 4]));
+        assert!(!targets.is_target(address(0x44), [0; 4]));
+
+        targets.add_hints(
+            Some(addresses(&[0x11, 0x22])),
+            Some(selectors),
+            Some(addresses(&[0x22])),
+        );
+        assert!(targets.is_target(address(0x11), [0; 4]));
+        assert!(!targets.is_target(address(0x22), [0; 4]));
+        assert!(targets.is_target(address(0x33), TARGET_CONTRACTS_SELECTOR));
+        assert!(!targets.is_target(address(0x33), [0; 4]));
+        assert!(!targets.is_target(address(0x55), [0; 4]));
     }
 }
@@ -175,8 +175,35 @@ impl
             .oracle_recheck_reserve
             .clear();
 
-        if exec_res.new_state.state.flashloan_data.earned
-            > exec_res.new_state.state.flashloan_data.owed
+        // a loan from a real venue is only settled once the venue regains control
+        let depth = exec_res.new_state.state.post_execution.len();
+        let outstanding_loan = exec_res
+            .new_state
+            .state
+            .flashloan_data
+            .flashloans
+            .iter()
+            .any(|loan| depth > loan.depth);
+
+        let loans = exec_res
+            .new_state
+            .state
+            .flashloan_data
+            .flashloans
+            .iter()
+            .map(|loan| format!("Borrowed {}\n", loan))
+            .collect::<String>();
+        // settled loans are not carried over to the inputs executed on this state
+        exec_res
+            .new_state
+            .state
+            .flashloan_data
+            .flashloans
+            .retain(|loan| depth > loan.depth);
+
+        if !outstanding_loan
+            && exec_res.new_state.state.flashloan_data.earned
+                > exec_res.new_state.state.flashloan_data.owed
             && exec_res.new_state.state.flashloan_data.earned
                 - exec_res.new_state.state.flashloan_data.owed
                 > EVMU512::from(100_000_000_000_000_000_000_000_u128)
@@ -190,11 +217,12 @@ impl
                 "erc20".to_string(),
                 ERC20_BUG_IDX,
                 format!(
-                    "Earned {} more than owed {}, net earned = {}wei ({}ETH)\n",
+                    "Earned {} more than owed {}, net earned = {}wei ({}ETH)\n{}",
                     exec_res.new_state.state.flashloan_data.earned,
                     exec_res.new_state.state.flashloan_data.owed,
                     net,
                     net_eth,
+                    loans,
                 ),
                 ConciseEVMInput::from_input(ctx.input, ctx.fuzz_state.get_execution_result()),
             )
@@ -0,0 +1,281 @@
+use crate::evm::contract_tags::{ContractTag, ContractTags};
+use crate::evm::input::{ConciseEVMInput, EVMInput};
+use crate::evm::oracle::EVMBugResult;
+use crate::evm::oracles::amm::{selector, word};
+use crate::evm::oracles::ERC4626_BUG_IDX;
+use crate::evm::types::{as_u64, EVMAddress, EVMFuzzState, EVMOracleCtx, EVMU256, EVMU512};
+use crate::evm::vm::EVMState;
+use crate::oracle::Oracle;
+use crate::state::HasExecutionResult;
+use bytes::Bytes;
+use libafl::prelude::HasMetadata;
+use revm_primitives::Bytecode;
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+
+fn call(signature: &str, amount: Option<EVMU256>) -> Bytes {
+    let mut data = selector(signature).to_vec();
+    if let Some(amount) = amount {
+        data.extend(amount.to_be_bytes::<32>());
+    }
+    Bytes::from(data)
+}
+
+/// Share price and totals of a vault, read in the same batch so that they
+/// are consistent with each other
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct VaultSnapshot {
+    /// convertToAssets of one share
+    price: EVMU256,
+    total_assets: EVMU256,
+    total_supply: EVMU256,
+}
+
+impl VaultSnapshot {
+    /// Outputs of convertToAssets(one share), totalAssets() and totalSupply()
+    fn new(outs: &[Vec<u8>]) -> Option<Self> {
+        Some(Self {
+            price: word(&outs[0], 0)?,
+            total_assets: word(&outs[1], 0)?,
+            total_supply: word(&outs[2], 0)?,
+        })
+    }
+
+    /// Whether a share grew more than `jump` times more expensive since
+    /// `pre`, the price of an empty vault being given by convertToAssets
+    fn inflated(&self, pre: &Self, one_share: EVMU256, jump: u64) -> bool {
+        let ratio = |snapshot: &Self| {
+            if snapshot.total_supply == EVMU256::ZERO {
+                (snapshot.price, one_share)
+            } else {
+                (snapshot.total_assets, snapshot.total_supply)
+            }
+        };
+        let ((pre_assets, pre_supply), (post_assets, post_supply)) = (ratio(pre), ratio(self));
+        pre_assets > EVMU256::ZERO
+            && EVMU512::from(post_assets) * EVMU512::from(pre_supply) / EVMU512::from(jump)
+                > EVMU512::from(pre_assets) * EVMU512::from(post_supply)
+    }
+}
+
+/// Checks the share accounting of ERC4626 vaults touched by the transaction:
+/// - the price of a share does not jump (donation / first depositor inflation)
+/// - a deposit that minted shares before still mints shares
+/// - a deposit / redeem round trip never returns more assets than deposited,
+///   and minting a share never costs less than redeeming it
+pub struct ERC4626Oracle {
+    /// A share price growing more than this factor in one transaction is
+    /// considered manipulated (donation / first depositor inflation)
+    share_price_jump: u64,
+}
+
+impl ERC4626Oracle {
+    pub fn new(share_price_jump: u64) -> Self {
+        assert!(share_price_jump > 0, "share price jump must be positive");
+        Self { share_price_jump }
+    }
+
+    fn check_vault(&self, ctx: &mut EVMOracleCtx<'_>, vault: EVMAddress) -> Vec<(String, String)> {
+        let meta = ctx.call_post_batch(&vec![
+            (vault, call("asset()", None)),
+            (vault, call("decimals()", None)),
+        ]);
+        let (asset, decimals) = match (word(&meta[0], 0), word(&meta[1], 0)) {
+            (Some(asset), Some(decimals)) if decimals <= EVMU256::from(36) => (
+                EVMAddress::from_slice(&asset.to_be_bytes::<32>()[12..]),
+                as_u64(decimals),
+            ),
+            _ => return vec![],
+        };
+        let changed =
+            |addr: &EVMAddress| ctx.pre_state.state.get(addr) != ctx.post_state.state.get(addr);
+        if !changed(&vault) && !changed(&asset) {
+            return vec![];
+        }
+
+        let one_share = EVMU256::from(10).pow(EVMU256::from(decimals));
+        let calls = vec![
+            (vault, call("convertToAssets(uint256)", Some(one_share))),
+            (vault, call("totalAssets()", None)),
+            (vault, call("totalSupply()", None)),
+        ];
+        let (pre, post) = match (
+            VaultSnapshot::new(&ctx.call_pre_batch(&calls)),
+            VaultSnapshot::new(&ctx.call_post_batch(&calls)),
+        ) {
+            (Some(pre), Some(post)) => (pre, post),
+            _ => return vec![],
+        };
+        let (pre_price, post_price) = (pre.price, post.price);
+        let accounting = format!(
+            "assets per share: {} -> {}, totalAssets: {} -> {}, totalSupply: {} -> {}",
+            pre_price,
+            post_price,
+            pre.total_assets,
+            post.total_assets,
+            pre.total_supply,
+            post.total_supply
+        );
+
+        let mut violations = vec![];
+        if post.inflated(&pre, one_share, self.share_price_jump) {
+            violations.push((
+                "share_price_inflation".to_string(),
+                format!("share price inflated, {}", accounting),
+            ));
+        }
+
+        let amount = if pre_price > EVMU256::ZERO {
+            pre_price
+        } else {
+            one_share
+        };
+        let deposit = vec![(vault, call("previewDeposit(uint256)", Some(amount)))];
+        let pre_shares = word(&ctx.call_pre_batch(&deposit)[0], 0);
+        let post_shares = word(&ctx.call_post_batch(&deposit)[0], 0);
+        if let (Some(pre_shares), Some(post_shares)) = (pre_shares, post_shares) {
+            if pre_shares > EVMU256::ZERO && post_shares == EVMU256::ZERO {
+                violations.push((
+                    "zero_share_deposit".to_string(),
+                    format!(
+                        "depositing {} assets minted {} shares before, none after, {}",
+                        amount, pre_shares, accounting
+                    ),
+                ));
+            }
+        }
+
+        let amount = if post_price > EVMU256::ZERO {
+            post_price
+        } else {
+            one_share
+        };
+        let shares = word(
+            &ctx.call_post_batch(&vec![(
+                vault,
+                call("previewDeposit(uint256)", Some(amount)),
+            )])[0],
+            0,
+        );
+        if let Some(shares) = shares {
+            let round_trip = ctx.call_post_batch(&vec![
+                (vault, call("previewRedeem(uint256)", Some(shares))),
+                (vault, call("previewMint(uint256)", Some(one_share))),
+                (vault, call("previewRedeem(uint256)", Some(one_share))),
+            ]);
+            if let Some(redeemed) = word(&round_trip[0], 0) {
+                if redeemed > amount {
+                    violations.push((
+                        "rounding".to_string(),
+                        format!(
+                            "depositing {} assets and redeeming the {} shares returns {} assets",
+                            amount, shares, redeemed
+                        ),
+                    ));
+                }
+            }
+            if let (Some(mint_cost), Some(redeemed)) =
+                (word(&round_trip[1], 0), word(&round_trip[2], 0))
+            {
+                if mint_cost < redeemed {
+                    violations.push((
+                        "rounding".to_string(),
+                        format!(
+                            "minting {} shares costs {} assets but redeeming them returns {}",
+                            one_share, mint_cost, redeemed
+                        ),
+                    ));
+                }
+            }
+        }
+        violations
+    }
+}
+
+impl
+    Oracle<
+        EVMState,
+        EVMAddress,
+        Bytecode,
+        Bytes,
+        EVMAddress,
+        EVMU256,
+        Vec<u8>,
+        EVMInput,
+        EVMFuzzState,
+        ConciseEVMInput,
+    > for ERC4626Oracle
+{
+    fn transition(&self, _ctx: &mut EVMOracleCtx<'_>, _stage: u64) -> u64 {
+        0
+    }
+
+    fn oracle(&self, ctx: &mut EVMOracleCtx<'_>, _stage: u64) -> Vec<u64> {
+        let vaults = match ctx.fuzz_state.metadata_map().get::<ContractTags>() {
+            Some(tags) => tags.with_tag(ContractTag::ERC4626),
+            None => return vec![],
+        };
+
+        let mut bugs = vec![];
+        for vault in vaults {
+            for (kind, msg) in self.check_vault(ctx, vault) {
+                let mut hasher = DefaultHasher::new();
+                vault.hash(&mut hasher);
+                kind.hash(&mut hasher);
+                let bug_idx = hasher.finish() << (8 + ERC4626_BUG_IDX);
+
+                EVMBugResult::new_simple(
+                    "erc4626".to_string(),
+                    bug_idx,
+                    format!("{:?} {}: {}\n", vault, kind, msg),
+                    ConciseEVMInput::from_input(ctx.input, ctx.fuzz_state.get_execution_result()),
+                )
+                .push_to_output();
+                bugs.push(bug_idx);
+            }
+        }
+        bugs
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn snapshot(price: u128, total_assets: u128, total_supply: u128) -> VaultSnapshot {
+        VaultSnapshot {
+            price: EVMU256::from(price),
+            total_assets: EVMU256::from(total_assets),
+            total_supply: EVMU256::from(total_supply),
+        }
+    }
+
+    #[test]
+    fn test_share_price_inflation() {
+        let e18 = 10u128.pow(18);
+        let one_share = EVMU256::from(e18);
+        let pre = snapshot(e18, 100 * e18, 100 * e18);
+
+        // donation of 900 assets
+        let donated = snapshot(10 * e18, 1000 * e18, 100 * e18);
+        assert!(donated.inflated(&pre, one_share, 2));
+        assert!(!donated.inflated(&pre, one_share, 20));
+
+        // deposits, withdrawals and yield keep the share price
+        let deposited = snapshot(e18, 300 * e18, 300 * e18);
+        assert!(!deposited.inflated(&pre, one_share, 2));
+        let withdrawn = snapshot(e18, e18, e18);
+        assert!(!withdrawn.inflated(&pre, one_share, 2));
+        let earned = snapshot(e18 * 3 / 2, 150 * e18, 100 * e18);
+        assert!(!earned.inflated(&pre, one_share, 2));
+
+        // first depositor mints one wei of share, then donates
+        let empty = snapshot(e18, 0, 0);
+        let attacked = snapshot(e18 * e18, e18 + 1, 1);
+        assert!(attacked.inflated(&empty, one_share, 2));
+        let first_deposit = snapshot(e18, 5 * e18, 5 * e18);
+        assert!(!first_deposit.inflated(&empty, one_share, 2));
+        assert!(!empty.inflated(&empty, one_share, 2));
+        assert!(!pre.inflated(&snapshot(0, 0, 0), one_share, 2));
+    }
+}
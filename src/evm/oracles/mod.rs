@@ -1,10 +1,13 @@
+pub mod amm;
 pub mod arb_call;
 pub mod echidna;
 pub mod erc20;
+pub mod erc4626;
 pub mod function;
 pub mod icy_bug;
 pub mod reentrancy;
 pub mod selfdestruct;
+pub mod spot_price;
 pub mod state_comp;
 pub mod typed_bug;
 pub mod v2_pair;
@@ -19,3 +22,6 @@ pub static STATE_COMP_BUG_IDX: u64 = 7;
 pub static ARB_CALL_BUG_IDX: u64 = 8;
 pub static REENTRANCY_BUG_IDX: u64 = 9;
 pub static ICY_BUG_IDX: u64 = 10;
+pub static AMM_INVARIANT_BUG_IDX: u64 = 11;
+pub static ERC4626_BUG_IDX: u64 = 12;
+pub static SPOT_PRICE_BUG_IDX: u64 = 13;
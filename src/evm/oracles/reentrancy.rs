@@ -1,3 +1,4 @@
+use crate::evm::blaz::builder::{ArtifactInfoMetadata, BuildJobResult};
 use crate::evm::input::{ConciseEVMInput, EVMInput};
 use crate::evm::oracle::EVMBugResult;
 use crate::evm::types::{EVMAddress, EVMFuzzState, EVMOracleCtx, ProjectSourceMapTy, EVMU256};
@@ -7,6 +8,7 @@ use crate::oracle::{Oracle, OracleCtx};
 use crate::state::HasExecutionResult;
 use bytes::Bytes;
 use itertools::Itertools;
+use libafl::prelude::HasMetadata;
 use revm_primitives::Bytecode;
 use std::collections::hash_map::DefaultHasher;
 use std::collections::HashMap;
@@ -29,6 +31,13 @@ impl ReentrancyOracle {
             address_to_name,
         }
     }
+
+    fn get_name(&self, addr: &EVMAddress) -> String {
+        self.address_to_name
+            .get(addr)
+            .cloned()
+            .unwrap_or(format!("{:?}", addr))
+    }
 }
 
 impl
@@ -71,9 +80,54 @@ impl
                 .downcast_ref_unchecked::<EVMState>()
                 .reentrancy_metadata
         };
-        if reetrancy_metadata.found.is_empty() {
+        if reetrancy_metadata.found.is_empty() && reetrancy_metadata.found_read_only.is_empty() {
             return vec![];
         }
+        let read_only = reetrancy_metadata
+            .found_read_only
+            .iter()
+            .map(|(addr, slot, (read_addr, read_pc), (write_addr, write_pc))| {
+                let mut hasher = DefaultHasher::new();
+                addr.hash(&mut hasher);
+                slot.hash(&mut hasher);
+                read_addr.hash(&mut hasher);
+                read_pc.hash(&mut hasher);
+                write_addr.hash(&mut hasher);
+                write_pc.hash(&mut hasher);
+                let real_bug_idx = hasher.finish() << (8 + REENTRANCY_BUG_IDX);
+
+                let name = self.get_name(addr);
+                let srcmap = BuildJobResult::get_sourcemap_executor(
+                    ctx.fuzz_state
+                        .metadata_map_mut()
+                        .get_mut::<ArtifactInfoMetadata>()
+                        .expect("get metadata failed")
+                        .get_mut(read_addr),
+                    ctx.executor,
+                    read_addr,
+                    &self.sourcemap,
+                    *read_pc,
+                );
+                EVMBugResult::new(
+                    "read_only_reentrancy".to_string(),
+                    real_bug_idx,
+                    format!(
+                        "Read-only reentrancy on {:?} at slot {:?}: read by view call at {}:{} before written at {}:{}",
+                        name,
+                        slot,
+                        self.get_name(read_addr),
+                        read_pc,
+                        self.get_name(write_addr),
+                        write_pc
+                    ),
+                    ConciseEVMInput::from_input(ctx.input, ctx.fuzz_state.get_execution_result()),
+                    srcmap,
+                    Some(name.clone()),
+                )
+                .push_to_output();
+                real_bug_idx
+            })
+            .collect_vec();
         reetrancy_metadata
             .found
             .iter()
@@ -82,11 +136,7 @@ impl
                 addr.hash(&mut hasher);
                 let real_bug_idx = hasher.finish() << (8 + REENTRANCY_BUG_IDX);
 
-                let name = self
-                    .address_to_name
-                    .get(addr)
-                    .unwrap_or(&format!("{:?}", addr))
-                    .clone();
+                let name = self.get_name(addr);
                 EVMBugResult::new(
                     "reentrancy".to_string(),
                     real_bug_idx,
@@ -98,6 +148,7 @@ impl
                 .push_to_output();
                 real_bug_idx
             })
+            .chain(read_only)
             .collect_vec()
     }
 }
@@ -0,0 +1,188 @@
+use crate::evm::corpus_initializer::ABIMap;
+use crate::evm::input::{ConciseEVMInput, EVMInput};
+use crate::evm::middlewares::spot_price::SpotPriceRead;
+use crate::evm::oracle::EVMBugResult;
+use crate::evm::oracles::amm::{selector, to_f64, word};
+use crate::evm::oracles::SPOT_PRICE_BUG_IDX;
+use crate::evm::types::{EVMAddress, EVMFuzzState, EVMOracleCtx, EVMU256};
+use crate::evm::vm::EVMState;
+use crate::oracle::Oracle;
+use crate::state::HasExecutionResult;
+use bytes::Bytes;
+use libafl::prelude::HasMetadata;
+use revm_primitives::Bytecode;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{HashMap, HashSet};
+use std::hash::{Hash, Hasher};
+
+/// Reports contracts that read the spot price of an AMM pool (`getReserves`,
+/// `slot0`, `balanceOf(pool)`, ...) after it was moved by more than
+/// `threshold` percent and changed their own storage in the same transaction.
+///
+/// The price read is compared to the first price read in the sequence and to
+/// the price before the transaction.
+pub struct SpotPriceOracle {
+    pub threshold: f64,
+    pub address_to_name: HashMap<EVMAddress, String>,
+}
+
+impl SpotPriceOracle {
+    pub fn new(threshold: f64, address_to_name: HashMap<EVMAddress, String>) -> Self {
+        Self {
+            threshold,
+            address_to_name,
+        }
+    }
+
+    fn get_name(&self, addr: &EVMAddress) -> String {
+        self.address_to_name
+            .get(addr)
+            .cloned()
+            .unwrap_or(format!("{:?}", addr))
+    }
+}
+
+/// Getter name and price derived from the output of a price read
+pub fn spot_price(calldata: &[u8], output: &[u8]) -> Option<(&'static str, f64)> {
+    let is = |signature: &str| calldata.starts_with(&selector(signature));
+    if is("getReserves()") {
+        let (reserve0, reserve1) = (to_f64(word(output, 0)?), to_f64(word(output, 1)?));
+        if reserve0 == 0.0 {
+            return None;
+        }
+        Some(("getReserves", reserve1 / reserve0))
+    } else if is("slot0()") {
+        Some(("slot0", (to_f64(word(output, 0)?) / 2f64.powi(96)).powi(2)))
+    } else if is("get_virtual_price()") {
+        Some(("get_virtual_price", to_f64(word(output, 0)?)))
+    } else if is("balanceOf(address)") {
+        Some(("balanceOf", to_f64(word(output, 0)?)))
+    } else {
+        None
+    }
+}
+
+impl
+    Oracle<
+        EVMState,
+        EVMAddress,
+        Bytecode,
+        Bytes,
+        EVMAddress,
+        EVMU256,
+        Vec<u8>,
+        EVMInput,
+        EVMFuzzState,
+        ConciseEVMInput,
+    > for SpotPriceOracle
+{
+    fn transition(&self, _ctx: &mut EVMOracleCtx<'_>, _stage: u64) -> u64 {
+        0
+    }
+
+    fn oracle(&self, ctx: &mut EVMOracleCtx<'_>, _stage: u64) -> Vec<u64> {
+        let data = ctx.post_state.spot_price_metadata.clone();
+        let mut seen = HashSet::new();
+        let mut bugs = vec![];
+        for read in &data.reads {
+            let SpotPriceRead {
+                consumer,
+                function,
+                pool,
+                target,
+                calldata,
+                output,
+            } = read;
+            // the price only matters if the consumer updated its accounting
+            if ctx.pre_state.state.get(consumer) == ctx.post_state.state.get(consumer) {
+                continue;
+            }
+            let (getter, price) = match spot_price(calldata, output) {
+                Some(price) => price,
+                None => continue,
+            };
+
+            let pre = ctx.call_pre_batch(&vec![(*target, Bytes::from(calldata.clone()))]);
+            let deviation = data
+                .baseline
+                .get(&(*target, calldata.clone()))
+                .into_iter()
+                .chain(pre.iter())
+                .filter_map(|base| spot_price(calldata, base))
+                .filter(|(_, base)| *base != 0.0)
+                .map(|(_, base)| ((price - base) / base * 100.0, base))
+                .max_by(|a, b| a.0.abs().total_cmp(&b.0.abs()));
+            let (deviation, base) = match deviation {
+                Some(deviation) if deviation.0.abs() > self.threshold => deviation,
+                _ => continue,
+            };
+
+            let mut hasher = DefaultHasher::new();
+            consumer.hash(&mut hasher);
+            function.hash(&mut hasher);
+            pool.hash(&mut hasher);
+            let bug_idx = hasher.finish() << (8 + SPOT_PRICE_BUG_IDX);
+            if !seen.insert(bug_idx) {
+                continue;
+            }
+
+            let function_name = ctx
+                .fuzz_state
+                .metadata_map()
+                .get::<ABIMap>()
+                .and_then(|abis| abis.get(function))
+                .map(|abi| abi.function_name.clone())
+                .unwrap_or(hex::encode(function));
+            let name = self.get_name(consumer);
+            EVMBugResult::new(
+                "spot_price_manipulation".to_string(),
+                bug_idx,
+                format!(
+                    "{}.{} consumed manipulated price of pool {} ({} {:.4e} -> {:.4e}, {:+.2}%)\n",
+                    name,
+                    function_name,
+                    self.get_name(pool),
+                    getter,
+                    base,
+                    price,
+                    deviation
+                ),
+                ConciseEVMInput::from_input(ctx.input, ctx.fuzz_state.get_execution_result()),
+                None,
+                Some(name),
+            )
+            .push_to_output();
+            bugs.push(bug_idx);
+        }
+        bugs
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_spot_price() {
+        let words = |values: &[u64]| {
+            values
+                .iter()
+                .flat_map(|v| EVMU256::from(*v).to_be_bytes::<32>())
+                .collect::<Vec<u8>>()
+        };
+        assert_eq!(
+            spot_price(&selector("getReserves()"), &words(&[100, 250, 0])),
+            Some(("getReserves", 2.5))
+        );
+        assert_eq!(
+            spot_price(&selector("getReserves()"), &words(&[0, 250, 0])),
+            None
+        );
+        let sqrt_price = EVMU256::from(2) << 96;
+        assert_eq!(
+            spot_price(&selector("slot0()"), &sqrt_price.to_be_bytes::<32>()),
+            Some(("slot0", 4.0))
+        );
+        assert_eq!(spot_price(&selector("totalSupply()"), &words(&[1])), None);
+    }
+}
@@ -1,16 +1,19 @@
+use crate::evm::abi::split_with_parenthesis;
 use crate::evm::blaz::builder::{ArtifactInfoMetadata, BuildJobResult};
 use crate::evm::input::{ConciseEVMInput, EVMInput};
 use crate::evm::oracle::{dummy_precondition, EVMBugResult};
 use crate::evm::oracles::TYPED_BUG_BUG_IDX;
+use crate::evm::scribble::ScribbleMetadata;
 use crate::evm::srcmap::parser::{decode_instructions, SourceMapLocation};
 use crate::evm::types::{
-    EVMAddress, EVMFuzzState, EVMOracleCtx, EVMStagedVMState, ProjectSourceMapTy, EVMU256,
+    checksum, EVMAddress, EVMFuzzState, EVMOracleCtx, EVMStagedVMState, ProjectSourceMapTy, EVMU256,
 };
 use crate::evm::vm::{EVMExecutor, EVMState};
 use crate::fuzzer::ORACLE_OUTPUT;
 use crate::oracle::{BugMetadata, Oracle, OracleCtx, Producer};
 use crate::state::HasExecutionResult;
 use bytes::Bytes;
+use ethers::types::I256;
 use itertools::Itertools;
 use libafl::state::HasMetadata;
 use primitive_types::{H160, H256, U256};
@@ -23,19 +26,174 @@ use std::hash::{Hash, Hasher};
 use std::ops::Deref;
 use std::rc::Rc;
 
+/// `bytes32("fuzzland_bug")`, topic of the structured typed bug event whose
+/// data is `abi.encode(uint8 severity, string id, string types, bytes args)`,
+/// `types` describing the ABI-encoded `args` (e.g.,
+/// `uint256 expected,uint256 actual`)
+pub const TYPED_BUG_TOPIC: [u8; 32] = *b"fuzzland_bug\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0";
+
+const SEVERITIES: [&str; 5] = ["info", "low", "medium", "high", "critical"];
+
+/// Bug reported by a typed bug event
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct TypedBug {
+    pub id: String,
+    /// Index in `SEVERITIES`, None for the string only event
+    pub severity: Option<u8>,
+    /// Names and values of the args of the event
+    pub context: Vec<(String, String)>,
+}
+
+/// Word at `pos` of `data` as an offset or length within it
+fn word(data: &[u8], pos: usize) -> Option<usize> {
+    let word = data.get(pos..pos + 32)?;
+    if word[..24].iter().any(|byte| *byte != 0) {
+        return None;
+    }
+    let value = u64::from_be_bytes(word[24..].try_into().unwrap()) as usize;
+    (value <= data.len()).then_some(value)
+}
+
+/// `bytes` or `string` whose offset is at `pos` of `data`
+fn dynamic(data: &[u8], pos: usize) -> Option<&[u8]> {
+    let offset = word(data, pos)?;
+    data.get(offset + 32..offset + 32 + word(data, offset)?)
+}
+
+/// Size of the head of an arg of type `ty`, None if it is dynamic and its
+/// head is an offset
+fn static_size(ty: &str) -> Option<usize> {
+    if let Some((item, len)) = ty.strip_suffix(']').and_then(|ty| ty.rsplit_once('[')) {
+        return Some(len.parse::<usize>().ok()? * static_size(item)?);
+    }
+    if let Some(items) = ty.strip_prefix('(').and_then(|ty| ty.strip_suffix(')')) {
+        return split_with_parenthesis(items)
+            .iter()
+            .map(|item| static_size(item.trim().split(' ').next().unwrap_or_default()))
+            .sum();
+    }
+    match ty {
+        "string" | "bytes" => None,
+        _ => Some(32),
+    }
+}
+
+/// Arg of type `ty` whose head is at `pos` of `args`
+fn decode_arg(args: &[u8], pos: usize, ty: &str) -> Option<String> {
+    if ty.ends_with(']') || ty.ends_with(')') {
+        // static arrays and tuples are encoded in place
+        let size = static_size(ty).unwrap_or(32);
+        return Some(format!("0x{}", hex::encode(args.get(pos..pos + size)?)));
+    }
+    match ty {
+        "string" => {
+            return dynamic(args, pos).map(|value| String::from_utf8_lossy(value).to_string())
+        }
+        "bytes" => return dynamic(args, pos).map(|value| format!("0x{}", hex::encode(value))),
+        _ => {}
+    }
+    let value = args.get(pos..pos + 32)?;
+    Some(match ty {
+        "bool" => value.iter().any(|byte| *byte != 0).to_string(),
+        "address" => checksum(&EVMAddress::from_slice(&value[12..])),
+        _ if ty.starts_with("uint") => EVMU256::try_from_be_slice(value).unwrap().to_string(),
+        _ if ty.starts_with("int") => {
+            I256::from_raw(ethers::types::U256::from_big_endian(value)).to_string()
+        }
+        _ if ty.starts_with("bytes") => {
+            let len = ty[5..].parse::<usize>().unwrap_or(32).min(32);
+            format!("0x{}", hex::encode(&value[..len]))
+        }
+        _ => format!("0x{}", hex::encode(value)),
+    })
+}
+
+impl TypedBug {
+    pub fn new(id: String) -> Self {
+        Self {
+            id,
+            severity: None,
+            context: vec![],
+        }
+    }
+
+    /// Decode the data of a structured typed bug event
+    pub fn decode(data: &[u8]) -> Option<Self> {
+        let severity = *data.get(31)?;
+        let id = String::from_utf8_lossy(dynamic(data, 32)?).to_string();
+        let types = String::from_utf8_lossy(dynamic(data, 64)?).to_string();
+        let args = dynamic(data, 96)?;
+        // position of the head of the next arg
+        let mut pos = 0;
+        let context = split_with_parenthesis(&types)
+            .iter()
+            .map(|param| param.trim())
+            .filter(|param| !param.is_empty())
+            .enumerate()
+            .map(|(idx, param)| {
+                let (ty, name) = match param
+                    .rsplit_once(' ')
+                    .filter(|(_, name)| !name.contains([')', ']']))
+                {
+                    Some((ty, name)) => (ty.trim(), name.to_string()),
+                    None => (param, format!("arg{}", idx)),
+                };
+                let value = decode_arg(args, pos, ty).unwrap_or_default();
+                pos += static_size(ty).unwrap_or(32);
+                (name, value)
+            })
+            .collect();
+        Some(Self {
+            id,
+            severity: Some(severity),
+            context,
+        })
+    }
+
+    pub fn severity_name(&self) -> Option<String> {
+        self.severity.map(|severity| {
+            SEVERITIES
+                .get(severity as usize)
+                .map_or(severity.to_string(), |name| name.to_string())
+        })
+    }
+
+    /// e.g., `"slippage" violated (severity: high, expected: 100, actual: 90)`
+    pub fn describe(&self) -> String {
+        let details = self
+            .severity_name()
+            .map(|severity| format!("severity: {}", severity))
+            .into_iter()
+            .chain(
+                self.context
+                    .iter()
+                    .map(|(name, value)| format!("{}: {}", name, value)),
+            )
+            .join(", ");
+        if details.is_empty() {
+            format!("{:?} violated", self.id)
+        } else {
+            format!("{:?} violated ({})", self.id, details)
+        }
+    }
+}
+
 pub struct TypedBugOracle {
     sourcemap: ProjectSourceMapTy,
     address_to_name: HashMap<EVMAddress, String>,
+    scribble: Option<ScribbleMetadata>,
 }
 
 impl TypedBugOracle {
     pub fn new(
         sourcemap: ProjectSourceMapTy,
         address_to_name: HashMap<EVMAddress, String>,
+        scribble: Option<ScribbleMetadata>,
     ) -> Self {
         Self {
             sourcemap,
             address_to_name,
+            scribble,
         }
     }
 }
@@ -78,9 +236,43 @@ impl
             ctx.post_state
                 .typed_bug
                 .iter()
-                .map(|(bug_id, (addr, pc))| {
+                // the same bug with other args
+                .unique_by(|(bug, location)| (&bug.id, *location))
+                .map(|(bug, (addr, pc))| {
+                    if let Some(annotation) = self
+                        .scribble
+                        .as_ref()
+                        .and_then(|scribble| scribble.annotation(&bug.id))
+                    {
+                        // one bug per annotation, wherever it is violated
+                        let mut hasher = DefaultHasher::new();
+                        ("scribble", annotation.id).hash(&mut hasher);
+                        let bug_idx = (hasher.finish() << 8) + TYPED_BUG_BUG_IDX;
+                        EVMBugResult::new(
+                            "scribble".to_string(),
+                            bug_idx,
+                            format!(
+                                "{} of {}.{} violated: {} ({}:{})",
+                                annotation.kind,
+                                annotation.contract,
+                                annotation.target,
+                                annotation.text,
+                                annotation.location.file.clone().unwrap_or_default(),
+                                annotation.line,
+                            ),
+                            ConciseEVMInput::from_input(
+                                ctx.input,
+                                ctx.fuzz_state.get_execution_result(),
+                            ),
+                            Some(annotation.location.clone()),
+                            Some(annotation.contract.clone()),
+                        )
+                        .push_to_output();
+                        return bug_idx;
+                    }
+
                     let mut hasher = DefaultHasher::new();
-                    bug_id.hash(&mut hasher);
+                    bug.id.hash(&mut hasher);
                     pc.hash(&mut hasher);
                     let mut name = self
                         .address_to_name
@@ -103,7 +295,7 @@ impl
                     EVMBugResult::new(
                         "typed_bug".to_string(),
                         real_bug_idx,
-                        format!("{:?} violated", bug_id,),
+                        bug.describe(),
                         ConciseEVMInput::from_input(
                             ctx.input,
                             ctx.fuzz_state.get_execution_result(),
@@ -111,6 +303,7 @@ impl
                         srcmap,
                         Some(name.clone()),
                     )
+                    .with_context(bug.severity_name(), bug.context.clone())
                     .push_to_output();
                     real_bug_idx
                 })
@@ -120,3 +313,93 @@ impl
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decode_typed_bug() {
+        let word = |value: usize| {
+            let mut word = vec![0; 32];
+            word[24..].copy_from_slice(&(value as u64).to_be_bytes());
+            word
+        };
+        let padded = |bytes: &[u8]| {
+            let mut padded = bytes.to_vec();
+            padded.resize((bytes.len() + 31) / 32 * 32, 0);
+            [word(bytes.len()), padded].concat()
+        };
+        // abi.encode(uint256(100), true)
+        let args = [word(100), word(1)].concat();
+        // abi.encode(uint8(3), "slippage", "uint256 expected,bool", args)
+        let data = [
+            word(3),
+            word(0x80),
+            word(0xc0),
+            word(0x100),
+            padded(b"slippage"),
+            padded(b"uint256 expected,bool"),
+            padded(&args),
+        ]
+        .concat();
+
+        let bug = TypedBug::decode(&data).unwrap();
+        assert_eq!(bug.id, "slippage");
+        assert_eq!(bug.severity_name(), Some("high".to_string()));
+        assert_eq!(
+            bug.context,
+            vec![
+                ("expected".to_string(), "100".to_string()),
+                ("arg1".to_string(), "true".to_string()),
+            ]
+        );
+        assert_eq!(
+            bug.describe(),
+            "\"slippage\" violated (severity: high, expected: 100, arg1: true)"
+        );
+        assert_eq!(
+            TypedBug::new("bug".to_string()).describe(),
+            "\"bug\" violated"
+        );
+        // truncated
+        assert_eq!(TypedBug::decode(&data[..data.len() - 32]), None);
+
+        // static arrays and tuples are encoded in place, before the string
+        let types = "uint256[2] amounts,(address, bool) pair,string";
+        let args = [
+            word(1),
+            word(2),
+            word(0xaa),
+            word(1),
+            word(0xa0),
+            padded(b"note"),
+        ]
+        .concat();
+        let data = [
+            word(4),
+            word(0x80),
+            word(0xc0),
+            word(0x120),
+            padded(b"slippage"),
+            padded(types.as_bytes()),
+            padded(&args),
+        ]
+        .concat();
+        let bug = TypedBug::decode(&data).unwrap();
+        assert_eq!(
+            bug.context,
+            vec![
+                (
+                    "amounts".to_string(),
+                    format!("0x{}", hex::encode([word(1), word(2)].concat()))
+                ),
+                (
+                    "pair".to_string(),
+                    format!("0x{}", hex::encode([word(0xaa), word(1)].concat()))
+                ),
+                ("arg2".to_string(), "note".to_string()),
+            ]
+        );
+    }
+}
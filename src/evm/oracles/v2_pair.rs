@@ -67,23 +67,15 @@ impl
                 .clone();
 
             for addr in to_check {
-                macro_rules! get_slot_allow_empty {
-                    ($state: ident) => {
-                        ctx.$state
-                            .state
-                            .get(&addr)
-                            .map(|data| data.get(&EVMU256::from(8)))
-                            .unwrap_or(None)
-                    };
-                }
-
-                let prev_reserve_slot = get_slot_allow_empty!(pre_state);
-                let new_reserve_slot = get_slot_allow_empty!(post_state);
-                if prev_reserve_slot.is_none() || new_reserve_slot.is_none() {
+                // read through getReserves, the storage layout differs among forks
+                let calls = vec![(addr, Bytes::from(vec![0x09, 0x02, 0xf1, 0xac]))];
+                let pre_reserves = reserves_parser(&ctx.call_pre_batch(&calls)[0]);
+                let new_reserves = reserves_parser(&ctx.call_post_batch(&calls)[0]);
+                if pre_reserves.is_none() || new_reserves.is_none() {
                     continue;
                 }
-                let (pre_r0, pre_r1) = reserve_parser(prev_reserve_slot.unwrap());
-                let (r0, r1) = reserve_parser(new_reserve_slot.unwrap());
+                let (pre_r0, pre_r1) = pre_reserves.unwrap();
+                let (r0, r1) = new_reserves.unwrap();
 
                 if pre_r0 == r0 && pre_r1 > r1 || pre_r1 == r1 && pre_r0 > r0 {
                     // calculate hash in u64 of pair address (addr) using DefaultHasher
@@ -120,9 +112,12 @@ impl
     }
 }
 
-pub fn reserve_parser(reserve_slot: &EVMU256) -> (EVMU256, EVMU256) {
-    let reserve_bytes: [u8; 32] = reserve_slot.to_be_bytes();
-    let reserve_0 = EVMU256::try_from_be_slice(&reserve_bytes[4..18]).unwrap();
-    let reserve_1 = EVMU256::try_from_be_slice(&reserve_bytes[18..32]).unwrap();
-    (reserve_0, reserve_1)
+/// Parse the output of `getReserves()`
+pub fn reserves_parser(output: &[u8]) -> Option<(EVMU256, EVMU256)> {
+    if output.len() < 64 {
+        return None;
+    }
+    let reserve_0 = EVMU256::try_from_be_slice(&output[0..32]).unwrap();
+    let reserve_1 = EVMU256::try_from_be_slice(&output[32..64]).unwrap();
+    Some((reserve_0, reserve_1))
 }
@@ -0,0 +1,158 @@
+//! Proxies deploying local contracts in their upgradeable form
+//! (`--proxy-contracts`).
+//!
+//! The proxies forward every call to their implementation and bubble up its
+//! result. A transparent proxy does not forward the calls of its admin, which
+//! is never a caller of the campaign, so transparent and UUPS proxies are both
+//! plain EIP-1967 proxies, the upgrade logic of UUPS being in the
+//! implementation.
+use crate::evm::contract_tags::{
+    ProxyKind, EIP1822_PROXIABLE_SLOT, EIP1967_BEACON_SLOT, EIP1967_IMPLEMENTATION_SLOT,
+    OZ_LEGACY_IMPLEMENTATION_SLOT,
+};
+use crate::evm::types::EVMAddress;
+
+pub const IMPLEMENTATION_SELECTOR: [u8; 4] = [0x5c, 0x60, 0xda, 0x1b]; // implementation()
+
+pub fn parse_proxy_kind(kind: &str) -> Option<ProxyKind> {
+    match kind {
+        "transparent" | "uups" => Some(ProxyKind::EIP1967),
+        "beacon" => Some(ProxyKind::Beacon),
+        _ => None,
+    }
+}
+
+/// Constructor argument of the proxies and beacons
+pub fn address_arg(address: EVMAddress) -> Vec<u8> {
+    [vec![0; 12], address.0.to_vec()].concat()
+}
+
+fn slot(slot: &str) -> Vec<u8> {
+    hex::decode(slot).unwrap()
+}
+
+fn push32(code: &mut Vec<u8>, word: &[u8]) {
+    code.push(0x7f);
+    code.extend(vec![0; 32 - word.len()]);
+    code.extend(word);
+}
+
+/// Runtime code calling the implementation left on the stack by
+/// `load_implementation` with the calldata, returning or reverting with its
+/// return data
+fn forwarding_runtime(load_implementation: Vec<u8>) -> Vec<u8> {
+    let mut code = load_implementation;
+    // CALLDATACOPY(0, 0, CALLDATASIZE)
+    code.extend([0x36, 0x60, 0x00, 0x80, 0x37]);
+    // DELEGATECALL(GAS, implementation, 0, CALLDATASIZE, 0, 0)
+    code.extend([0x60, 0x00, 0x80, 0x36, 0x81, 0x84, 0x5a, 0xf4]);
+    // RETURNDATACOPY(0, 0, RETURNDATASIZE)
+    code.extend([0x3d, 0x60, 0x00, 0x80, 0x3e]);
+    // JUMPI(return, success), REVERT(0, RETURNDATASIZE)
+    let jump_dest = code.len() + 7;
+    code.extend([0x60, jump_dest as u8, 0x57, 0x3d, 0x60, 0x00, 0xfd]);
+    // return: RETURN(0, RETURNDATASIZE)
+    code.extend([0x5b, 0x3d, 0x60, 0x00, 0xf3]);
+    code
+}
+
+/// Creation code storing its argument (the last 32 bytes of the code) in
+/// `slot` and deploying `runtime`
+fn creation_code(slot: &[u8], runtime: Vec<u8>, arg: Vec<u8>) -> Vec<u8> {
+    // CODECOPY(0, CODESIZE - 32, 32), SSTORE(slot, MLOAD(0))
+    let mut code = vec![
+        0x60, 0x20, 0x60, 0x20, 0x38, 0x03, 0x60, 0x00, 0x39, 0x60, 0x00, 0x51,
+    ];
+    push32(&mut code, slot);
+    code.push(0x55);
+    // CODECOPY(0, runtime, len), RETURN(0, len)
+    let runtime_offset = code.len() + 11;
+    code.extend([0x60, runtime.len() as u8, 0x80, 0x60, runtime_offset as u8]);
+    code.extend([0x60, 0x00, 0x39, 0x60, 0x00, 0xf3]);
+    code.extend(runtime);
+    code.extend(arg);
+    code
+}
+
+/// Slot holding the target of the proxy, the beacon for beacon proxies and
+/// the implementation otherwise
+fn target_slot(kind: ProxyKind) -> Vec<u8> {
+    match kind {
+        ProxyKind::EIP1967 => slot(EIP1967_IMPLEMENTATION_SLOT),
+        ProxyKind::EIP1822 => slot(EIP1822_PROXIABLE_SLOT),
+        ProxyKind::OZLegacy => slot(OZ_LEGACY_IMPLEMENTATION_SLOT),
+        ProxyKind::Beacon => slot(EIP1967_BEACON_SLOT),
+        ProxyKind::MinimalClone | ProxyKind::Diamond => {
+            panic!("{:?} proxies are not deployable", kind)
+        }
+    }
+}
+
+fn proxy_runtime(kind: ProxyKind) -> Vec<u8> {
+    let mut load_implementation = vec![];
+    match kind {
+        ProxyKind::Beacon => {
+            // MSTORE(0, selector << 224)
+            load_implementation.push(0x63);
+            load_implementation.extend(IMPLEMENTATION_SELECTOR);
+            load_implementation.extend([0x60, 0xe0, 0x1b, 0x60, 0x00, 0x52]);
+            // STATICCALL(GAS, SLOAD(slot), 0, 4, 0, 32), MLOAD(0)
+            load_implementation.extend([0x60, 0x20, 0x60, 0x00, 0x60, 0x04, 0x60, 0x00]);
+            push32(&mut load_implementation, &target_slot(kind));
+            load_implementation.extend([0x54, 0x5a, 0xfa, 0x50, 0x60, 0x00, 0x51]);
+        }
+        _ => {
+            // SLOAD(slot)
+            push32(&mut load_implementation, &target_slot(kind));
+            load_implementation.push(0x54);
+        }
+    }
+    forwarding_runtime(load_implementation)
+}
+
+/// Creation code of a proxy of `target`, the beacon for beacon proxies and the
+/// implementation otherwise
+pub fn proxy_creation_code(kind: ProxyKind, target: EVMAddress) -> Vec<u8> {
+    creation_code(&target_slot(kind), proxy_runtime(kind), address_arg(target))
+}
+
+/// Creation code of a beacon returning `implementation` from
+/// `implementation()`, stored in slot 0
+pub fn beacon_creation_code(implementation: EVMAddress) -> Vec<u8> {
+    // MSTORE(0, SLOAD(0)), RETURN(0, 32)
+    let runtime = vec![
+        0x60, 0x00, 0x54, 0x60, 0x00, 0x52, 0x60, 0x20, 0x60, 0x00, 0xf3,
+    ];
+    creation_code(&[0], runtime, address_arg(implementation))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::evm::contract_tags::classify_proxy;
+
+    #[test]
+    fn test_proxy_code() {
+        for kind in [
+            ProxyKind::EIP1967,
+            ProxyKind::EIP1822,
+            ProxyKind::OZLegacy,
+            ProxyKind::Beacon,
+        ] {
+            let runtime = proxy_runtime(kind);
+            assert_eq!(classify_proxy(&runtime), Some(kind));
+            // PUSH1 dest JUMPI
+            let jumpi = runtime.iter().rposition(|op| *op == 0x57).unwrap();
+            assert_eq!(runtime[runtime[jumpi - 1] as usize], 0x5b);
+
+            let target = EVMAddress::from_slice(&[0x11; 20]);
+            let code = proxy_creation_code(kind, target);
+            let (runtime_len, runtime_offset) = (code[47] as usize, code[50] as usize);
+            assert_eq!(
+                code[runtime_offset..runtime_offset + runtime_len],
+                runtime[..]
+            );
+            assert_eq!(code[code.len() - 20..], [0x11; 20]);
+        }
+    }
+}
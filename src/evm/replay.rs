@@ -0,0 +1,323 @@
+//! Structured replay reports. Replaying with `--replay-file` writes a JSON
+//! report per transaction to `{work_dir}/replay/{file}/{idx}.json`, and
+//! `ityfuzz replay-diff` compares the reports of two replays (e.g., the same
+//! PoC at two block numbers or against two bytecode versions).
+use crate::evm::contract_tags::{ContractTag, ContractTags};
+use crate::evm::corpus_initializer::ABIMap;
+use crate::evm::middlewares::call_printer::{CallPrinter, CallType};
+use crate::evm::oracles::amm::selector;
+use crate::evm::types::{EVMAddress, EVMFuzzState, EVMQueueExecutor, EVMU256};
+use crate::evm::vm::EVMState;
+use crate::state::HasExecutionResult;
+use bytes::Bytes;
+use clap::Parser;
+use glob::glob;
+use libafl::prelude::HasMetadata;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+use tracing::info;
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct CallFrame {
+    pub depth: usize,
+    pub call_type: CallType,
+    pub caller: String,
+    pub contract: String,
+    /// Resolved from the ABIs of the campaign
+    pub function: Option<String>,
+    pub input: String,
+    pub value: String,
+    pub output: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Log {
+    pub contract: String,
+    /// `data(topics)`
+    pub log: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct BalanceChange {
+    pub eth: Option<(EVMU256, EVMU256)>,
+    /// Token name to (before, after)
+    pub tokens: BTreeMap<String, (EVMU256, EVMU256)>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct TxReport {
+    pub index: usize,
+    /// Transaction, as in the replayed file
+    pub transaction: String,
+    pub reverted: bool,
+    pub output: String,
+    pub calls: Vec<CallFrame>,
+    pub logs: Vec<Log>,
+    /// Contract to slot to (before, after)
+    pub storage_diff: BTreeMap<String, BTreeMap<EVMU256, (EVMU256, EVMU256)>>,
+    /// Balance changes of the callers
+    pub balance_changes: BTreeMap<String, BalanceChange>,
+    /// Outputs of the oracles violated by the transaction
+    pub oracles: Vec<Value>,
+}
+
+impl TxReport {
+    /// Report of the transaction just executed on `pre`
+    pub fn new(
+        index: usize,
+        transaction: String,
+        pre: &EVMState,
+        state: &mut EVMFuzzState,
+        executor: &mut EVMQueueExecutor,
+        printer: &CallPrinter,
+        oracles: Vec<Value>,
+    ) -> Self {
+        let result = state.get_execution_result().clone();
+        let post = &result.new_state.state;
+        let name = |addr: &EVMAddress| printer.translate_address(*addr);
+
+        let abis = state.metadata_map().get::<ABIMap>();
+        let mut calls = vec![];
+        let mut logs = vec![];
+        for (depth, call) in &printer.results.data {
+            if let CallType::Event = call.call_type {
+                logs.push(Log {
+                    contract: call.contract.clone(),
+                    log: call.input.clone(),
+                });
+                continue;
+            }
+            let function = hex::decode(call.input.get(0..8).unwrap_or_default())
+                .ok()
+                .and_then(|sig| abis?.get(&<[u8; 4]>::try_from(sig).ok()?))
+                .map(|abi| abi.function_name.clone());
+            calls.push(CallFrame {
+                depth: *depth,
+                call_type: call.call_type.clone(),
+                caller: call.caller.clone(),
+                contract: call.contract.clone(),
+                function,
+                input: call.input.clone(),
+                value: call.value.clone(),
+                output: call.results.clone(),
+            });
+        }
+
+        let mut storage_diff = BTreeMap::new();
+        let contracts: HashSet<&EVMAddress> = pre.state.keys().chain(post.state.keys()).collect();
+        for contract in contracts {
+            let (before, after) = (pre.state.get(contract), post.state.get(contract));
+            let slots: HashSet<&EVMU256> = before
+                .iter()
+                .chain(after.iter())
+                .flat_map(|slots| slots.keys())
+                .collect();
+            let diff: BTreeMap<EVMU256, (EVMU256, EVMU256)> = slots
+                .into_iter()
+                .map(|slot| {
+                    let value = |slots: Option<&HashMap<EVMU256, EVMU256>>| {
+                        slots
+                            .and_then(|slots| slots.get(slot).cloned())
+                            .unwrap_or_default()
+                    };
+                    (*slot, (value(before), value(after)))
+                })
+                .filter(|(_, (before, after))| before != after)
+                .collect();
+            if !diff.is_empty() {
+                storage_diff.insert(name(contract), diff);
+            }
+        }
+
+        let tokens = state
+            .metadata_map()
+            .get::<ContractTags>()
+            .map(|tags| tags.with_tag(ContractTag::ERC20))
+            .unwrap_or_default();
+        let mut balance_changes = BTreeMap::new();
+        for caller in state.callers_pool.clone() {
+            let mut change = BalanceChange::default();
+            let eth = (
+                pre.balance.get(&caller).cloned().unwrap_or_default(),
+                post.balance.get(&caller).cloned().unwrap_or_default(),
+            );
+            if eth.0 != eth.1 {
+                change.eth = Some(eth);
+            }
+            for token in &tokens {
+                let mut call = selector("balanceOf(address)").to_vec();
+                call.extend([0; 12]);
+                call.extend(caller.as_bytes());
+                let before = balance_of(executor, state, pre, *token, &call);
+                let after = balance_of(executor, state, post, *token, &call);
+                if before != after {
+                    change.tokens.insert(name(token), (before, after));
+                }
+            }
+            if change.eth.is_some() || !change.tokens.is_empty() {
+                balance_changes.insert(name(&caller), change);
+            }
+        }
+
+        Self {
+            index,
+            transaction,
+            reverted: result.reverted,
+            output: hex::encode(result.output),
+            calls,
+            logs,
+            storage_diff,
+            balance_changes,
+            oracles,
+        }
+    }
+
+    pub fn dump(&self, dir: &str) {
+        fs::create_dir_all(dir).expect("failed to create replay report dir");
+        fs::write(
+            format!("{}/{}.json", dir, self.index),
+            serde_json::to_string_pretty(self).expect("failed to serialize replay report"),
+        )
+        .expect("failed to write replay report");
+    }
+}
+
+fn balance_of(
+    executor: &mut EVMQueueExecutor,
+    state: &mut EVMFuzzState,
+    vm_state: &EVMState,
+    token: EVMAddress,
+    call: &[u8],
+) -> EVMU256 {
+    let host = &mut executor.host;
+    let current = std::mem::replace(&mut host.evmstate, vm_state.clone());
+    let balance = host
+        .probe_static_call(token, Bytes::from(call.to_vec()), state)
+        .filter(|output| output.len() >= 32)
+        .map(|output| EVMU256::try_from_be_slice(&output[0..32]).unwrap())
+        .unwrap_or_default();
+    host.evmstate = current;
+    balance
+}
+
+/// Directory of the reports of a replayed file, keyed by its path so that
+/// files with the same name in different directories do not collide
+pub fn report_dir(work_dir: &str, replayed_file: &Path) -> String {
+    let relative = replayed_file
+        .components()
+        .filter_map(|component| match component {
+            Component::Normal(part) => Some(part),
+            _ => None,
+        })
+        .collect::<PathBuf>();
+    format!("{}/replay/{}", work_dir, relative.display())
+}
+
+/// Paths of `a` and `b` whose values differ
+pub fn json_diff(path: String, a: &Value, b: &Value, diffs: &mut Vec<Value>) {
+    match (a, b) {
+        (Value::Object(a_map), Value::Object(b_map)) => {
+            let keys: BTreeSet<&String> = a_map.keys().chain(b_map.keys()).collect();
+            for key in keys {
+                json_diff(
+                    format!("{}.{}", path, key),
+                    a_map.get(key).unwrap_or(&Value::Null),
+                    b_map.get(key).unwrap_or(&Value::Null),
+                    diffs,
+                );
+            }
+        }
+        (Value::Array(a_items), Value::Array(b_items)) if a_items.len() == b_items.len() => {
+            for (idx, (a_item, b_item)) in a_items.iter().zip(b_items).enumerate() {
+                json_diff(format!("{}[{}]", path, idx), a_item, b_item, diffs);
+            }
+        }
+        _ if a != b => diffs.push(json!({ "path": path, "a": a, "b": b })),
+        _ => {}
+    }
+}
+
+/// Compare the replay reports of two work dirs
+#[derive(Parser, Debug)]
+pub struct ReplayDiffArgs {
+    /// Work dir of the first replay
+    a: String,
+    /// Work dir of the second replay
+    b: String,
+    /// Write the differences to this file instead of stdout
+    #[arg(long)]
+    output: Option<String>,
+}
+
+pub fn replay_diff_main(args: ReplayDiffArgs) {
+    let (a_dir, b_dir) = (
+        Path::new(&args.a).join("replay"),
+        Path::new(&args.b).join("replay"),
+    );
+    let reports = |dir: &Path| {
+        glob(&format!("{}/**/*.json", dir.display()))
+            .expect("Failed to read glob pattern")
+            .flatten()
+            .filter_map(|report| report.strip_prefix(dir).ok().map(Path::to_path_buf))
+            .collect::<BTreeSet<_>>()
+    };
+    let read = |path: &Path| -> Value {
+        fs::read_to_string(path)
+            .ok()
+            .and_then(|report| serde_json::from_str(&report).ok())
+            .unwrap_or(Value::Null)
+    };
+    let mut diffs = BTreeMap::new();
+    // reports only written by one of the replays differ from null
+    for relative in reports(&a_dir).union(&reports(&b_dir)) {
+        let a = read(&a_dir.join(relative));
+        let b = read(&b_dir.join(relative));
+        let mut report_diffs = vec![];
+        json_diff(String::new(), &a, &b, &mut report_diffs);
+        if !report_diffs.is_empty() {
+            diffs.insert(relative.to_string_lossy().to_string(), report_diffs);
+        }
+    }
+
+    info!("{} transaction reports differ", diffs.len());
+    let diffs = serde_json::to_string_pretty(&diffs).unwrap();
+    match args.output {
+        Some(output) => fs::write(output, diffs).expect("failed to write diff"),
+        None => println!("{}", diffs),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_json_diff() {
+        let a = json!({"reverted": false, "calls": [{"output": "00"}], "logs": []});
+        let b = json!({"reverted": true, "calls": [{"output": "01"}], "logs": []});
+        let mut diffs = vec![];
+        json_diff(String::new(), &a, &b, &mut diffs);
+        assert_eq!(
+            diffs,
+            vec![
+                json!({"path": ".calls[0].output", "a": "00", "b": "01"}),
+                json!({"path": ".reverted", "a": false, "b": true}),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_report_dir() {
+        assert_eq!(
+            report_dir("work", Path::new("a/exploit.t")),
+            "work/replay/a/exploit.t"
+        );
+        assert_eq!(
+            report_dir("work", Path::new("/tmp/b/exploit.t")),
+            "work/replay/tmp/b/exploit.t"
+        );
+    }
+}
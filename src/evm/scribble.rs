@@ -0,0 +1,233 @@
+//! Scribble annotations (`#if_succeeds`, `#invariant`, ...) of local builds.
+//!
+//! Instrumented contracts emit `AssertionFailed("<id>: <message>")` when an
+//! annotation is violated. The instrumentation metadata written by
+//! `scribble --instrumentation-metadata-file` maps the id back to the
+//! annotation and its location in the original sources.
+use crate::evm::srcmap::parser::SourceMapLocation;
+use crate::evm::EVMTargetType;
+use glob::glob;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use tracing::info;
+
+/// Entry of the `propertyMap` of the instrumentation metadata
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct PropertyDesc {
+    id: usize,
+    contract: String,
+    /// `start:length:file_idx` in the original sources
+    annotation_source: String,
+    #[serde(rename = "type")]
+    kind: String,
+    #[serde(default)]
+    target_name: String,
+    #[serde(default)]
+    message: String,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct InstrumentationMetadata {
+    property_map: Vec<PropertyDesc>,
+    #[serde(default)]
+    original_source_list: Vec<String>,
+}
+
+#[derive(Clone, Debug)]
+pub struct Annotation {
+    pub id: usize,
+    /// `if_succeeds`, `invariant`, ...
+    pub kind: String,
+    pub contract: String,
+    /// Annotated function, variable or contract
+    pub target: String,
+    /// Source of the annotation, its message if the source is not found
+    pub text: String,
+    pub location: SourceMapLocation,
+    /// 1-based, 0 if the source is not found
+    pub line: usize,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct ScribbleMetadata {
+    pub annotations: HashMap<usize, Annotation>,
+}
+
+impl ScribbleMetadata {
+    /// `root` is the directory the original sources are relative to
+    pub fn from_json(json: &str, root: &Path) -> Self {
+        let metadata: InstrumentationMetadata =
+            serde_json::from_str(json).expect("failed to parse scribble metadata");
+        let sources = metadata
+            .original_source_list
+            .iter()
+            .map(|file| {
+                [root.join(file), PathBuf::from(file)]
+                    .iter()
+                    .find_map(|path| fs::read_to_string(path).ok())
+            })
+            .collect::<Vec<_>>();
+
+        let annotations = metadata
+            .property_map
+            .into_iter()
+            .map(|property| {
+                let mut parts = property
+                    .annotation_source
+                    .split(':')
+                    .map(|part| part.parse::<usize>().unwrap_or_default());
+                let (offset, length, file_idx) = (
+                    parts.next().unwrap_or_default(),
+                    parts.next().unwrap_or_default(),
+                    parts.next().unwrap_or_default(),
+                );
+                let source = sources.get(file_idx).and_then(|source| source.as_ref());
+                let text = source
+                    .and_then(|source| source.get(offset..offset + length))
+                    .map(|text| text.trim().to_string())
+                    .unwrap_or(property.message);
+                let line = source
+                    .and_then(|source| source.get(..offset))
+                    .map_or(0, |before| before.matches('\n').count() + 1);
+                let annotation = Annotation {
+                    id: property.id,
+                    kind: property.kind,
+                    contract: property.contract,
+                    target: property.target_name,
+                    text,
+                    location: SourceMapLocation::new(
+                        metadata.original_source_list.get(file_idx).cloned(),
+                        Some(file_idx),
+                        offset,
+                        length,
+                    ),
+                    line,
+                };
+                (property.id, annotation)
+            })
+            .collect();
+        Self { annotations }
+    }
+
+    pub fn from_file(path: &str) -> Self {
+        let json = fs::read_to_string(path).expect("failed to read scribble metadata");
+        Self::from_json(&json, Path::new(path).parent().unwrap_or(Path::new(".")))
+    }
+
+    /// Annotation violated given the `AssertionFailed` message
+    pub fn annotation(&self, message: &str) -> Option<&Annotation> {
+        let (id, _) = message.split_once(':')?;
+        self.annotations.get(&id.trim().parse().ok()?)
+    }
+}
+
+const METADATA_FILE: &str = "scribble-metadata.json";
+
+fn project_root(target: &str) -> &Path {
+    Path::new(target).parent().unwrap_or(Path::new("."))
+}
+
+/// Instrumentation metadata of the project `target` belongs to
+pub fn metadata_path(target: &str) -> String {
+    project_root(target)
+        .join(METADATA_FILE)
+        .to_string_lossy()
+        .to_string()
+}
+
+fn run(root: &Path, command: &[&str]) -> bool {
+    Command::new(command[0])
+        .args(&command[1..])
+        .current_dir(root)
+        .status()
+        .map_or(false, |status| status.success())
+}
+
+/// Instrument the sources of the Foundry or Hardhat project `target` belongs
+/// to, rebuild it and restore the sources
+pub fn instrument(target: &str, target_type: EVMTargetType) {
+    let (sources_dir, build): (_, &[&str]) = match target_type {
+        EVMTargetType::Foundry => ("src", &["forge", "build", "--build-info"]),
+        EVMTargetType::Hardhat => ("contracts", &["npx", "hardhat", "compile"]),
+        _ => panic!("scribble instrumentation requires a Foundry or Hardhat project"),
+    };
+    if !run(Path::new("."), &["scribble", "--version"]) {
+        panic!(
+            "scribble not found, install it or pass the metadata of pre-instrumented artifacts \
+             with --scribble-metadata"
+        );
+    }
+
+    let root = project_root(target);
+    let files = glob(&root.join(sources_dir).join("**/*.sol").to_string_lossy())
+        .expect("failed to read glob pattern")
+        .filter_map(|file| file.ok())
+        .filter_map(|file| {
+            file.strip_prefix(root)
+                .ok()
+                .map(|file| file.to_string_lossy().to_string())
+        })
+        .collect::<Vec<_>>();
+    let files = files.iter().map(String::as_str).collect::<Vec<_>>();
+    info!("instrumenting {} files with scribble", files.len());
+
+    let mut arm = vec![
+        "scribble",
+        "--arm",
+        "--output-mode",
+        "files",
+        "--instrumentation-metadata-file",
+        METADATA_FILE,
+    ];
+    arm.extend(&files);
+    let armed = run(root, &arm);
+    let built = armed && run(root, build);
+    let mut disarm = vec!["scribble", "--disarm"];
+    disarm.extend(&files);
+    let disarmed = run(root, &disarm);
+    assert!(armed, "scribble failed to instrument the sources");
+    assert!(built, "failed to build the instrumented sources");
+    assert!(disarmed, "scribble failed to restore the sources");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_scribble_metadata() {
+        let root = std::env::temp_dir().join("ityfuzz_scribble_test");
+        fs::create_dir_all(root.join("src")).unwrap();
+        let source = "contract C {\n    /// #if_succeeds {:msg \"P0\"} x > 0;\n    function f() public {}\n}\n";
+        fs::write(root.join("src/C.sol"), source).unwrap();
+        let text = "#if_succeeds {:msg \"P0\"} x > 0;";
+        let offset = source.find(text).unwrap();
+        let json = format!(
+            r#"{{
+                "propertyMap": [{{
+                    "id": 3, "contract": "C", "filename": "src/C.sol",
+                    "propertySource": "", "annotationSource": "{}:{}:0",
+                    "target": "function", "type": "if_succeeds", "targetName": "f",
+                    "message": "P0"
+                }}],
+                "originalSourceList": ["src/C.sol"]
+            }}"#,
+            offset,
+            text.len()
+        );
+
+        let metadata = ScribbleMetadata::from_json(&json, &root);
+        let annotation = metadata.annotation("3: P0").unwrap();
+        assert_eq!(annotation.text, text);
+        assert_eq!(annotation.line, 2);
+        assert_eq!(annotation.target, "f");
+        assert_eq!(annotation.location.file, Some("src/C.sol".to_string()));
+        assert!(metadata.annotation("4: P1").is_none());
+        assert!(metadata.annotation("typed bug").is_none());
+    }
+}
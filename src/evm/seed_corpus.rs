@@ -0,0 +1,224 @@
+//! Seeding a campaign with `--seed-corpus <dir|glob>`, e.g., the corpus of a
+//! previous campaign or the PoC of a known exploit.
+//!
+//! A seed file is either a replayable trace (one concise transaction per line,
+//! as in the `*_replayable` files of a work dir) or a Foundry call sequence
+//! (the JSON persisted for failed invariants). The transactions of a file are
+//! executed in order, each on the state left by the previous one. They are
+//! added to the transaction corpus and the states they reach to the infant
+//! state corpus.
+use crate::evm::abi::get_abi_type_boxed;
+use crate::evm::corpus_initializer::ABIMap;
+use crate::evm::host::CALL_UNTIL;
+#[cfg(feature = "flashloan_v2")]
+use crate::evm::input::EVMInputTy;
+use crate::evm::input::{ConciseEVMInput, EVMInput};
+use crate::evm::mutator::AccessPattern;
+use crate::evm::types::{
+    EVMAddress, EVMFuzzExecutor, EVMFuzzState, EVMInfantStateState, EVMStagedVMState, EVMU256,
+};
+use crate::input::VMInputT;
+use crate::scheduler::SortedDroppingScheduler;
+use crate::state::{HasExecutionResult, HasItyState};
+use crate::state_input::StagedVMState;
+use bytes::Bytes;
+use glob::glob;
+use libafl::prelude::{HasMetadata, ObserversTuple, Scheduler, Testcase};
+use libafl::state::UsesState;
+use libafl::Evaluator;
+use serde_json::Value;
+use std::cell::RefCell;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::str::FromStr;
+use tracing::{info, warn};
+
+/// Files matched by `--seed-corpus`, every file under it if it is a directory
+fn seed_files(pattern: &str) -> Vec<PathBuf> {
+    let pattern = if Path::new(pattern).is_dir() {
+        format!("{}/**/*", pattern.trim_end_matches('/'))
+    } else {
+        pattern.to_string()
+    };
+    glob(&pattern)
+        .expect("Failed to read glob pattern")
+        .flatten()
+        .filter(|path| path.is_file())
+        .collect()
+}
+
+/// Foundry call sequence: an array of calls, either as is or in the
+/// `call_sequence` field of the persisted failure
+fn foundry_calls(seed: &str) -> Option<Vec<Value>> {
+    match serde_json::from_str::<Value>(seed).ok()? {
+        Value::Array(calls) => Some(calls),
+        Value::Object(mut failure) => match failure.remove("call_sequence")? {
+            Value::Array(calls) => Some(calls),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// Input of a call of a Foundry sequence, with its arguments decoded with the
+/// ABIs of the campaign when the function is known
+fn foundry_input(call: &Value, abis: Option<&ABIMap>) -> Option<EVMInput> {
+    // `addr` in counterexamples, `call_details.target` in fuzz failures
+    let field = |name: &str| {
+        call.get(name)
+            .or_else(|| {
+                call.get("call_details")
+                    .and_then(|details| details.get(name))
+            })
+            .and_then(|value| value.as_str())
+    };
+    let caller = EVMAddress::from_str(field("sender")?).ok()?;
+    let contract = EVMAddress::from_str(field("addr").or_else(|| field("target"))?).ok()?;
+    let calldata = hex::decode(field("calldata")?.trim_start_matches("0x")).ok()?;
+    let txn_value = field("value").and_then(|value| EVMU256::from_str(value).ok());
+
+    let data = calldata
+        .get(0..4)
+        .and_then(|sig| abis?.get(&<[u8; 4]>::try_from(sig).ok()?))
+        .and_then(|abi| {
+            let mut abi_instance = get_abi_type_boxed(&abi.abi);
+            abi_instance.set_func_with_signature(abi.function, &abi.function_name, &abi.abi);
+            abi_instance
+                .set_bytes(calldata.clone())
+                .then_some(abi_instance)
+        });
+    Some(EVMInput {
+        caller,
+        contract,
+        direct_data: if data.is_none() {
+            Bytes::from(calldata)
+        } else {
+            Bytes::new()
+        },
+        data,
+        sstate: StagedVMState::new_uninitialized(),
+        sstate_idx: 0,
+        txn_value,
+        step: false,
+        env: Default::default(),
+        access_pattern: Rc::new(RefCell::new(AccessPattern::new())),
+        #[cfg(feature = "flashloan_v2")]
+        liquidation_percent: 0,
+        #[cfg(feature = "flashloan_v2")]
+        input_type: EVMInputTy::ABI,
+        randomness: vec![0],
+        repeat: 1,
+    })
+}
+
+/// Execute the seeds matched by `pattern` from the initial state of the
+/// campaign, returns the number of transactions loaded
+#[allow(clippy::too_many_arguments)]
+pub fn load_seed_corpus<Z, EM, OT, SC>(
+    pattern: &str,
+    initial_state: &EVMStagedVMState,
+    fuzzer: &mut Z,
+    executor: &mut EVMFuzzExecutor<OT>,
+    state: &mut EVMFuzzState,
+    manager: &mut EM,
+    scheduler: &mut SC,
+    infant_scheduler: &mut SortedDroppingScheduler<EVMInfantStateState>,
+) -> usize
+where
+    Z: Evaluator<EVMFuzzExecutor<OT>, EM, State = EVMFuzzState>,
+    EM: UsesState<State = EVMFuzzState>,
+    OT: ObserversTuple<EVMFuzzState>,
+    SC: Scheduler<State = EVMFuzzState>,
+{
+    let mut loaded = 0;
+    for file in seed_files(pattern) {
+        let seed = match fs::read_to_string(&file) {
+            Ok(seed) => seed,
+            Err(e) => {
+                warn!("failed to read seed {}: {:?}", file.display(), e);
+                continue;
+            }
+        };
+        let inputs: Vec<(EVMInput, u32)> = match foundry_calls(&seed) {
+            Some(calls) => {
+                let abis = state.metadata_map().get::<ABIMap>();
+                calls
+                    .iter()
+                    .filter_map(|call| foundry_input(call, abis))
+                    .map(|input| (input, u32::MAX))
+                    .collect()
+            }
+            None => seed
+                .lines()
+                .filter(|txn| txn.len() >= 4)
+                .filter_map(|txn| serde_json::from_str::<ConciseEVMInput>(txn).ok())
+                .map(|txn| txn.to_input(initial_state.clone()))
+                .collect(),
+        };
+        if inputs.is_empty() {
+            warn!("no transaction found in seed {}", file.display());
+            continue;
+        }
+
+        // the initial state is the first infant state
+        let (mut vm_state, mut state_idx) = (initial_state.clone(), 0);
+        for (mut input, call_until) in inputs {
+            input.set_staged_state(vm_state.clone(), state_idx);
+            unsafe {
+                CALL_UNTIL = call_until;
+            }
+            let (_, corpus_idx) = fuzzer
+                .evaluate_input_events(state, executor, manager, input.clone(), false)
+                .expect("failed to execute seed");
+            if corpus_idx.is_none() {
+                let idx = state
+                    .add_tx_to_corpus(Testcase::new(input))
+                    .expect("failed to add seed to corpus");
+                scheduler
+                    .on_add(state, idx)
+                    .expect("failed to call scheduler on_add");
+            }
+            loaded += 1;
+
+            // later transactions of the seed run on the state it reached
+            if !state.get_execution_result().reverted {
+                vm_state = state.get_execution_result().new_state.clone();
+                state_idx = state.add_infant_state(&vm_state, infant_scheduler, state_idx);
+            }
+        }
+        unsafe {
+            CALL_UNTIL = u32::MAX;
+        }
+    }
+    info!("Loaded {} transactions from the seed corpus", loaded);
+    loaded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_foundry_input() {
+        let sequence = r#"{"call_sequence": [{
+            "sender": "0x0000000000000000000000000000000000000001",
+            "addr": "0x0000000000000000000000000000000000000002",
+            "calldata": "0xdeadbeef00"
+        }]}"#;
+        let calls = foundry_calls(sequence).unwrap();
+        assert_eq!(calls.len(), 1);
+        let input = foundry_input(&calls[0], None).unwrap();
+        assert_eq!(
+            input.contract,
+            EVMAddress::from_str("0x0000000000000000000000000000000000000002").unwrap()
+        );
+        assert!(input.data.is_none());
+        assert_eq!(
+            input.direct_data.to_vec(),
+            vec![0xde, 0xad, 0xbe, 0xef, 0x00]
+        );
+        // replayable traces are not JSON arrays
+        assert!(foundry_calls("{\"caller\": \"0x00\"}\n{\"caller\": \"0x00\"}").is_none());
+    }
+}
@@ -11,6 +11,7 @@ use serde::Serialize;
 use tracing::{debug, error};
 
 use super::{
+    onchain::flashloan_venue::FlashloanVenue,
     types::{EVMAddress, EVMU256},
     uniswap::{self, UniswapProvider},
     Chain,
@@ -113,6 +114,7 @@ pub struct Tx {
     fn_args: String,
     liq_percent: u8,
     liq_idx: u32,
+    is_flashloan_v3: bool,
 }
 
 impl<T: SolutionTx> From<&T> for Tx {
@@ -145,9 +147,21 @@ pub struct TemplateArgs {
     solution: String,
     trace: Vec<Tx>,
     stepping_with_return: bool,
+    flashloan: Option<FlashloanCallback>,
     output_dir: String,
 }
 
+/// Callback of a loan from a real venue, the txs in the loan are made in it
+#[derive(Debug, Serialize, Default)]
+pub struct FlashloanCallback {
+    venue: String,
+    is_aave: bool,
+    is_balancer: bool,
+    is_uniswap_v3: bool,
+    is_uniswap_v2: bool,
+    trace: Vec<Tx>,
+}
+
 impl TemplateArgs {
     pub fn new(solution: String, trace: Vec<Tx>) -> Result<Self, String> {
         let cli_args = CLI_ARGS.get();
@@ -158,17 +172,22 @@ impl TemplateArgs {
 
         // Stepping with return
         let stepping_with_return = trace.iter().any(|tx| tx.fn_selector == "0x00000000");
+        let (trace, mut flashloan) = split_flashloan(trace);
         let mut trace: Vec<Tx> = trace
             .into_iter()
             .filter(|tx| tx.fn_selector != "0x00000000")
             .collect();
 
         setup_trace(&mut trace, &cli_args);
+        if let Some(flashloan) = flashloan.as_mut() {
+            setup_trace(&mut flashloan.trace, &cli_args);
+        }
         let router = get_router(&cli_args.chain);
         let contract_name = make_contract_name(&cli_args);
-        let include_interface = trace
-            .iter()
-            .any(|x| !x.raw_code.is_empty() || x.is_borrow || x.liq_percent > 0);
+        let include_interface = flashloan.is_some()
+            || trace
+                .iter()
+                .any(|x| !x.raw_code.is_empty() || x.is_borrow || x.liq_percent > 0);
 
         Ok(Self {
             contract_name,
@@ -183,6 +202,7 @@ impl TemplateArgs {
             solution,
             trace,
             stepping_with_return,
+            flashloan,
             output_dir: cli_args.output_dir.clone(),
         })
     }
@@ -220,6 +240,54 @@ fn setup_trace(trace: &mut Vec<Tx>, cli_args: &CliArgs) {
     }
 }
 
+/// Move the txs made while the first loan from a venue is taken into its
+/// callback, and make the test contract the receiver of the loan. Uniswap V3
+/// calls back the caller of `flash`, so the test contract takes that loan
+/// without pranking.
+fn split_flashloan(mut trace: Vec<Tx>) -> (Vec<Tx>, Option<FlashloanCallback>) {
+    let loan = trace
+        .iter()
+        .position(|tx| FlashloanVenue::from_signature(&tx.fn_signature).is_some());
+    let (start, venue) = match loan {
+        Some(idx) => (
+            idx,
+            FlashloanVenue::from_signature(&trace[idx].fn_signature).unwrap(),
+        ),
+        None => return (trace, None),
+    };
+    // the venue regains control with the first step after the loan
+    let end = match trace
+        .iter()
+        .skip(start + 1)
+        .position(|tx| tx.fn_selector == "0x00000000")
+    {
+        Some(idx) => start + 1 + idx,
+        None => return (trace, None),
+    };
+
+    let inner: Vec<Tx> = trace
+        .drain(start + 1..=end)
+        .filter(|tx| tx.fn_selector != "0x00000000")
+        .collect();
+    let tx = &mut trace[start];
+    let mut args: Vec<&str> = tx.fn_args.split(", ").collect();
+    if args.len() > venue.receiver_idx() {
+        args[venue.receiver_idx()] = "address(this)";
+    }
+    tx.fn_args = args.join(", ");
+    tx.is_flashloan_v3 = venue == FlashloanVenue::UniswapV3;
+
+    let callback = FlashloanCallback {
+        venue: venue.to_string(),
+        is_aave: venue == FlashloanVenue::AaveV3,
+        is_balancer: venue == FlashloanVenue::Balancer,
+        is_uniswap_v3: venue == FlashloanVenue::UniswapV3,
+        is_uniswap_v2: venue == FlashloanVenue::UniswapV2,
+        trace: inner,
+    };
+    (trace, Some(callback))
+}
+
 fn make_raw_code(tx: &Tx) -> Option<String> {
     if tx.is_borrow || tx.is_deposit {
         return None;
@@ -71,6 +71,18 @@ pub enum SourceMapAvailability {
     Unknown,
 }
 
+/// Contents of a source file, from the sources of the build artifact if it
+/// has them or from the base path
+pub fn read_source_file(file_name: &str, file_blob: &Vec<(String, String)>) -> Option<String> {
+    if let Some((_, code)) = file_blob.iter().find(|(name, _)| name == file_name) {
+        return Some(code.clone());
+    }
+    let mut file = File::open(unsafe { BASE_PATH.clone() } + file_name).ok()?;
+    let mut contents = String::new();
+    file.read_to_string(&mut contents).ok()?;
+    Some(contents)
+}
+
 fn read_source_code(
     loc: &SourceMapLocation,
     file_blob: &Vec<(String, String)>,
@@ -79,30 +91,18 @@ fn read_source_code(
     let offset = loc.offset;
     let length = loc.length;
 
-    let mut contents = String::new();
-    let bad_file = SourceMapWithCode {
-        file: file_name.clone(),
-        line_start: offset,
-        line_end: length,
-        code: "code not available".to_string(),
+    let contents = match read_source_file(&file_name, file_blob) {
+        Some(contents) => contents,
+        None => {
+            return SourceMapWithCode {
+                file: file_name.clone(),
+                line_start: offset,
+                line_end: length,
+                code: "code not available".to_string(),
+            };
+        }
     };
 
-    if file_blob.len() > 0 && file_blob.iter().any(|(name, _)| name == &file_name) {
-        let (_, code) = file_blob
-            .iter()
-            .find(|(name, _)| name == &file_name)
-            .unwrap();
-        contents = code.clone();
-    } else {
-        let mut file = match File::open(unsafe { BASE_PATH.clone() } + file_name.as_str()) {
-            Ok(f) => f,
-            Err(e) => {
-                return bad_file;
-            }
-        };
-        file.read_to_string(&mut contents).unwrap();
-    }
-
     // get starting and ending line number
     let mut line_number = 1;
     let mut start_line = 0;
@@ -0,0 +1,116 @@
+//! Machine-readable `summary.json` written in the work dir when a campaign
+//! stops.
+use crate::evm::middlewares::coverage::Coverage;
+use crate::evm::onchain::endpoints::{rpc_stats, RPCStats};
+use crate::evm::types::EVMFuzzState;
+use crate::fuzzer::STOP_REASON;
+use crate::oracle::BugMetadata;
+use crate::state::HasInfantStateState;
+use libafl::prelude::{Corpus, HasCorpus, HasMetadata};
+use libafl::state::HasExecutions;
+use libafl_bolts::current_time;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::fs;
+use std::time::Duration;
+
+#[derive(Serialize, Debug)]
+pub struct ContractCoverage {
+    pub instructions: usize,
+    pub total_instructions: usize,
+    pub branches: usize,
+    pub total_branches: usize,
+}
+
+#[derive(Serialize, Debug)]
+pub struct FoundBug {
+    /// Seconds since the start of the campaign
+    pub time: u64,
+    /// Oracle output, as in `vuln_info.jsonl`
+    pub bug: serde_json::Value,
+}
+
+#[derive(Serialize, Debug)]
+pub struct CampaignSummary {
+    pub stop_reason: String,
+    pub elapsed_secs: f64,
+    pub execs: usize,
+    pub execs_per_sec: f64,
+    pub corpus_size: usize,
+    pub infant_states: usize,
+    pub coverage: HashMap<String, ContractCoverage>,
+    pub bugs: Vec<FoundBug>,
+    pub rpc: RPCStats,
+}
+
+impl CampaignSummary {
+    /// `start` is the time since the UNIX epoch the campaign started at
+    pub fn new(
+        state: &mut EVMFuzzState,
+        coverage: &mut Coverage,
+        start: Duration,
+        stop_reason: String,
+    ) -> Self {
+        let elapsed = current_time().saturating_sub(start);
+        let execs = *state.executions();
+        let bugs = state
+            .metadata_map()
+            .get::<BugMetadata>()
+            .map(|bugs| {
+                bugs.found
+                    .iter()
+                    .map(|(time, bug)| FoundBug {
+                        time: time.saturating_sub(start.as_secs()),
+                        bug: bug.clone(),
+                    })
+                    .collect()
+            })
+            .unwrap_or_default();
+        Self {
+            stop_reason,
+            elapsed_secs: elapsed.as_secs_f64(),
+            execs,
+            execs_per_sec: execs as f64 / elapsed.as_secs_f64().max(1.0),
+            corpus_size: state.corpus().count(),
+            infant_states: state.get_infant_state_state().corpus().count(),
+            coverage: coverage
+                .coverage_report()
+                .coverage
+                .into_iter()
+                .map(|(name, cov)| {
+                    (
+                        name,
+                        ContractCoverage {
+                            instructions: cov.instruction_coverage,
+                            total_instructions: cov.total_instructions,
+                            branches: cov.branch_coverage,
+                            total_branches: cov.total_branches,
+                        },
+                    )
+                })
+                .collect(),
+            bugs,
+            rpc: rpc_stats(),
+        }
+    }
+
+    pub fn dump(&self, work_dir: &str) {
+        fs::write(
+            format!("{}/summary.json", work_dir),
+            serde_json::to_string_pretty(self).expect("failed to serialize summary"),
+        )
+        .expect("failed to write summary.json");
+    }
+
+    /// Writes the summary of the campaign started at `start` once the fuzz
+    /// loop has returned
+    pub fn dump_after_campaign(
+        state: &mut EVMFuzzState,
+        coverage: &mut Coverage,
+        start: Duration,
+        work_dir: &str,
+    ) {
+        let stop_reason = unsafe { STOP_REASON.clone() }.unwrap_or_default();
+        Self::new(state, coverage, start, stop_reason).dump(work_dir);
+    }
+}
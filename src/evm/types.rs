@@ -114,6 +114,34 @@ pub fn is_zero(v: EVMU256) -> bool {
     v == EVMU256::ZERO
 }
 
+/// Simpler values strictly smaller than `v`, simplest first: zero, one, the
+/// largest power of ten not above `v`, and `v` rounded down to 1-3
+/// significant digits
+pub fn simplify_u256(v: EVMU256) -> Vec<EVMU256> {
+    if v == EVMU256::ZERO {
+        return vec![];
+    }
+    let ten = EVMU256::from(10);
+    let mut magnitude = EVMU256::from(1);
+    while magnitude <= v / ten {
+        magnitude *= ten;
+    }
+
+    let mut candidates = vec![EVMU256::ZERO, EVMU256::from(1), magnitude];
+    let mut unit = magnitude;
+    for _ in 0..3 {
+        candidates.push(v / unit * unit);
+        if unit < ten {
+            break;
+        }
+        unit /= ten;
+    }
+    // candidates are already sorted
+    candidates.dedup();
+    candidates.retain(|c| *c < v);
+    candidates
+}
+
 /// As u64
 pub fn as_u64(v: EVMU256) -> u64 {
     v.as_limbs()[0]
@@ -156,10 +184,23 @@ pub fn checksum(address: &EVMAddress) -> String {
 
 #[cfg(test)]
 mod tests {
-    use crate::evm::types::{as_u64, EVMU256};
+    use crate::evm::types::{as_u64, simplify_u256, EVMU256};
 
     #[test]
     fn test_as_u64() {
         assert_eq!(as_u64(EVMU256::from(100)), 100)
     }
+
+    #[test]
+    fn test_simplify_u256() {
+        let to_u256 = |v: Vec<u64>| v.into_iter().map(EVMU256::from).collect::<Vec<_>>();
+        assert_eq!(
+            simplify_u256(EVMU256::from(123456)),
+            to_u256(vec![0, 1, 100000, 120000, 123000])
+        );
+        assert_eq!(simplify_u256(EVMU256::from(100)), to_u256(vec![0, 1]));
+        assert_eq!(simplify_u256(EVMU256::from(7)), to_u256(vec![0, 1]));
+        assert_eq!(simplify_u256(EVMU256::from(1)), to_u256(vec![0]));
+        assert!(simplify_u256(EVMU256::ZERO).is_empty());
+    }
 }
@@ -3,7 +3,7 @@ use itertools::Itertools;
 use std::borrow::Borrow;
 use std::cell::RefCell;
 use std::cmp::{max, min};
-use std::collections::{HashMap, HashSet};
+use std::collections::{BTreeSet, HashMap, HashSet};
 
 use std::collections::hash_map::DefaultHasher;
 use std::fmt::{Debug, Formatter};
@@ -45,12 +45,13 @@ use tracing::{debug, error};
 
 use crate::evm::bytecode_analyzer;
 use crate::evm::host::{
-    FuzzHost, CMP_MAP, COVERAGE_NOT_CHANGED, JMP_MAP, READ_MAP, RET_OFFSET, RET_SIZE, STATE_CHANGE,
-    WRITE_MAP,
+    is_assert_panic, FuzzHost, ASSERTION_MODE, CMP_MAP, COVERAGE_NOT_CHANGED, JMP_MAP, READ_MAP,
+    RET_OFFSET, RET_SIZE, STATE_CHANGE, WRITE_MAP,
 };
 use crate::evm::input::{ConciseEVMInput, EVMInput, EVMInputT, EVMInputTy};
 use crate::evm::middlewares::middleware::{Middleware, MiddlewareType};
 use crate::evm::onchain::flashloan::FlashloanData;
+use crate::evm::oracles::typed_bug::TypedBug;
 use crate::evm::types::{EVMAddress, EVMU256};
 use crate::evm::uniswap::generate_uniswap_router_buy;
 use crate::evm::vm::Constraint::{NoLiquidation, Value};
@@ -63,6 +64,7 @@ use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
 
 use super::middlewares::reentrancy::ReentrancyData;
+use super::middlewares::spot_price::SpotPriceData;
 
 pub const MEM_LIMIT: u64 = 500 * 1024;
 const MAX_POST_EXECUTION: usize = 10;
@@ -239,6 +241,10 @@ pub struct EVMState {
     /// Balance of addresses
     pub balance: HashMap<EVMAddress, EVMU256>,
 
+    /// Nonces of addresses that created contracts with CREATE
+    #[serde(default)]
+    pub nonces: HashMap<EVMAddress, u64>,
+
     /// Post execution context
     /// If control leak happens, we add the post execution context to the VM state,
     /// which contains all information needed to continue execution.
@@ -258,14 +264,21 @@ pub struct EVMState {
     /// selftdestruct() call in Solidity hit?
     #[serde(skip)]
     pub self_destruct: HashSet<(EVMAddress, usize)>,
-    /// bug type call in solidity type
+    /// bug type call in solidity type, ordered so that bugs are reported
+    /// deterministically
     #[serde(skip)]
-    pub typed_bug: HashSet<(String, (EVMAddress, usize))>,
+    pub typed_bug: BTreeSet<(TypedBug, (EVMAddress, usize))>,
     #[serde(skip)]
     pub arbitrary_calls: HashSet<(EVMAddress, EVMAddress, usize)>,
+    /// `assert` failures and `AssertionFailed` events in assertion mode
+    #[serde(skip)]
+    pub assertion_failures: HashSet<(String, (EVMAddress, usize))>,
 
     #[serde(skip)]
     pub reentrancy_metadata: ReentrancyData,
+
+    #[serde(skip)]
+    pub spot_price_metadata: SpotPriceData,
 }
 
 pub trait EVMStateT {
@@ -329,6 +342,11 @@ impl VMStateT for EVMState {
         self.post_execution.len()
     }
 
+    /// Failing `assert`s revert the transaction in assertion mode
+    fn has_reverted_bugs(&self) -> bool {
+        !self.assertion_failures.is_empty()
+    }
+
     /// Get flashloan information
     #[cfg(feature = "full_trace")]
     fn get_flashloan(&self) -> String {
@@ -496,6 +514,7 @@ where
             self.host.jumpi_trace = 37;
             self.host.current_self_destructs = vec![];
             self.host.current_arbitrary_calls = vec![];
+            self.host.current_assertion_failures = vec![];
             // Initially, there is no state change
             unsafe {
                 STATE_CHANGE = false;
@@ -610,7 +629,7 @@ where
     }
 
     /// Conduct a fast call that does not write to the feedback
-    fn fast_call(
+    pub fn fast_call(
         &mut self,
         address: EVMAddress,
         data: Bytes,
@@ -780,6 +799,19 @@ where
             }
         }
         let mut r = r.unwrap();
+        // a failing `assert` reverts the transaction, the oracles report the
+        // failure on the state before it
+        if unsafe { ASSERTION_MODE }
+            && r.ret == InstructionResult::Revert
+            && is_assert_panic(&r.output)
+        {
+            if self.host.current_assertion_failures.is_empty() {
+                self.host
+                    .current_assertion_failures
+                    .push(("Panic(0x01)".to_string(), (input.get_contract(), r.pc)));
+            }
+            r.new_state = vm_state.clone();
+        }
         match r.ret {
             ControlLeak
             | InstructionResult::ArbitraryExternalCallAddressBounded(_, _, _)
@@ -826,7 +858,7 @@ where
             _ => {}
         }
 
-        r.new_state.typed_bug = HashSet::from_iter(
+        r.new_state.typed_bug = BTreeSet::from_iter(
             vm_state
                 .typed_bug
                 .iter()
@@ -840,6 +872,13 @@ where
                 .cloned()
                 .chain(self.host.current_self_destructs.iter().cloned()),
         );
+        r.new_state.assertion_failures = HashSet::from_iter(
+            vm_state
+                .assertion_failures
+                .iter()
+                .cloned()
+                .chain(self.host.current_assertion_failures.iter().cloned()),
+        );
         r.new_state.arbitrary_calls = HashSet::from_iter(
             vm_state
                 .arbitrary_calls
@@ -1059,6 +1098,18 @@ where
         vm_state: &VS,
         state: &mut S,
     ) -> (Vec<Vec<u8>>) {
+        self.fast_static_call_with_status(data, vm_state, state)
+            .into_iter()
+            .map(|(out, success)| if success { out } else { vec![] })
+            .collect()
+    }
+
+    fn fast_static_call_with_status(
+        &mut self,
+        data: &Vec<(EVMAddress, Bytes)>,
+        vm_state: &VS,
+        state: &mut S,
+    ) -> Vec<(Vec<u8>, bool)> {
         unsafe {
             IS_FAST_CALL_STATIC = true;
             self.host.evmstate = vm_state
@@ -1067,6 +1118,7 @@ where
                 .clone();
             self.host.current_self_destructs = vec![];
             self.host.current_arbitrary_calls = vec![];
+            self.host.current_assertion_failures = vec![];
             self.host.call_count = 0;
             self.host.jumpi_trace = 37;
             self.host.current_typed_bug = vec![];
@@ -1088,13 +1140,9 @@ where
                 let mut interp =
                     Interpreter::new_with_memory_limit(call, 1e10 as u64, false, MEM_LIMIT);
                 let ret = self.host.run_inspect(&mut interp, state);
-                if !is_call_success!(ret) {
-                    vec![]
-                } else {
-                    interp.return_value().to_vec()
-                }
+                (interp.return_value().to_vec(), is_call_success!(ret))
             })
-            .collect::<Vec<Vec<u8>>>();
+            .collect::<Vec<(Vec<u8>, bool)>>();
 
         unsafe {
             IS_FAST_CALL_STATIC = false;
@@ -1117,6 +1165,7 @@ where
                 .clone();
             self.host.current_self_destructs = vec![];
             self.host.current_arbitrary_calls = vec![];
+            self.host.current_assertion_failures = vec![];
             self.host.call_count = 0;
             self.host.jumpi_trace = 37;
             self.host.current_typed_bug = vec![];
@@ -1187,7 +1236,7 @@ mod tests {
     use crate::evm::host::{FuzzHost, JMP_MAP};
     use crate::evm::input::{ConciseEVMInput, EVMInput, EVMInputTy};
     use crate::evm::mutator::AccessPattern;
-    use crate::evm::types::{generate_random_address, EVMFuzzState, EVMU256};
+    use crate::evm::types::{generate_random_address, EVMAddress, EVMFuzzState, EVMU256};
     use crate::evm::vm::{EVMExecutor, EVMState};
     use crate::generic_vm::vm_executor::{GenericVM, MAP_SIZE};
     use crate::state::FuzzState;
@@ -1324,4 +1373,72 @@ mod tests {
         assert_eq!(cov_changed, true);
         assert_eq!(execution_result_5.reverted, true);
     }
+
+    fn call_input(caller: EVMAddress, contract: EVMAddress) -> EVMInput {
+        EVMInput {
+            caller,
+            contract,
+            data: None,
+            sstate: StagedVMState::new_uninitialized(),
+            sstate_idx: 0,
+            txn_value: Some(EVMU256::ZERO),
+            step: false,
+            env: Default::default(),
+            access_pattern: Rc::new(RefCell::new(AccessPattern::new())),
+            #[cfg(feature = "flashloan_v2")]
+            liquidation_percent: 0,
+            direct_data: Bytes::new(),
+            #[cfg(feature = "flashloan_v2")]
+            input_type: EVMInputTy::ABI,
+            randomness: vec![],
+            repeat: 1,
+        }
+    }
+
+    #[test]
+    fn test_static_call() {
+        let mut state: EVMFuzzState = FuzzState::new(0, None);
+        let dir = tempfile::tempdir().unwrap();
+        let mut evm_executor: EVMExecutor<
+            EVMInput,
+            EVMFuzzState,
+            EVMState,
+            ConciseEVMInput,
+            StdScheduler<EVMFuzzState>,
+        > = EVMExecutor::new(
+            FuzzHost::new(
+                StdScheduler::new(),
+                dir.path().to_string_lossy().to_string(),
+            ),
+            generate_random_address(&mut state),
+        );
+
+        // sstore(0, 1)
+        let writer = EVMAddress::repeat_byte(0xb0);
+        let writer_code = "6006600c60003960066000f3600160005500".to_string();
+        // if iszero(staticcall(gas(), writer, 0, 0, 0, 0)) { revert(0, 0) }
+        let reader = EVMAddress::repeat_byte(0xb1);
+        let reader_code = format!(
+            "6028600c60003960286000f3600060006000600073{}5afa602657600080fd5b00",
+            hex::encode(writer)
+        );
+        for (address, code) in [(writer, writer_code), (reader, reader_code)] {
+            evm_executor
+                .deploy(
+                    Bytecode::new_raw(Bytes::from(hex::decode(code).unwrap())),
+                    None,
+                    address,
+                    &mut FuzzState::new(0, None),
+                )
+                .unwrap();
+        }
+
+        let caller = generate_random_address(&mut state);
+        let mut state = FuzzState::new(0, None);
+        let direct = evm_executor.execute(&call_input(caller, writer), &mut state);
+        assert!(!direct.reverted);
+        // the write is forbidden in the static call
+        let through_static = evm_executor.execute(&call_input(caller, reader), &mut state);
+        assert!(through_static.reverted);
+    }
 }
@@ -126,7 +126,7 @@ where
     /// specified in the bug_idx.
     pub fn reproduces(&mut self, state: &mut S, input: &S::Input, bug_idx: &Vec<u64>) -> bool {
         let initial_oracle_output = unsafe { ORACLE_OUTPUT.clone() };
-        if state.get_execution_result().reverted {
+        if !state.get_execution_result().is_checked() {
             return false;
         }
         // set up oracle context
@@ -224,7 +224,7 @@ where
         EMI: EventFirer<State = S>,
         OT: ObserversTuple<S>,
     {
-        if state.get_execution_result().reverted {
+        if !state.get_execution_result().is_checked() {
             return Ok(false);
         }
         {
@@ -669,7 +669,7 @@ where
     /// specified in the bug_idx.
     pub fn reproduces(&mut self, state: &mut S, input: &S::Input, bug_idx: &Vec<u64>) -> bool {
         let initial_oracle_output = unsafe { ORACLE_OUTPUT.clone() };
-        if state.get_execution_result().reverted {
+        if !state.get_execution_result().is_checked() {
             return false;
         }
         // set up oracle context
@@ -767,7 +767,7 @@ where
         EMI: EventFirer<State = S>,
         OT: ObserversTuple<S>,
     {
-        if state.get_execution_result().reverted {
+        if !state.get_execution_result().is_checked() {
             return Ok(false);
         }
         {
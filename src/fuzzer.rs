@@ -7,8 +7,7 @@ use std::{
     io::Write,
     marker::PhantomData,
     path::Path,
-    process::exit,
-    time::Duration,
+    time::{Duration, Instant},
 };
 
 use itertools::Itertools;
@@ -42,7 +41,11 @@ use serde::{de::DeserializeOwned, Serialize};
 use tracing::info;
 
 use crate::{
-    evm::{host::JMP_MAP, solution, utils::prettify_concise_inputs},
+    evm::{
+        host::{COVERAGE_NOT_CHANGED, JMP_MAP},
+        solution,
+        utils::prettify_concise_inputs,
+    },
     generic_vm::{vm_executor::MAP_SIZE, vm_state::VMStateT},
     input::{ConciseSerde, SolutionTx, VMInputT},
     minimizer::SequentialMinimizer,
@@ -54,6 +57,23 @@ use crate::{
 pub static mut RUN_FOREVER: bool = false;
 pub static mut ORACLE_OUTPUT: Vec<serde_json::Value> = vec![];
 
+/// Budget of the campaign, the fuzz loop returns once one is exhausted
+#[derive(Debug, Clone, Default)]
+pub struct StopConditions {
+    pub time_limit: Option<Duration>,
+    pub max_execs: Option<usize>,
+    /// Executions without coverage change
+    pub plateau_execs: Option<u32>,
+}
+
+pub static mut STOP_CONDITIONS: StopConditions = StopConditions {
+    time_limit: None,
+    max_execs: None,
+    plateau_execs: None,
+};
+/// Why the fuzz loop returned, set once the campaign should stop
+pub static mut STOP_REASON: Option<String> = None;
+
 /// A fuzzer that implements ItyFuzz logic using LibAFL's [`Fuzzer`] trait
 ///
 /// CS: The scheduler for the input corpus
@@ -268,9 +288,36 @@ where
                 .parse::<u64>()
                 .unwrap(),
         );
+        let start = Instant::now();
         loop {
-            self.fuzz_one(stages, executor, state, manager)?;
+            let idx = self.fuzz_one(stages, executor, state, manager)?;
             manager.maybe_report_progress(state, reporting_interval)?;
+
+            let conditions = unsafe { STOP_CONDITIONS.clone() };
+            if conditions.time_limit.map_or(false, |limit| start.elapsed() >= limit) {
+                stop("time limit reached");
+            } else if conditions.max_execs.map_or(false, |max| *state.executions() >= max) {
+                stop("execution limit reached");
+            } else if conditions
+                .plateau_execs
+                .map_or(false, |plateau| unsafe { COVERAGE_NOT_CHANGED } >= plateau)
+            {
+                stop("coverage plateau reached");
+            }
+            if let Some(reason) = unsafe { STOP_REASON.as_ref() } {
+                info!("Stopping campaign: {}", reason);
+                return Ok(idx);
+            }
+        }
+    }
+}
+
+/// Stop the campaign once the current fuzz loop iteration ends, the remaining
+/// inputs of the iteration are not executed
+pub fn stop(reason: &str) {
+    unsafe {
+        if STOP_REASON.is_none() {
+            STOP_REASON = Some(reason.to_string());
         }
     }
 }
@@ -389,6 +436,9 @@ where
         input: <Self::State as UsesInput>::Input,
         send_events: bool,
     ) -> Result<(ExecuteInputResult, Option<CorpusId>), Error> {
+        if unsafe { STOP_REASON.is_some() } {
+            return Ok((ExecuteInputResult::None, None));
+        }
         start_timer!(state);
         executor.observers_mut().pre_exec_all(state, &input)?;
         mark_feature_time!(state, PerfFeature::PreExecObservers);
@@ -450,7 +500,7 @@ where
         }
 
         let mut res = ExecuteInputResult::None;
-        if is_solution && !reverted {
+        if is_solution && state.get_execution_result().is_checked() {
             res = ExecuteInputResult::Solution;
         } else {
             let is_corpus = self
@@ -533,11 +583,11 @@ where
             }
             // find the solution
             ExecuteInputResult::Solution => {
-                state
-                    .metadata_map_mut()
-                    .get_mut::<BugMetadata>()
-                    .unwrap()
-                    .register_corpus_idx(corpus_idx.into());
+                let bug_metadata = state.metadata_map_mut().get_mut::<BugMetadata>().unwrap();
+                bug_metadata.register_corpus_idx(corpus_idx.into());
+                bug_metadata.found.extend(
+                    unsafe { ORACLE_OUTPUT.iter() }.map(|output| (current_time().as_secs(), output.clone())),
+                );
 
                 let minimized = self.sequential_minimizer.minimize(
                     state,
@@ -609,7 +659,7 @@ where
                 }
 
                 if !unsafe { RUN_FOREVER } {
-                    exit(0);
+                    stop("bug found");
                 }
 
                 return Ok((res, None));
@@ -8,6 +8,7 @@ use std::path::Path;
 use std::rc::Rc;
 use std::str::FromStr;
 use std::sync::Arc;
+use std::time::Duration;
 use tracing::info;
 
 use crate::{
@@ -18,7 +19,9 @@ use crate::{
     evm::{
         contract_utils::{copy_local_source_code, modify_concolic_skip},
         middlewares::reentrancy::ReentrancyTracer,
+        middlewares::spot_price::SpotPriceTracer,
         oracles::reentrancy::ReentrancyOracle,
+        oracles::spot_price::SpotPriceOracle,
         types::ProjectSourceMapTy,
         vm::EVMExecutor,
     },
@@ -37,11 +40,13 @@ use libafl::{
 use libafl_bolts::bolts_prelude::ShMemProvider;
 
 use glob::glob;
+use libafl_bolts::current_time;
 use libafl_bolts::tuples::tuple_list;
 
 use crate::evm::host::CALL_UNTIL;
 use crate::evm::host::{
-    ACTIVE_MATCH_EXT_CALL, CMP_MAP, JMP_MAP, PANIC_ON_BUG, READ_MAP, WRITE_MAP, WRITE_RELATIONSHIPS,
+    ACTIVE_MATCH_EXT_CALL, ASSERTION_MODE, CMP_MAP, JMP_MAP, PANIC_ON_BUG, READ_MAP, WRITE_MAP,
+    WRITE_RELATIONSHIPS,
 };
 use crate::evm::minimizer::EVMMinimizer;
 use crate::evm::vm::EVMState;
@@ -57,6 +62,7 @@ use crate::evm::input::{ConciseEVMInput, EVMInput, EVMInputT, EVMInputTy};
 
 use crate::evm::abi::ABIAddressToInstanceMap;
 use crate::evm::blaz::builder::{ArtifactInfoMetadata, BuildJob};
+use crate::evm::checkpoint::{load_checkpoint, CheckpointStage};
 use crate::evm::concolic::concolic_host::{ConcolicHost, CONCOLIC_TIMEOUT};
 use crate::evm::concolic::concolic_stage::{ConcolicFeedbackWrapper, ConcolicStage};
 use crate::evm::cov_stage::CoverageStage;
@@ -65,20 +71,24 @@ use crate::evm::middlewares::call_printer::CallPrinter;
 use crate::evm::middlewares::coverage::{Coverage, EVAL_COVERAGE};
 use crate::evm::middlewares::middleware::Middleware;
 use crate::evm::middlewares::sha3_bypass::{Sha3Bypass, Sha3TaintAnalysis};
+use crate::evm::multicore::SyncStage;
 use crate::evm::mutator::{AccessPattern, FuzzMutator};
 use crate::evm::onchain::flashloan::Flashloan;
 use crate::evm::onchain::onchain::{OnChain, WHITELIST_ADDR};
 use crate::evm::oracles::arb_call::ArbitraryCallOracle;
-use crate::evm::oracles::echidna::EchidnaOracle;
+use crate::evm::oracles::echidna::{is_property, EchidnaOracle};
 use crate::evm::oracles::selfdestruct::SelfdestructOracle;
 use crate::evm::oracles::state_comp::StateCompOracle;
 use crate::evm::oracles::typed_bug::TypedBugOracle;
 use crate::evm::presets::pair::PairPreset;
+use crate::evm::replay::{self, TxReport};
+use crate::evm::seed_corpus::load_seed_corpus;
 use crate::evm::srcmap::parser::{SourceMapLocation, BASE_PATH};
+use crate::evm::summary::CampaignSummary;
 use crate::evm::types::{
     fixed_address, EVMAddress, EVMFuzzMutator, EVMFuzzState, EVMQueueExecutor, EVMU256,
 };
-use crate::fuzzer::{REPLAY, RUN_FOREVER};
+use crate::fuzzer::{ORACLE_OUTPUT, REPLAY, RUN_FOREVER, STOP_CONDITIONS};
 use crate::input::{ConciseSerde, VMInputT};
 use crate::oracle::BugMetadata;
 use primitive_types::{H160, U256};
@@ -132,6 +142,7 @@ pub fn evm_fuzzer(
     let deployer = fixed_address(FIX_DEPLOYER);
     let mut fuzz_host = FuzzHost::new(scheduler.clone(), config.work_dir.clone());
     fuzz_host.set_spec_id(config.spec_id);
+    fuzz_host.chain_profile = config.chain_profile.clone();
 
     let onchain_middleware = match config.onchain.clone() {
         Some(onchain) => {
@@ -147,6 +158,19 @@ pub fn evm_fuzzer(
                 if let Some(builder) = config.builder.clone() {
                     mid.borrow_mut().add_builder(builder);
                 }
+                mid.borrow_mut().set_fetch_lists(
+                    config.onchain_fetch_lists.clone(),
+                    config.onchain_learn_threshold,
+                );
+                // the storage of the targets is always fetched
+                mid.borrow_mut().allowlist.extend(
+                    config
+                        .contract_loader
+                        .contracts
+                        .iter()
+                        .map(|contract| contract.deployed_address)
+                        .chain(config.only_fuzz.iter().cloned()),
+                );
 
                 fuzz_host.add_middlewares(mid.clone());
                 mid
@@ -179,6 +203,8 @@ pub fn evm_fuzzer(
 
     unsafe {
         PANIC_ON_BUG = config.panic_on_bug;
+        ASSERTION_MODE = config.assertion_mode;
+        STOP_CONDITIONS = config.stop_conditions.clone();
     }
 
     if config.only_fuzz.len() > 0 {
@@ -209,9 +235,10 @@ pub fn evm_fuzzer(
                 true,
                 config.onchain.clone().unwrap(),
                 config.price_oracle,
-                onchain_middleware.unwrap(),
+                onchain_middleware.clone().unwrap(),
                 config.flashloan_oracle,
                 config.icy_oracle,
+                config.flashloan_venues,
             ));
         }
     }
@@ -225,6 +252,10 @@ pub fn evm_fuzzer(
         fuzz_host.add_middlewares(Rc::new(RefCell::new(ReentrancyTracer::new())));
     }
 
+    if config.spot_price_oracle.is_some() {
+        fuzz_host.add_middlewares(Rc::new(RefCell::new(SpotPriceTracer::new())));
+    }
+
     let mut evm_executor: EVMQueueExecutor = EVMExecutor::new(fuzz_host, deployer);
 
     if config.replay_file.is_some() {
@@ -245,6 +276,11 @@ pub fn evm_fuzzer(
     #[cfg(feature = "use_presets")]
     corpus_initializer.register_preset(&PairPreset {});
 
+    if let Some(script) = config.deployment_script.clone() {
+        corpus_initializer.set_deployment_script(script);
+    }
+    corpus_initializer.set_invariant_targets(config.echidna_oracle);
+
     let mut artifacts = corpus_initializer.initialize(&mut config.contract_loader.clone());
 
     let mut instance_map = ABIAddressToInstanceMap::new();
@@ -354,7 +390,27 @@ pub fn evm_fuzzer(
         config.work_dir.clone(),
     );
 
-    let mut stages = tuple_list!(std_stage, concolic_stage, coverage_obs_stage);
+    let checkpoint_stage = CheckpointStage::new(
+        evm_executor_ref.clone(),
+        onchain_middleware.clone(),
+        config.work_dir.clone(),
+        Duration::from_secs(config.checkpoint_interval),
+    );
+
+    let sync_stage = SyncStage::new(
+        infant_scheduler.clone(),
+        config.sync_dir.clone(),
+        config.worker_id.unwrap_or(0),
+        Duration::from_secs(config.sync_interval),
+    );
+
+    let mut stages = tuple_list!(
+        std_stage,
+        concolic_stage,
+        coverage_obs_stage,
+        checkpoint_stage,
+        sync_stage
+    );
 
     let mut executor = FuzzExecutor::new(evm_executor_ref.clone(), tuple_list!(jmp_observer));
 
@@ -366,14 +422,14 @@ pub fn evm_fuzzer(
 
     let mut oracles = config.oracle;
 
-    if config.echidna_oracle {
+    if config.echidna_oracle || config.assertion_mode {
         let echidna_oracle = EchidnaOracle::new(
             artifacts
                 .address_to_abi
                 .iter()
                 .map(|(address, abis)| {
                     abis.iter()
-                        .filter(|abi| abi.function_name.starts_with("echidna_") && abi.abi == "()")
+                        .filter(|abi| config.echidna_oracle && is_property(abi))
                         .map(|abi| (address.clone(), abi.function.to_vec()))
                         .collect_vec()
                 })
@@ -384,12 +440,13 @@ pub fn evm_fuzzer(
                 .iter()
                 .map(|(address, abis)| {
                     abis.iter()
-                        .filter(|abi| abi.function_name.starts_with("echidna_") && abi.abi == "()")
+                        .filter(|abi| config.echidna_oracle && is_property(abi))
                         .map(|abi| (abi.function.to_vec(), abi.function_name.clone()))
                         .collect_vec()
                 })
                 .flatten()
                 .collect::<HashMap<Vec<u8>, String>>(),
+            artifacts.address_to_name.clone(),
         );
         oracles.push(Rc::new(RefCell::new(echidna_oracle)));
     }
@@ -421,6 +478,7 @@ pub fn evm_fuzzer(
         oracles.push(Rc::new(RefCell::new(TypedBugOracle::new(
             artifacts.address_to_sourcemap.clone(),
             artifacts.address_to_name.clone(),
+            config.scribble.clone(),
         ))));
     }
 
@@ -440,6 +498,13 @@ pub fn evm_fuzzer(
         ))));
     }
 
+    if let Some(threshold) = config.spot_price_oracle {
+        oracles.push(Rc::new(RefCell::new(SpotPriceOracle::new(
+            threshold,
+            artifacts.address_to_name.clone(),
+        ))));
+    }
+
     let mut producers = config.producers;
 
     let objective: OracleFeedback<
@@ -469,6 +534,8 @@ pub fn evm_fuzzer(
         config.sha3_bypass,
     ));
 
+    let mut seed_scheduler = scheduler.clone();
+    let mut seed_infant_scheduler = infant_scheduler.clone();
     let mut fuzzer: ItyFuzzer<_, _, _, _, _, _, _, _, _, _, _, _, _, _, EVMMinimizer> =
         ItyFuzzer::new(
             scheduler,
@@ -478,13 +545,39 @@ pub fn evm_fuzzer(
             infant_result_feedback,
             objective,
             EVMMinimizer::new(evm_executor_ref.clone()),
-            config.work_dir,
+            config.work_dir.clone(),
+        );
+    if config.resume {
+        load_checkpoint(
+            &config.work_dir,
+            state,
+            &mut evm_executor_ref.borrow_mut(),
+            onchain_middleware.clone(),
         );
+    } else if let (Some(seeds), None) = (&config.seed_corpus, &config.replay_file) {
+        load_seed_corpus(
+            seeds,
+            &artifacts.initial_state,
+            &mut fuzzer,
+            &mut executor,
+            state,
+            &mut mgr,
+            &mut seed_scheduler,
+            &mut seed_infant_scheduler,
+        );
+    }
     match config.replay_file {
         None => {
+            let start = current_time();
             fuzzer
                 .fuzz_loop(&mut stages, &mut executor, state, &mut mgr)
                 .expect("Fuzzing failed");
+            CampaignSummary::dump_after_campaign(
+                state,
+                &mut cov_middleware.borrow_mut(),
+                start,
+                &config.work_dir,
+            );
         }
         Some(files) => {
             unsafe {
@@ -500,9 +593,15 @@ pub fn evm_fuzzer(
                 .host
                 .add_middlewares(printer.clone());
 
+            // replay every transaction of the files, even after a bug is found
+            unsafe {
+                RUN_FOREVER = true;
+            }
             let initial_vm_state = artifacts.initial_state.clone();
             for file in glob(files.as_str()).expect("Failed to read glob pattern") {
-                let mut f = File::open(file.expect("glob issue")).expect("Failed to open file");
+                let file = file.expect("glob issue");
+                let report_dir = replay::report_dir(&config.work_dir, &file);
+                let mut f = File::open(&file).expect("Failed to open file");
                 let mut transactions = String::new();
                 f.read_to_string(&mut transactions)
                     .expect("Failed to read file");
@@ -532,6 +631,16 @@ pub fn evm_fuzzer(
                     fuzzer
                         .evaluate_input_events(state, &mut executor, &mut mgr, inp, false)
                         .unwrap();
+                    TxReport::new(
+                        idx,
+                        txn.to_string(),
+                        &vm_state.state,
+                        state,
+                        &mut evm_executor_ref.borrow_mut(),
+                        &printer.borrow(),
+                        unsafe { ORACLE_OUTPUT.drain(..).collect() },
+                    )
+                    .dump(&report_dir);
 
                     info!("============ Execution result {} =============", idx);
                     info!(
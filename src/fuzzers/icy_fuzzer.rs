@@ -8,10 +8,12 @@ use std::io::Read;
 use std::ops::Deref;
 use std::path::Path;
 use std::rc::Rc;
+use std::time::Duration;
 use tracing::info;
 
 use crate::evm::abi::ABIAddressToInstanceMap;
 use crate::evm::blaz::builder::{ArtifactInfoMetadata, BuildJob};
+use crate::evm::checkpoint::{load_checkpoint, CheckpointStage};
 use crate::evm::concolic::concolic_host::{ConcolicHost, CONCOLIC_TIMEOUT};
 use crate::evm::concolic::concolic_stage::{ConcolicFeedbackWrapper, ConcolicStage};
 use crate::evm::config::Config;
@@ -24,17 +26,20 @@ use crate::evm::middlewares::call_printer::CallPrinter;
 use crate::evm::middlewares::coverage::{Coverage, EVAL_COVERAGE};
 use crate::evm::middlewares::middleware::Middleware;
 use crate::evm::middlewares::sha3_bypass::{Sha3Bypass, Sha3TaintAnalysis};
+use crate::evm::multicore::SyncStage;
 use crate::evm::mutator::{AccessPattern, FuzzMutator};
 use crate::evm::onchain::flashloan::Flashloan;
 use crate::evm::onchain::onchain::{OnChain, WHITELIST_ADDR};
 use crate::evm::oracles::arb_call::ArbitraryCallOracle;
-use crate::evm::oracles::echidna::EchidnaOracle;
+use crate::evm::oracles::echidna::{is_property, EchidnaOracle};
 use crate::evm::oracles::reentrancy::ReentrancyOracle;
 use crate::evm::oracles::selfdestruct::SelfdestructOracle;
+use crate::evm::oracles::spot_price::SpotPriceOracle;
 use crate::evm::oracles::state_comp::StateCompOracle;
 use crate::evm::oracles::typed_bug::TypedBugOracle;
 use crate::evm::presets::pair::PairPreset;
 use crate::evm::srcmap::parser::{SourceMapLocation, BASE_PATH};
+use crate::evm::summary::CampaignSummary;
 use crate::evm::types::{
     fixed_address, EVMAddress, EVMFuzzMutator, EVMFuzzState, EVMQueueExecutor, EVMU256,
 };
@@ -46,10 +51,11 @@ use crate::evm::{
     },
     host::FuzzHost,
     host::{
-        ACTIVE_MATCH_EXT_CALL, CALL_UNTIL, CMP_MAP, JMP_MAP, JMP_OP_MAP, PANIC_ON_BUG, READ_MAP,
-        TRANSFER_DIFF_MAP, WRITE_MAP, WRITE_RELATIONSHIPS,
+        ACTIVE_MATCH_EXT_CALL, ASSERTION_MODE, CALL_UNTIL, CMP_MAP, JMP_MAP, JMP_OP_MAP,
+        PANIC_ON_BUG, READ_MAP, TRANSFER_DIFF_MAP, WRITE_MAP, WRITE_RELATIONSHIPS,
     },
     middlewares::reentrancy::ReentrancyTracer,
+    middlewares::spot_price::SpotPriceTracer,
     minimizer::EVMMinimizer,
     types::ProjectSourceMapTy,
     vm::{EVMExecutor, EVMState},
@@ -58,7 +64,7 @@ use crate::generic_vm::vm_executor::GenericVM;
 
 use crate::executor::FuzzExecutor;
 use crate::feedback::{CmpFeedback, DataflowFeedback, IcyFeedback};
-use crate::fuzzer::{ItyFuzzer, REPLAY, RUN_FOREVER};
+use crate::fuzzer::{ItyFuzzer, REPLAY, RUN_FOREVER, STOP_CONDITIONS};
 use crate::input::{ConciseSerde, VMInputT};
 use crate::oracle::BugMetadata;
 use crate::scheduler::SortedDroppingScheduler;
@@ -77,6 +83,7 @@ use libafl::prelude::{
 use libafl::stages::{CalibrationStage, StdMutationalStage};
 use libafl::{Evaluator, Fuzzer};
 use libafl_bolts::bolts_prelude::ShMemProvider;
+use libafl_bolts::current_time;
 use libafl_bolts::tuples::tuple_list;
 
 struct ABIConfig {
@@ -130,6 +137,7 @@ pub fn icy_fuzzer(
     let deployer = fixed_address(FIX_DEPLOYER);
     let mut fuzz_host = FuzzHost::new(scheduler.clone(), config.work_dir.clone());
     fuzz_host.set_spec_id(config.spec_id);
+    fuzz_host.chain_profile = config.chain_profile.clone();
 
     let onchain_middleware = match config.onchain.clone() {
         Some(onchain) => {
@@ -145,6 +153,19 @@ pub fn icy_fuzzer(
                 if let Some(builder) = config.builder.clone() {
                     mid.borrow_mut().add_builder(builder);
                 }
+                mid.borrow_mut().set_fetch_lists(
+                    config.onchain_fetch_lists.clone(),
+                    config.onchain_learn_threshold,
+                );
+                // the storage of the targets is always fetched
+                mid.borrow_mut().allowlist.extend(
+                    config
+                        .contract_loader
+                        .contracts
+                        .iter()
+                        .map(|contract| contract.deployed_address)
+                        .chain(config.only_fuzz.iter().cloned()),
+                );
 
                 fuzz_host.add_middlewares(mid.clone());
                 mid
@@ -177,6 +198,8 @@ pub fn icy_fuzzer(
 
     unsafe {
         PANIC_ON_BUG = config.panic_on_bug;
+        ASSERTION_MODE = config.assertion_mode;
+        STOP_CONDITIONS = config.stop_conditions.clone();
     }
 
     if config.only_fuzz.len() > 0 {
@@ -207,9 +230,10 @@ pub fn icy_fuzzer(
                 true,
                 config.onchain.clone().unwrap(),
                 config.price_oracle,
-                onchain_middleware.unwrap(),
+                onchain_middleware.clone().unwrap(),
                 config.flashloan_oracle,
                 config.icy_oracle,
+                config.flashloan_venues,
             ));
         }
     }
@@ -224,6 +248,10 @@ pub fn icy_fuzzer(
         fuzz_host.add_middlewares(Rc::new(RefCell::new(ReentrancyTracer::new())));
     }
 
+    if config.spot_price_oracle.is_some() {
+        fuzz_host.add_middlewares(Rc::new(RefCell::new(SpotPriceTracer::new())));
+    }
+
     let mut evm_executor: EVMQueueExecutor = EVMExecutor::new(fuzz_host, deployer);
 
     if config.replay_file.is_some() {
@@ -244,6 +272,11 @@ pub fn icy_fuzzer(
     #[cfg(feature = "use_presets")]
     corpus_initializer.register_preset(&PairPreset {});
 
+    if let Some(script) = config.deployment_script.clone() {
+        corpus_initializer.set_deployment_script(script);
+    }
+    corpus_initializer.set_invariant_targets(config.echidna_oracle);
+
     let mut artifacts = if config.is_icy_oracle {
         corpus_initializer.initialize_with_transaction(&mut config.contract_loader.clone())
     } else {
@@ -383,7 +416,27 @@ pub fn icy_fuzzer(
         config.work_dir.clone(),
     );
 
-    let mut stages = tuple_list!(std_stage, concolic_stage, coverage_obs_stage);
+    let checkpoint_stage = CheckpointStage::new(
+        evm_executor_ref.clone(),
+        onchain_middleware.clone(),
+        config.work_dir.clone(),
+        Duration::from_secs(config.checkpoint_interval),
+    );
+
+    let sync_stage = SyncStage::new(
+        infant_scheduler.clone(),
+        config.sync_dir.clone(),
+        config.worker_id.unwrap_or(0),
+        Duration::from_secs(config.sync_interval),
+    );
+
+    let mut stages = tuple_list!(
+        std_stage,
+        concolic_stage,
+        coverage_obs_stage,
+        checkpoint_stage,
+        sync_stage
+    );
 
     let mut executor = FuzzExecutor::new(
         evm_executor_ref.clone(),
@@ -399,14 +452,14 @@ pub fn icy_fuzzer(
     let mut oracles = config.oracle;
     let mut producers = config.producers;
 
-    if config.echidna_oracle {
+    if config.echidna_oracle || config.assertion_mode {
         let echidna_oracle = EchidnaOracle::new(
             artifacts
                 .address_to_abi
                 .iter()
                 .map(|(address, abis)| {
                     abis.iter()
-                        .filter(|abi| abi.function_name.starts_with("echidna_") && abi.abi == "()")
+                        .filter(|abi| config.echidna_oracle && is_property(abi))
                         .map(|abi| (address.clone(), abi.function.to_vec()))
                         .collect_vec()
                 })
@@ -417,12 +470,13 @@ pub fn icy_fuzzer(
                 .iter()
                 .map(|(address, abis)| {
                     abis.iter()
-                        .filter(|abi| abi.function_name.starts_with("echidna_") && abi.abi == "()")
+                        .filter(|abi| config.echidna_oracle && is_property(abi))
                         .map(|abi| (abi.function.to_vec(), abi.function_name.clone()))
                         .collect_vec()
                 })
                 .flatten()
                 .collect::<HashMap<Vec<u8>, String>>(),
+            artifacts.address_to_name.clone(),
         );
         oracles.push(Rc::new(RefCell::new(echidna_oracle)));
     }
@@ -454,6 +508,7 @@ pub fn icy_fuzzer(
         oracles.push(Rc::new(RefCell::new(TypedBugOracle::new(
             artifacts.address_to_sourcemap.clone(),
             artifacts.address_to_name.clone(),
+            config.scribble.clone(),
         ))));
     }
 
@@ -473,6 +528,13 @@ pub fn icy_fuzzer(
         ))));
     }
 
+    if let Some(threshold) = config.spot_price_oracle {
+        oracles.push(Rc::new(RefCell::new(SpotPriceOracle::new(
+            threshold,
+            artifacts.address_to_name.clone(),
+        ))));
+    }
+
     let objective: IcyFeedback<
         '_,
         EVMState,
@@ -512,13 +574,28 @@ pub fn icy_fuzzer(
             infant_result_feedback,
             objective,
             EVMMinimizer::new(evm_executor_ref.clone()),
-            config.work_dir,
+            config.work_dir.clone(),
+        );
+    if config.resume {
+        load_checkpoint(
+            &config.work_dir,
+            state,
+            &mut evm_executor_ref.borrow_mut(),
+            onchain_middleware.clone(),
         );
+    }
     match config.replay_file {
         None => {
+            let start = current_time();
             fuzzer
                 .fuzz_loop(&mut stages, &mut executor, state, &mut mgr)
                 .expect("Fuzzing failed");
+            CampaignSummary::dump_after_campaign(
+                state,
+                &mut cov_middleware.borrow_mut(),
+                start,
+                &config.work_dir,
+            );
         }
         Some(files) => {
             unsafe {
@@ -41,6 +41,12 @@ where
             additional_info: None,
         }
     }
+
+    /// Whether the oracles check the execution, reverted executions are only
+    /// checked for the bugs they still report
+    pub fn is_checked(&self) -> bool {
+        !self.reverted || self.new_state.state.has_reverted_bugs()
+    }
 }
 
 pub trait GenericVM<VS, Code, By, Loc, Addr, SlotTy, Out, I, S, CI> {
@@ -71,6 +77,19 @@ pub trait GenericVM<VS, Code, By, Loc, Addr, SlotTy, Out, I, S, CI> {
         Loc: Serialize + DeserializeOwned + Debug,
         Out: Default;
 
+    /// Like `fast_static_call`, also returning whether each call succeeded
+    fn fast_static_call_with_status(
+        &mut self,
+        data: &Vec<(Addr, By)>,
+        vm_state: &VS,
+        state: &mut S,
+    ) -> Vec<(Out, bool)>
+    where
+        VS: VMStateT,
+        Addr: Serialize + DeserializeOwned + Debug,
+        Loc: Serialize + DeserializeOwned + Debug,
+        Out: Default;
+
     fn fast_call(
         &mut self,
         data: &Vec<(Addr, Addr, By)>,
@@ -9,6 +9,9 @@ pub trait VMStateT: Clone + Debug + Default + Serialize + DeserializeOwned {
     fn get_post_execution_needed_len(&self) -> usize;
     fn get_post_execution_pc(&self) -> usize;
     fn get_post_execution_len(&self) -> usize;
+    /// Whether a reverted execution leading to this state still has bugs to
+    /// report
+    fn has_reverted_bugs(&self) -> bool;
     #[cfg(feature = "full_trace")]
     fn get_flashloan(&self) -> String;
     fn as_any(&self) -> &dyn std::any::Any;
@@ -26,6 +26,7 @@ pub mod tracer;
 use clap::Parser;
 use clap::Subcommand;
 
+use evm::replay::{replay_diff_main, ReplayDiffArgs};
 use evm::{evm_main, EvmArgs};
 
 #[derive(Parser)]
@@ -38,13 +39,18 @@ struct Cli {
 #[derive(Subcommand, Debug)]
 enum Commands {
     EVM(EvmArgs),
+    ReplayDiff(ReplayDiffArgs),
 }
 
 fn main() {
     let args = Cli::parse();
     match args.command {
         Commands::EVM(args) => {
-            evm_main(args);
+            // the `evm` subcommand and its arguments, `evm` being parsed as the binary name
+            evm_main(args, std::env::args().skip(1).collect());
+        }
+        Commands::ReplayDiff(args) => {
+            replay_diff_main(args);
         }
     }
 }
@@ -90,6 +90,18 @@ where
             .fast_static_call(data, &self.post_state, self.fuzz_state)
     }
 
+    /// Conduct a batch of static calls on the state after the execution,
+    /// returning whether each call succeeded
+    pub(crate) fn call_post_batch_with_status(
+        &mut self,
+        data: &Vec<(Addr, By)>,
+    ) -> Vec<(Out, bool)> {
+        self.executor
+            .deref()
+            .borrow_mut()
+            .fast_static_call_with_status(data, &self.post_state, self.fuzz_state)
+    }
+
     /// Conduct a batch of dynamic calls on the state before the execution
     pub(crate) fn call_pre_batch_dyn(&mut self, data: &Vec<(Addr, Addr, By)>) -> (Vec<Out>, VS) {
         self.executor
@@ -154,6 +166,9 @@ pub struct BugMetadata {
     pub known_bugs: HashSet<u64>,
     pub current_bugs: Vec<u64>,
     pub corpus_idx_to_bug: HashMap<usize, Vec<u64>>,
+    /// Oracle outputs of the solutions found, with the time (seconds since the
+    /// UNIX epoch) they were found at
+    pub found: Vec<(u64, serde_json::Value)>,
 }
 
 impl BugMetadata {